@@ -0,0 +1,330 @@
+//! Detection of brute-force attempts and protocol abuse.
+//!
+//! Counters are kept per client address, so that an attacker spreading
+//! attempts over many connections is still noticed.
+use crate::session::Session;
+use crate::types::command::Command;
+use crate::types::response::Response;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+
+/// Thresholds
+///
+/// usize, failed USER/PASS/APOP/AUTH responses a client may receive
+///
+/// usize, distinct mailbox names a client may try
+///
+/// usize, commands a single session may send
+///
+/// usize, maximum length of a command argument in octets
+///
+/// usize, maximum length of a command line, including the CRLF pair
+#[derive(Debug, Clone)]
+pub struct Thresholds {
+    pub failed_logins: usize,
+    pub distinct_users: usize,
+    pub commands_per_session: usize,
+    pub argument_len: usize,
+    pub line_len: usize,
+}
+
+/// Arguments are limited to 40 octets as in RFC 1939, command lines to
+/// 255 octets as in RFC 2449.
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            failed_logins: 5,
+            distinct_users: 10,
+            commands_per_session: 1000,
+            argument_len: 40,
+            line_len: 255,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AlertKind {
+    /// The client received `count` failed authentication responses
+    FailedLogins { count: usize },
+    /// The client tried `count` distinct mailbox names
+    UserEnumeration { count: usize },
+    /// A single session sent `count` commands
+    CommandFlood { count: usize },
+    /// A command argument of `len` octets, in the exchange at `exchange`
+    OverlongArgument { exchange: usize, len: usize },
+    /// A command line of `len` octets, in the exchange at `exchange`
+    OverlongLine { exchange: usize, len: usize },
+}
+
+/// Alert
+///
+/// Option<IpAddr>, address of the offending client, None if unknown
+///
+/// AlertKind, what the client did
+#[derive(Debug, PartialEq)]
+pub struct Alert {
+    pub client: Option<IpAddr>,
+    pub kind: AlertKind,
+}
+
+#[derive(Debug, Default)]
+struct ClientState {
+    failed_logins: usize,
+    users: HashSet<Vec<u8>>,
+}
+
+/// Raises alerts over the sessions of many clients.
+///
+/// Alerts for counters kept per client (failed logins and user
+/// enumeration) are raised once, when the threshold is exceeded.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::analysis::abuse::{AbuseDetector, AlertKind, Thresholds};
+/// use rfc1939::session::Session;
+///
+/// let mut detector = AbuseDetector::new(Thresholds {
+///     failed_logins: 1,
+///     ..Default::default()
+/// });
+/// let session = Session::parse(
+///     b"USER mrose\r\nPASS a\r\nPASS b\r\n",
+///     b"+OK POP3 server ready\r\n+OK\r\n-ERR invalid password\r\n-ERR invalid password\r\n",
+/// );
+/// let alerts = detector.observe(&session);
+/// assert_eq!(alerts[0].kind, AlertKind::FailedLogins { count: 2 });
+/// ```
+#[derive(Debug, Default)]
+pub struct AbuseDetector {
+    pub thresholds: Thresholds,
+    clients: HashMap<Option<IpAddr>, ClientState>,
+}
+
+impl AbuseDetector {
+    pub fn new(thresholds: Thresholds) -> AbuseDetector {
+        AbuseDetector {
+            thresholds,
+            clients: HashMap::new(),
+        }
+    }
+
+    /// Accounts `session` to its client and returns the alerts it raised.
+    pub fn observe(&mut self, session: &Session) -> Vec<Alert> {
+        let client = session.client.map(|x| x.ip());
+        let thresholds = &self.thresholds;
+        let state = self.clients.entry(client).or_default();
+        let mut kinds = vec![];
+
+        let failed_logins = state.failed_logins;
+        let users = state.users.len();
+        for (i, exchange) in session.exchanges.iter().enumerate() {
+            if let Command::User(x) = &exchange.command {
+                state.users.insert(x.name.to_vec());
+            }
+            if let Some(
+                response @ (Response::User(_)
+                | Response::Pass(_)
+                | Response::Apop(_)
+                | Response::Auth(_)),
+            ) = &exchange.response
+            {
+                if response.is_err() {
+                    state.failed_logins += 1;
+                }
+            }
+            if exchange.raw_command.len() > thresholds.line_len {
+                kinds.push(AlertKind::OverlongLine {
+                    exchange: i,
+                    len: exchange.raw_command.len(),
+                });
+            }
+            if let Some(len) = arguments(exchange.raw_command, &exchange.command)
+                .iter()
+                .map(|x| x.len())
+                .filter(|len| *len > thresholds.argument_len)
+                .max()
+            {
+                kinds.push(AlertKind::OverlongArgument { exchange: i, len });
+            }
+        }
+
+        if session.exchanges.len() > thresholds.commands_per_session {
+            kinds.push(AlertKind::CommandFlood {
+                count: session.exchanges.len(),
+            });
+        }
+        if failed_logins <= thresholds.failed_logins
+            && state.failed_logins > thresholds.failed_logins
+        {
+            kinds.push(AlertKind::FailedLogins {
+                count: state.failed_logins,
+            });
+        }
+        if users <= thresholds.distinct_users && state.users.len() > thresholds.distinct_users {
+            kinds.push(AlertKind::UserEnumeration {
+                count: state.users.len(),
+            });
+        }
+        kinds
+            .into_iter()
+            .map(|kind| Alert { client, kind })
+            .collect()
+    }
+
+    /// Forgets everything known about `client`, e.g. after it was blocked.
+    pub fn forget(&mut self, client: Option<IpAddr>) {
+        self.clients.remove(&client);
+    }
+}
+
+/// The arguments of a command line. PASS has exactly one argument which
+/// may contain spaces. The base64 SASL data of AUTH and of the lines
+/// answering its challenges is not an argument, its length follows from
+/// the mechanism.
+fn arguments<'a>(raw_command: &'a [u8], command: &Command<'a>) -> Vec<&'a [u8]> {
+    match command {
+        Command::Pass(x) => vec![x.string],
+        Command::Auth(x) => vec![x.mechanism],
+        Command::AuthResponse(_) => vec![],
+        _ => {
+            let line = raw_command.strip_suffix(b"\r\n").unwrap_or(raw_command);
+            line.split(|x| *x == b' ').skip(1).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn session<'a>(client: &'a [u8], server: &'a [u8], addr: &str) -> Session<'a> {
+        Session::parse(client, server)
+            .with_endpoints(addr.parse().unwrap(), "192.0.2.2:110".parse().unwrap())
+    }
+
+    #[test]
+    fn test_failed_logins_across_sessions() {
+        let mut detector = AbuseDetector::new(Thresholds {
+            failed_logins: 2,
+            ..Default::default()
+        });
+        let client: &[u8] = b"USER mrose\r\nPASS secret\r\n";
+        let server: &[u8] = b"+OK ready\r\n+OK\r\n-ERR invalid password\r\n";
+        assert_eq!(
+            detector.observe(&session(client, server, "192.0.2.1:1")),
+            vec![]
+        );
+        assert_eq!(
+            detector.observe(&session(client, server, "192.0.2.1:2")),
+            vec![]
+        );
+        assert_eq!(
+            detector.observe(&session(client, server, "192.0.2.9:3")),
+            vec![]
+        );
+        assert_eq!(
+            detector.observe(&session(client, server, "192.0.2.1:4")),
+            vec![Alert {
+                client: Some("192.0.2.1".parse().unwrap()),
+                kind: AlertKind::FailedLogins { count: 3 }
+            }]
+        );
+        // Raised once only
+        assert_eq!(
+            detector.observe(&session(client, server, "192.0.2.1:5")),
+            vec![]
+        );
+        detector.forget(Some("192.0.2.1".parse().unwrap()));
+        assert_eq!(
+            detector.observe(&session(client, server, "192.0.2.1:6")),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_user_enumeration() {
+        let mut detector = AbuseDetector::new(Thresholds {
+            distinct_users: 2,
+            ..Default::default()
+        });
+        let alerts = detector.observe(&Session::parse(
+            b"USER a\r\nUSER b\r\nUSER a\r\nUSER c\r\n",
+            b"+OK ready\r\n-ERR\r\n-ERR\r\n-ERR\r\n-ERR\r\n",
+        ));
+        assert_eq!(
+            alerts,
+            vec![Alert {
+                client: None,
+                kind: AlertKind::UserEnumeration { count: 3 }
+            }]
+        );
+    }
+
+    #[test]
+    fn test_command_flood() {
+        let mut detector = AbuseDetector::new(Thresholds {
+            commands_per_session: 2,
+            ..Default::default()
+        });
+        let addr: SocketAddr = "[2001:db8::1]:110".parse().unwrap();
+        let session = session(b"NOOP\r\nNOOP\r\nNOOP\r\n", b"", &addr.to_string());
+        assert_eq!(
+            detector.observe(&session),
+            vec![Alert {
+                client: Some(addr.ip()),
+                kind: AlertKind::CommandFlood { count: 3 }
+            }]
+        );
+    }
+
+    #[test]
+    fn test_overlong() {
+        let mut detector = AbuseDetector::default();
+        let mut client = b"USER ".to_vec();
+        client.extend_from_slice(&[b'a'; 41]);
+        client.extend_from_slice(b"\r\nPASS ");
+        client.extend_from_slice(&[b'b'; 300]);
+        client.extend_from_slice(b"\r\n");
+        assert_eq!(
+            detector.observe(&Session::parse(&client, b"")),
+            vec![
+                Alert {
+                    client: None,
+                    kind: AlertKind::OverlongArgument {
+                        exchange: 0,
+                        len: 41
+                    }
+                },
+                Alert {
+                    client: None,
+                    kind: AlertKind::OverlongLine {
+                        exchange: 1,
+                        len: 307
+                    }
+                },
+                Alert {
+                    client: None,
+                    kind: AlertKind::OverlongArgument {
+                        exchange: 1,
+                        len: 300
+                    }
+                },
+            ]
+        );
+    }
+    #[test]
+    fn test_sasl_is_not_overlong() {
+        let mut detector = AbuseDetector::default();
+        // \0mrose@example.com\0tanstaaftanstaaf is 48 octets in base64;
+        // the same credentials follow with AUTH LOGIN
+        let session = Session::parse(
+            b"AUTH PLAIN AG1yb3NlQGV4YW1wbGUuY29tAHRhbnN0YWFmdGFuc3RhYWY=\r\n\
+              AUTH LOGIN\r\nbXJvc2VAZXhhbXBsZS5jb20=\r\ndGFuc3RhYWZ0YW5zdGFhZg==\r\n",
+            b"+OK ready\r\n-ERR\r\n+ VXNlcm5hbWU6\r\n+ UGFzc3dvcmQ6\r\n+OK\r\n",
+        );
+        assert_eq!(session.exchanges.len(), 4);
+        assert_eq!(detector.observe(&session), vec![]);
+    }
+}
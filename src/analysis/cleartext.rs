@@ -0,0 +1,353 @@
+//! Detection of credentials sent over an unencrypted connection.
+use crate::sasl::oauth::{OAuthBearer, XOauth2};
+use crate::sasl::plain::Plain;
+use crate::session::Session;
+use crate::types::command::{AuthResponse, Command};
+
+/// The kind of credential which was exposed
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Exposure {
+    /// A plaintext password sent with PASS
+    Pass,
+    /// A digest sent with APOP. The digest is a MD5 hash of the greeting
+    /// timestamp and the shared secret, weak enough to be brute forced
+    /// offline by anyone who saw both.
+    Apop,
+    /// A plaintext password sent with AUTH PLAIN
+    SaslPlain,
+    /// A plaintext password sent with AUTH LOGIN
+    SaslLogin,
+    /// An OAuth 2.0 bearer token sent with AUTH XOAUTH2
+    SaslXOauth2,
+    /// An OAuth 2.0 bearer token sent with AUTH OAUTHBEARER
+    SaslOAuthBearer,
+}
+
+/// How far a plaintext SASL exchange went
+#[derive(Debug, Clone, Copy)]
+enum SaslStep {
+    /// The next client payload is the first of the mechanism: the
+    /// credentials of PLAIN, XOAUTH2 and OAUTHBEARER, the user name of
+    /// LOGIN
+    AwaitingInitial(Exposure),
+    /// LOGIN sent the user name, the next client payload is the password
+    AwaitingPassword,
+}
+
+/// The exposed secret, or only its length when redacted
+#[derive(Debug, PartialEq)]
+pub enum Secret<'a> {
+    Clear(&'a [u8]),
+    Redacted { len: usize },
+}
+
+/// Finding
+///
+/// Exposure, the kind of credential which was exposed
+///
+/// usize, index of the exchange in `Session::exchanges`
+///
+/// Option<&[u8]>, the mailbox name the credential belongs to, if known
+///
+/// Secret, the exposed secret
+///
+/// Option<bool>, whether the server accepted the credential, None if
+/// the server did not answer
+#[derive(Debug, PartialEq)]
+pub struct Finding<'a> {
+    pub exposure: Exposure,
+    pub exchange: usize,
+    pub user: Option<&'a [u8]>,
+    pub secret: Secret<'a>,
+    pub accepted: Option<bool>,
+}
+
+/// Reports credentials which a session sent in cleartext.
+///
+/// The credentials of PASS and APOP are reported, and those of the SASL
+/// mechanisms which send them as they are: PLAIN, LOGIN, XOAUTH2 and
+/// OAUTHBEARER. Challenge-response mechanisms such as CRAM-MD5 and SCRAM
+/// are not.
+///
+/// A session is considered encrypted when the server listens on one of
+/// `implicit_tls_ports` (995, POP3S, by default). Credentials sent after
+/// a successful STLS are never reported, since parsing of the session
+/// stops where TLS starts. When `redact` is set (the default) findings
+/// only record the length of each secret.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::analysis::cleartext::{CleartextAnalyzer, Exposure, Secret};
+/// use rfc1939::session::Session;
+///
+/// let session = Session::parse(
+///     b"USER mrose\r\nPASS tanstaaf\r\n",
+///     b"+OK POP3 server ready\r\n+OK\r\n+OK maildrop ready\r\n",
+/// );
+/// let findings = CleartextAnalyzer::default().analyze(&session);
+/// assert_eq!(findings[0].exposure, Exposure::Pass);
+/// assert_eq!(findings[0].user, Some(&b"mrose"[..]));
+/// assert_eq!(findings[0].secret, Secret::Redacted { len: 8 });
+/// ```
+#[derive(Debug)]
+pub struct CleartextAnalyzer {
+    pub redact: bool,
+    pub implicit_tls_ports: Vec<u16>,
+}
+
+impl Default for CleartextAnalyzer {
+    fn default() -> Self {
+        CleartextAnalyzer {
+            redact: true,
+            implicit_tls_ports: vec![995],
+        }
+    }
+}
+
+impl CleartextAnalyzer {
+    /// Whether the whole session was carried over TLS.
+    pub fn is_encrypted(&self, session: &Session) -> bool {
+        match session.server {
+            Some(server) => self.implicit_tls_ports.contains(&server.port()),
+            None => false,
+        }
+    }
+
+    pub fn analyze<'a>(&self, session: &'a Session) -> Vec<Finding<'a>> {
+        let mut findings = vec![];
+        if self.is_encrypted(session) {
+            return findings;
+        }
+        let mut user = None;
+        let mut sasl = None;
+        for (i, exchange) in session.exchanges.iter().enumerate() {
+            let payload = match &exchange.command {
+                Command::Auth(x) => {
+                    sasl = match x.mechanism.to_ascii_uppercase().as_slice() {
+                        b"PLAIN" => Some(Exposure::SaslPlain),
+                        b"LOGIN" => Some(Exposure::SaslLogin),
+                        b"XOAUTH2" => Some(Exposure::SaslXOauth2),
+                        b"OAUTHBEARER" => Some(Exposure::SaslOAuthBearer),
+                        _ => None,
+                    }
+                    .map(SaslStep::AwaitingInitial);
+                    x.initial_response.as_deref()
+                }
+                Command::AuthResponse(AuthResponse::Data(x)) => Some(x.as_slice()),
+                _ => None,
+            };
+            let (exposure, name, secret) = match (&exchange.command, sasl, payload) {
+                (Command::User(x), _, _) => {
+                    user = Some(x.name);
+                    continue;
+                }
+                (Command::Pass(x), _, _) => (Exposure::Pass, user, x.string),
+                (Command::Apop(x), _, _) => (Exposure::Apop, Some(x.name), x.digest),
+                (_, Some(SaslStep::AwaitingInitial(Exposure::SaslPlain)), Some(payload)) => {
+                    sasl = None;
+                    match Plain::decode(payload) {
+                        Ok(x) => (Exposure::SaslPlain, Some(x.authcid), x.passwd),
+                        Err(_) => (Exposure::SaslPlain, None, payload),
+                    }
+                }
+                (_, Some(SaslStep::AwaitingInitial(Exposure::SaslXOauth2)), Some(payload)) => {
+                    sasl = None;
+                    match XOauth2::decode(payload) {
+                        Ok(x) => (Exposure::SaslXOauth2, Some(x.user), x.token),
+                        Err(_) => (Exposure::SaslXOauth2, None, payload),
+                    }
+                }
+                (_, Some(SaslStep::AwaitingInitial(Exposure::SaslOAuthBearer)), Some(payload)) => {
+                    sasl = None;
+                    match OAuthBearer::decode(payload) {
+                        Ok(x) => {
+                            let user = Some(x.user).filter(|x| !x.is_empty());
+                            (Exposure::SaslOAuthBearer, user, x.token)
+                        }
+                        Err(_) => (Exposure::SaslOAuthBearer, None, payload),
+                    }
+                }
+                (_, Some(SaslStep::AwaitingInitial(Exposure::SaslLogin)), Some(payload)) => {
+                    user = Some(payload);
+                    sasl = Some(SaslStep::AwaitingPassword);
+                    continue;
+                }
+                (_, Some(SaslStep::AwaitingPassword), Some(payload)) => {
+                    sasl = None;
+                    (Exposure::SaslLogin, user, payload)
+                }
+                _ => continue,
+            };
+            findings.push(Finding {
+                exposure,
+                exchange: i,
+                user: name,
+                secret: self.secret(secret),
+                accepted: match &exchange.response {
+                    Some(x) if x.status_indicator().is_some() => Some(x.is_ok()),
+                    _ => None,
+                },
+            });
+        }
+        findings
+    }
+
+    fn secret<'a>(&self, secret: &'a [u8]) -> Secret<'a> {
+        if self.redact {
+            Secret::Redacted { len: secret.len() }
+        } else {
+            Secret::Clear(secret)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: &[u8] =
+        b"USER mrose\r\nPASS tanstaaf\r\nAPOP mrose c4c9334bac560ecc979e58001b3e22fb\r\n";
+    const SERVER: &[u8] = b"+OK POP3 server ready <1896.697170952@dbc.mtview.ca.us>\r\n\
+                            +OK\r\n-ERR invalid password\r\n+OK maildrop has 1 message\r\n";
+
+    #[test]
+    fn test_analyze() {
+        let session = Session::parse(CLIENT, SERVER);
+        let analyzer = CleartextAnalyzer {
+            redact: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            analyzer.analyze(&session),
+            vec![
+                Finding {
+                    exposure: Exposure::Pass,
+                    exchange: 1,
+                    user: Some(b"mrose"),
+                    secret: Secret::Clear(b"tanstaaf"),
+                    accepted: Some(false),
+                },
+                Finding {
+                    exposure: Exposure::Apop,
+                    exchange: 2,
+                    user: Some(b"mrose"),
+                    secret: Secret::Clear(b"c4c9334bac560ecc979e58001b3e22fb"),
+                    accepted: Some(true),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_redacted() {
+        let session = Session::parse(CLIENT, SERVER);
+        let findings = CleartextAnalyzer::default().analyze(&session);
+        assert_eq!(findings[0].secret, Secret::Redacted { len: 8 });
+        assert_eq!(findings[1].secret, Secret::Redacted { len: 32 });
+    }
+
+    #[test]
+    fn test_analyze_implicit_tls() {
+        let session = Session::parse(CLIENT, SERVER).with_endpoints(
+            "192.0.2.1:50000".parse().unwrap(),
+            "192.0.2.2:995".parse().unwrap(),
+        );
+        assert_eq!(CleartextAnalyzer::default().analyze(&session), vec![]);
+    }
+
+    #[test]
+    fn test_analyze_stls() {
+        let session = Session::parse(
+            b"STLS\r\nUSER mrose\r\nPASS tanstaaf\r\n",
+            b"+OK POP3 server ready\r\n+OK Begin TLS negotiation\r\n+OK\r\n+OK\r\n",
+        );
+        assert_eq!(CleartextAnalyzer::default().analyze(&session), vec![]);
+
+        let session = Session::parse(
+            b"STLS\r\nUSER mrose\r\nPASS tanstaaf\r\n",
+            b"+OK POP3 server ready\r\n-ERR TLS unavailable\r\n+OK\r\n+OK\r\n",
+        );
+        assert_eq!(CleartextAnalyzer::default().analyze(&session).len(), 1);
+    }
+
+    #[test]
+    fn test_analyze_sasl() {
+        let session = Session::parse(
+            b"AUTH PLAIN AHRpbQB0YW5zdGFhZnRhbnN0YWFm\r\n\
+              AUTH LOGIN\r\ndGlt\r\ndGFuc3RhYWY=\r\n\
+              AUTH CRAM-MD5\r\ndGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\n",
+            b"+OK POP3 server ready\r\n-ERR [AUTH] invalid\r\n\
+              + VXNlcm5hbWU6\r\n+ UGFzc3dvcmQ6\r\n+OK\r\n\
+              + PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n+OK\r\n",
+        );
+        let analyzer = CleartextAnalyzer {
+            redact: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            analyzer.analyze(&session),
+            vec![
+                Finding {
+                    exposure: Exposure::SaslPlain,
+                    exchange: 0,
+                    user: Some(b"tim"),
+                    secret: Secret::Clear(b"tanstaaftanstaaf"),
+                    accepted: Some(false),
+                },
+                Finding {
+                    exposure: Exposure::SaslLogin,
+                    exchange: 3,
+                    user: Some(b"tim"),
+                    secret: Secret::Clear(b"tanstaaf"),
+                    accepted: Some(true),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_oauth() {
+        // The second XOAUTH2 waits for the empty challenge of the server
+        let session = Session::parse(
+            b"AUTH XOAUTH2 dXNlcj1tcm9zZQFhdXRoPUJlYXJlciB2RjlkZnQ0cW1UAQE=\r\n\
+              AUTH XOAUTH2\r\ndXNlcj1tcm9zZQFhdXRoPUJlYXJlciB2RjlkZnQ0cW1UAQE=\r\n\
+              AUTH OAUTHBEARER bixhPW1yb3NlLAFhdXRoPUJlYXJlciB2RjlkZnQ0cW1UAQE=\r\n",
+            b"+OK POP3 server ready\r\n-ERR [AUTH] invalid\r\n+ \r\n+OK\r\n+OK\r\n",
+        );
+        let analyzer = CleartextAnalyzer {
+            redact: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            analyzer.analyze(&session),
+            vec![
+                Finding {
+                    exposure: Exposure::SaslXOauth2,
+                    exchange: 0,
+                    user: Some(b"mrose"),
+                    secret: Secret::Clear(b"vF9dft4qmT"),
+                    accepted: Some(false),
+                },
+                Finding {
+                    exposure: Exposure::SaslXOauth2,
+                    exchange: 2,
+                    user: Some(b"mrose"),
+                    secret: Secret::Clear(b"vF9dft4qmT"),
+                    accepted: Some(true),
+                },
+                Finding {
+                    exposure: Exposure::SaslOAuthBearer,
+                    exchange: 3,
+                    user: Some(b"mrose"),
+                    secret: Secret::Clear(b"vF9dft4qmT"),
+                    accepted: Some(true),
+                },
+            ]
+        );
+        let findings = CleartextAnalyzer::default().analyze(&session);
+        assert!(findings
+            .iter()
+            .all(|x| x.secret == Secret::Redacted { len: 10 }));
+    }
+}
@@ -0,0 +1,4 @@
+//! Analyzers which inspect parsed POP3 sessions.
+
+pub mod abuse;
+pub mod cleartext;
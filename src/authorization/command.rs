@@ -1,8 +1,9 @@
-use crate::common::{take_until_crlf, take_until_crlf_consume_crlf, take_until_sp};
-use crate::types::command::{Apop, Pass, User};
+use crate::common::{base64_parser, take_until_crlf, take_until_crlf_consume_crlf, take_until_sp};
+use crate::types::command::{Apop, Auth, AuthResponse, Pass, Stls, User, Utf8};
 use nom::{
-    bytes::complete::tag, bytes::complete::tag_no_case, combinator::map, sequence::delimited,
-    sequence::preceded, sequence::separated_pair, IResult,
+    branch::alt, bytes::complete::tag, bytes::complete::tag_no_case,
+    bytes::complete::take_while1, combinator::map, combinator::opt, sequence::delimited,
+    sequence::preceded, sequence::separated_pair, sequence::terminated, sequence::tuple, IResult,
 };
 
 // ################################################################################
@@ -26,17 +27,17 @@ use nom::{
 /// assert_eq!(user(b"USER name\r\n").unwrap(), User { name: b"name" })
 /// ```
 // ################################################################################
-pub fn user(s: &[u8]) -> Option<User> {
+pub fn user(s: &[u8]) -> Option<User<'_>> {
     match user_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn user_parser(s: &[u8]) -> IResult<&[u8], User> {
+pub(crate) fn user_parser(s: &[u8]) -> IResult<&[u8], User<'_>> {
     map(
         preceded(tag_no_case(b"USER "), take_until_crlf_consume_crlf),
-        |name| User { name: name },
+        |name| User { name },
     )(s)
 }
 
@@ -61,17 +62,17 @@ pub(crate) fn user_parser(s: &[u8]) -> IResult<&[u8], User> {
 /// assert_eq!(pass(b"PASS secret\r\n").unwrap(), Pass { string: b"secret" })
 /// ```
 // ################################################################################
-pub fn pass(s: &[u8]) -> Option<Pass> {
+pub fn pass(s: &[u8]) -> Option<Pass<'_>> {
     match pass_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn pass_parser(s: &[u8]) -> IResult<&[u8], Pass> {
+pub(crate) fn pass_parser(s: &[u8]) -> IResult<&[u8], Pass<'_>> {
     map(
         preceded(tag_no_case(b"PASS "), take_until_crlf_consume_crlf),
-        |string| Pass { string: string },
+        |string| Pass { string },
     )(s)
 }
 
@@ -103,14 +104,14 @@ pub(crate) fn pass_parser(s: &[u8]) -> IResult<&[u8], Pass> {
 /// )
 /// ```
 // ################################################################################
-pub fn apop(s: &[u8]) -> Option<Apop> {
+pub fn apop(s: &[u8]) -> Option<Apop<'_>> {
     match apop_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn apop_parser(s: &[u8]) -> IResult<&[u8], Apop> {
+pub(crate) fn apop_parser(s: &[u8]) -> IResult<&[u8], Apop<'_>> {
     map(
         delimited(
             tag_no_case(b"APOP "),
@@ -121,6 +122,164 @@ pub(crate) fn apop_parser(s: &[u8]) -> IResult<&[u8], Apop> {
     )(s)
 }
 
+// ################################################################################
+/// STLS
+///
+/// **Arguments** none
+///
+/// **Restrictions**
+///
+/// Only permitted in AUTHORIZATION state.
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: STLS
+/// use rfc1939::authorization::command::stls;
+/// use rfc1939::types::command::Stls;
+/// assert_eq!(stls(b"STLS\r\n").unwrap(), Stls)
+/// ```
+// ################################################################################
+pub fn stls(s: &[u8]) -> Option<Stls> {
+    match stls_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn stls_parser(s: &[u8]) -> IResult<&[u8], Stls> {
+    map(terminated(tag_no_case(b"STLS"), tag(b"\r\n")), |_| Stls)(s)
+}
+
+// ################################################################################
+/// UTF8
+///
+/// **Arguments** none
+///
+/// **Restrictions**
+///
+/// Only valid in AUTHORIZATION state.
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: UTF8
+/// use rfc1939::authorization::command::utf8;
+/// use rfc1939::types::command::Utf8;
+/// assert_eq!(utf8(b"UTF8\r\n").unwrap(), Utf8)
+/// ```
+// ################################################################################
+pub fn utf8(s: &[u8]) -> Option<Utf8> {
+    match utf8_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn utf8_parser(s: &[u8]) -> IResult<&[u8], Utf8> {
+    map(terminated(tag_no_case(b"UTF8"), tag(b"\r\n")), |_| Utf8)(s)
+}
+
+// ################################################################################
+/// AUTH *mechanism* \[*initial-response*\]
+///
+/// **Arguments**
+///
+/// a string identifying a SASL authentication mechanism
+/// (required)
+///
+/// an optional initial client response, encoded in base64. If
+/// present, the initial response MUST be encoded as specified in
+/// Section 4 of RFC 4648. If the initial response is empty, it
+/// is sent as a single equals sign ("=")
+///
+/// **Restrictions**
+///
+/// may only be given in the AUTHORIZATION state
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: AUTH PLAIN dGVzdAB0ZXN0AHRlc3Q=
+/// use rfc1939::authorization::command::auth;
+/// use rfc1939::types::command::Auth;
+/// assert_eq!(
+///     auth(b"AUTH PLAIN dGVzdAB0ZXN0AHRlc3Q=\r\n").unwrap(),
+///     Auth {
+///         mechanism: b"PLAIN",
+///         initial_response: Some(b"test\0test\0test".to_vec())
+///     }
+/// )
+/// ```
+// ################################################################################
+pub fn auth(s: &[u8]) -> Option<Auth<'_>> {
+    match auth_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn auth_parser(s: &[u8]) -> IResult<&[u8], Auth<'_>> {
+    map(
+        delimited(
+            tag_no_case(b"AUTH "),
+            tuple((
+                take_while1(|x: u8| x.is_ascii_alphanumeric() || x == b'-' || x == b'_'),
+                opt(preceded(
+                    tag(b" "),
+                    alt((map(tag(b"="), |_| vec![]), base64_parser)),
+                )),
+            )),
+            tag(b"\r\n"),
+        ),
+        |(mechanism, initial_response)| Auth {
+            mechanism,
+            initial_response,
+        },
+    )(s)
+}
+
+// ################################################################################
+/// A client response during an AUTH exchange
+///
+/// **Discussion**
+///
+/// The client response consists of a line containing a string
+/// encoded as defined in Section 4 of RFC 4648.  If the client
+/// wishes to cancel the authentication exchange, it issues a line
+/// with a single "*".
+///
+/// Since a client response cannot be told apart from a command,
+/// it may only be parsed after a continuation line from the server.
+///
+/// **Examples**
+///
+/// ```rust
+/// // S: + PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+
+/// // C: dGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw
+/// use rfc1939::authorization::command::auth_response;
+/// use rfc1939::types::command::AuthResponse;
+/// assert_eq!(
+///     auth_response(b"dGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\n").unwrap(),
+///     AuthResponse::Data(b"tim b913a602c7eda7a495b4e6e7334d3890".to_vec())
+/// );
+/// assert_eq!(auth_response(b"*\r\n").unwrap(), AuthResponse::Cancel);
+/// ```
+// ################################################################################
+pub fn auth_response(s: &[u8]) -> Option<AuthResponse> {
+    match auth_response_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn auth_response_parser(s: &[u8]) -> IResult<&[u8], AuthResponse> {
+    alt((
+        map(tag(b"*\r\n"), |_| AuthResponse::Cancel),
+        map(terminated(base64_parser, tag(b"\r\n")), AuthResponse::Data),
+    ))(s)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,4 +304,43 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn test_stls() {
+        assert_eq!(stls(b"STLS\r\n").unwrap(), Stls);
+        assert_eq!(stls(b"STLS now\r\n"), None);
+    }
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(utf8(b"UTF8\r\n").unwrap(), Utf8);
+        assert_eq!(utf8(b"utf8\r\n").unwrap(), Utf8);
+        assert_eq!(utf8(b"UTF8 USER\r\n"), None);
+    }
+
+    #[test]
+    fn test_auth() {
+        assert_eq!(
+            auth(b"AUTH CRAM-MD5\r\n").unwrap(),
+            Auth {
+                mechanism: b"CRAM-MD5",
+                initial_response: None
+            }
+        );
+        assert_eq!(
+            auth(b"auth EXTERNAL =\r\n").unwrap(),
+            Auth {
+                mechanism: b"EXTERNAL",
+                initial_response: Some(vec![])
+            }
+        );
+        assert_eq!(auth(b"AUTH PLAIN not-base64\r\n"), None);
+        assert_eq!(auth(b"AUTH\r\n"), None);
+    }
+
+    #[test]
+    fn test_auth_response() {
+        assert_eq!(auth_response(b"\r\n").unwrap(), AuthResponse::Data(vec![]));
+        assert_eq!(auth_response(b"USER mrose\r\n"), None);
+    }
 }
@@ -1,6 +1,11 @@
 use crate::common::*;
 use crate::types::response::*;
-use nom::IResult;
+use nom::{
+    bytes::complete::tag,
+    combinator::{map, opt},
+    sequence::{delimited, preceded},
+    IResult,
+};
 
 // ################################################################################
 /// Greeting
@@ -8,14 +13,14 @@ use nom::IResult;
 /// Once the TCP connection has been opened by a POP3 client, the POP3
 /// server issues a one line greeting.
 // ################################################################################
-pub fn greeting(s: &[u8]) -> Option<Greeting> {
+pub fn greeting(s: &[u8]) -> Option<Greeting<'_>> {
     match greeting_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn greeting_parser(s: &[u8]) -> IResult<&[u8], Greeting> {
+pub(crate) fn greeting_parser(s: &[u8]) -> IResult<&[u8], Greeting<'_>> {
     one_line_response_two_parts_parser::<Greeting>(s)
 }
 
@@ -24,14 +29,14 @@ pub(crate) fn greeting_parser(s: &[u8]) -> IResult<&[u8], Greeting> {
 /// 
 /// The QUIT command when used in the AUTHORIZATION state
 // ################################################################################
-pub fn quit(s: &[u8]) -> Option<Quit> {
+pub fn quit(s: &[u8]) -> Option<Quit<'_>> {
     match quit_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn quit_parser(s: &[u8]) -> IResult<&[u8], Quit> {
+pub(crate) fn quit_parser(s: &[u8]) -> IResult<&[u8], Quit<'_>> {
     one_line_response_two_parts_parser::<Quit>(s)
 }
 
@@ -70,14 +75,14 @@ pub(crate) fn quit_parser(s: &[u8]) -> IResult<&[u8], Quit> {
 /// 
 /// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK mrose is a real hoopy frood
 // ################################################################################
-pub fn user(s: &[u8]) -> Option<User> {
+pub fn user(s: &[u8]) -> Option<User<'_>> {
     match user_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn user_parser(s: &[u8]) -> IResult<&[u8], User> {
+pub(crate) fn user_parser(s: &[u8]) -> IResult<&[u8], User<'_>> {
     one_line_response_two_parts_parser::<User>(s)
 }
 
@@ -111,14 +116,14 @@ pub(crate) fn user_parser(s: &[u8]) -> IResult<&[u8], User> {
 /// &nbsp;&nbsp;&nbsp;&nbsp;S: -ERR maildrop already locked
 /// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK mrose's maildrop has 2 messages (320 octets)
 // ################################################################################
-pub fn pass(s: &[u8]) -> Option<Pass> {
+pub fn pass(s: &[u8]) -> Option<Pass<'_>> {
     match pass_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn pass_parser(s: &[u8]) -> IResult<&[u8], Pass> {
+pub(crate) fn pass_parser(s: &[u8]) -> IResult<&[u8], Pass<'_>> {
     one_line_response_two_parts_parser::<Pass>(s)
 }
 
@@ -141,17 +146,166 @@ pub(crate) fn pass_parser(s: &[u8]) -> IResult<&[u8], Pass> {
 /// &nbsp;&nbsp;&nbsp;&nbsp;C: APOP mrose c4c9334bac560ecc979e58001b3e22fb
 /// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK maildrop has 1 message (369 octets)
 // ################################################################################
-pub fn apop(s: &[u8]) -> Option<Apop> {
+pub fn apop(s: &[u8]) -> Option<Apop<'_>> {
     match apop_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn apop_parser(s: &[u8]) -> IResult<&[u8], Apop> {
+pub(crate) fn apop_parser(s: &[u8]) -> IResult<&[u8], Apop<'_>> {
     one_line_response_two_parts_parser::<Apop>(s)
 }
 
+// ################################################################################
+/// STLS
+///
+/// **Restrictions**
+///
+/// Only permitted in AUTHORIZATION state.
+///
+/// **Discussion**
+///
+/// A TLS negotiation begins immediately after the CRLF at the
+/// end of the +OK response from the server.  A -ERR response
+/// MAY result if a security layer is already active.  Once a
+/// client issues a STLS command, it MUST NOT issue further
+/// commands until a server response is seen and the TLS
+/// negotiation is complete.
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK -ERR
+///
+/// **Examples**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;C: STLS
+/// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK Begin TLS negotiation
+// ################################################################################
+pub fn stls(s: &[u8]) -> Option<Stls<'_>> {
+    match stls_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn stls_parser(s: &[u8]) -> IResult<&[u8], Stls<'_>> {
+    one_line_response_two_parts_parser::<Stls>(s)
+}
+
+// ################################################################################
+/// UTF8
+///
+/// **Restrictions**
+///
+/// Only valid in AUTHORIZATION state.
+///
+/// **Discussion**
+///
+/// The UTF8 command enables UTF-8 mode.  Once the +OK response
+/// is sent, the server may send UTF-8 in the information text of
+/// responses and in multi-line responses, and, if the UTF8
+/// capability has the USER argument, the USER and PASS commands
+/// accept UTF-8 names and passwords.  UTF-8 mode remains in
+/// effect until the session ends.
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK -ERR
+///
+/// **Examples**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;C: UTF8
+/// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK UTF8 enabled
+// ################################################################################
+pub fn utf8(s: &[u8]) -> Option<Utf8<'_>> {
+    match utf8_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn utf8_parser(s: &[u8]) -> IResult<&[u8], Utf8<'_>> {
+    one_line_response_two_parts_parser::<Utf8>(s)
+}
+
+// ################################################################################
+/// AUTH *mechanism* \[*initial-response*\]
+///
+/// **Restrictions**
+///
+/// may only be given in the AUTHORIZATION state
+///
+/// **Discussion**
+///
+/// If the server has no more challenges to send, it ends the AUTH
+/// exchange with a status line: "+OK" if the authentication
+/// succeeded, "-ERR" if it failed or was cancelled by the client.
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK maildrop locked and ready
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;-ERR authentication exchange failed
+///
+/// **Examples**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK Maildrop locked and ready
+// ################################################################################
+pub fn auth(s: &[u8]) -> Option<Auth<'_>> {
+    match auth_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn auth_parser(s: &[u8]) -> IResult<&[u8], Auth<'_>> {
+    one_line_response_two_parts_parser::<Auth>(s)
+}
+
+// ################################################################################
+/// A server challenge during an AUTH exchange
+///
+/// **Discussion**
+///
+/// A server challenge is sent as a line consisting of a "+"
+/// character followed by a single space and a string encoded
+/// as defined in Section 4 of RFC 4648.
+///
+/// **Examples**
+///
+/// ```rust
+/// // S: + PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+
+/// use rfc1939::authorization::response::auth_challenge;
+/// use rfc1939::types::response::AuthChallenge;
+/// assert_eq!(
+///     auth_challenge(b"+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n").unwrap(),
+///     AuthChallenge {
+///         challenge: b"<1896.697170952@postoffice.reston.mci.net>".to_vec()
+///     }
+/// )
+/// ```
+// ################################################################################
+pub fn auth_challenge(s: &[u8]) -> Option<AuthChallenge> {
+    match auth_challenge_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn auth_challenge_parser(s: &[u8]) -> IResult<&[u8], AuthChallenge> {
+    map(
+        delimited(
+            tag(b"+"),
+            opt(preceded(tag(b" "), base64_parser)),
+            tag(b"\r\n"),
+        ),
+        |challenge| AuthChallenge {
+            challenge: challenge.unwrap_or_default(),
+        },
+    )(s)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -178,6 +332,20 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_greeting_timestamp() {
+        fn timestamp(s: &[u8]) -> Option<&[u8]> {
+            greeting(s).unwrap().timestamp()
+        }
+        assert_eq!(
+            timestamp(b"+OK POP3 server ready <1896.697170952@dbc.mtview.ca.us>\r\n"),
+            Some(&b"<1896.697170952@dbc.mtview.ca.us>"[..])
+        );
+        assert_eq!(timestamp(b"+OK POP3 server ready\r\n"), None);
+        assert_eq!(timestamp(b"+OK <ready>\r\n"), None);
+        assert_eq!(timestamp(b"+OK <a@b\r\n"), None);
+    }
+
     #[test]
     fn test_quit() {
         assert_eq!(
@@ -211,6 +379,22 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_pass_response_code() {
+        let response = pass(b"-ERR [IN-USE] maildrop already locked\r\n").unwrap();
+        assert_eq!(response.response_code(), Some(ResponseCode::InUse));
+        assert_eq!(response.text(), b"maildrop already locked");
+        assert!(response.response_code().unwrap().is_temporary());
+
+        let response = pass(b"-ERR [AUTH] invalid password\r\n").unwrap();
+        assert_eq!(response.response_code(), Some(ResponseCode::Auth));
+        assert!(!response.response_code().unwrap().is_temporary());
+
+        let response = pass(b"-ERR invalid password\r\n").unwrap();
+        assert_eq!(response.response_code(), None);
+        assert_eq!(response.text(), b"invalid password");
+    }
+
     #[test]
     fn test_apop() {
         assert_eq!(
@@ -221,4 +405,52 @@ mod tests {
             }
         )
     }
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(
+            utf8(b"+OK UTF8 enabled\r\n").unwrap(),
+            Utf8 {
+                status_indicator: StatusIndicator::OK,
+                information: b"UTF8 enabled"
+            }
+        );
+        let x = utf8(b"-ERR [UTF8] mailbox name is not UTF-8\r\n").unwrap();
+        assert_eq!(x.response_code(), Some(ResponseCode::Utf8));
+    }
+
+    #[test]
+    fn test_stls() {
+        assert_eq!(
+            stls(b"+OK Begin TLS negotiation\r\n").unwrap(),
+            Stls {
+                status_indicator: StatusIndicator::OK,
+                information: b"Begin TLS negotiation"
+            }
+        )
+    }
+
+    #[test]
+    fn test_auth() {
+        assert_eq!(
+            auth(b"-ERR [AUTH] authentication failed\r\n").unwrap(),
+            Auth {
+                status_indicator: StatusIndicator::ERR,
+                information: b"[AUTH] authentication failed"
+            }
+        );
+    }
+
+    #[test]
+    fn test_auth_challenge() {
+        assert_eq!(
+            auth_challenge(b"+ \r\n").unwrap(),
+            AuthChallenge { challenge: vec![] }
+        );
+        assert_eq!(
+            auth_challenge(b"+\r\n").unwrap(),
+            AuthChallenge { challenge: vec![] }
+        );
+        assert_eq!(auth_challenge(b"+OK\r\n"), None);
+    }
 }
@@ -0,0 +1,325 @@
+//! The asynchronous clients, written once for the I/O traits of each
+//! runtime.
+//!
+//! [`async_client!`] expands to the `Client` of the module it is invoked
+//! in.  The module brings the `AsyncRead`, `AsyncWrite`, `AsyncReadExt`
+//! and `AsyncWriteExt` traits of its runtime in scope, along with a
+//! `within` function which runs a future for at most a duration, or
+//! fails with [`Error::Timeout`](super::Error::Timeout).
+
+macro_rules! async_client {
+    () => {
+        use std::io;
+        use std::time::Duration;
+        use $crate::authorization::response::greeting_parser;
+        use $crate::client::negotiation::{Method, Negotiated, Offer};
+        use $crate::client::{greeting, scram_algorithm, Connection, Error};
+        use $crate::sasl::{self, cram_md5::CramMd5, scram::Scram, Mechanism};
+        use $crate::types::command::{self, AuthResponse, Command, Encode};
+        use $crate::types::response::{
+            Apop, Auth, Capa, Dele, Greeting, Lang, List, Noop, Pass, Quit, Response, Retr, Rset,
+            Stat, Stls, Top, Uidl, User, Utf8,
+        };
+
+        /// An asynchronous POP3 client
+        #[derive(Debug)]
+        pub struct Client<S> {
+            stream: S,
+            connection: Connection,
+            greeting: Vec<u8>,
+            timeout: Option<Duration>,
+        }
+
+        impl<S: AsyncRead + AsyncWrite + Unpin> Client<S> {
+            /// Reads the greeting of the server.
+            pub async fn new(stream: S) -> Result<Client<S>, Error> {
+                Client::with_timeout(stream, None).await
+            }
+
+            /// Reads the greeting of the server, waiting at most `timeout` for
+            /// it and for each response afterwards.
+            pub async fn with_timeout(
+                stream: S,
+                timeout: Option<Duration>,
+            ) -> Result<Client<S>, Error> {
+                let mut client = Client {
+                    stream,
+                    connection: Connection::default(),
+                    greeting: vec![],
+                    timeout,
+                };
+                match timeout {
+                    Some(duration) => within(duration, client.read_greeting()).await??,
+                    None => client.read_greeting().await?,
+                }
+                Ok(client)
+            }
+
+            async fn read_greeting(&mut self) -> Result<(), Error> {
+                while !self.connection.next_response() {
+                    self.read().await?;
+                }
+                self.greeting = greeting(self.connection.response())?;
+                Ok(())
+            }
+
+            /// The greeting of the server
+            pub fn greeting(&self) -> Greeting<'_> {
+                greeting_parser(&self.greeting)
+                    .expect("the greeting is parsed in Client::new")
+                    .1
+            }
+
+            /// Sets the time each operation waits for its response, None to
+            /// wait forever.
+            pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+                self.timeout = timeout;
+            }
+
+            pub fn get_ref(&self) -> &S {
+                &self.stream
+            }
+
+            pub fn into_inner(self) -> S {
+                self.stream
+            }
+
+            async fn read(&mut self) -> Result<(), Error> {
+                let mut buf = [0; 4096];
+                match self.stream.read(&mut buf).await? {
+                    0 => Err(Error::Closed),
+                    n => {
+                        self.connection.received(&buf[..n]);
+                        Ok(())
+                    }
+                }
+            }
+
+            /// Sends `command` and reads the response to it.
+            pub async fn execute(&mut self, command: &Command<'_>) -> Result<Response<'_>, Error> {
+                match self.timeout {
+                    Some(duration) => within(duration, self.exchange(command)).await?,
+                    None => self.exchange(command).await,
+                }
+            }
+
+            async fn exchange(&mut self, command: &Command<'_>) -> Result<Response<'_>, Error> {
+                self.connection.send(command)?;
+                while !self.connection.output().is_empty() {
+                    match self.stream.write(self.connection.output()).await? {
+                        0 => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                        n => self.connection.written(n),
+                    }
+                }
+                self.stream.flush().await?;
+                loop {
+                    while self.connection.next_response() {
+                        if self.connection.is_current() {
+                            return self.connection.parse(command);
+                        }
+                    }
+                    self.read().await?;
+                }
+            }
+
+            pub async fn user(&mut self, name: &[u8]) -> Result<User<'_>, Error> {
+                match self.execute(&Command::User(command::User { name })).await? {
+                    Response::User(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            pub async fn pass(&mut self, string: &[u8]) -> Result<Pass<'_>, Error> {
+                match self
+                    .execute(&Command::Pass(command::Pass { string }))
+                    .await?
+                {
+                    Response::Pass(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// USER and PASS. Nothing is written unless both can be encoded.
+            pub async fn login(&mut self, name: &[u8], password: &[u8]) -> Result<Pass<'_>, Error> {
+                command::Pass { string: password }.to_bytes()?;
+                self.user(name).await?;
+                self.pass(password).await
+            }
+
+            /// APOP, with the digest of the greeting timestamp and `secret`
+            pub async fn apop(&mut self, name: &[u8], secret: &[u8]) -> Result<Apop<'_>, Error> {
+                let timestamp = self.greeting().timestamp().ok_or(Error::NoTimestamp)?;
+                let digest = command::Apop::digest(timestamp, secret);
+                let command = Command::Apop(command::Apop {
+                    name,
+                    digest: &digest,
+                });
+                match self.execute(&command).await? {
+                    Response::Apop(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// AUTH, answering the challenges of the server with `mechanism`.
+            /// The exchange is cancelled if the mechanism fails.
+            pub async fn auth(&mut self, mechanism: &mut dyn Mechanism) -> Result<Auth<'_>, Error> {
+                let mut command = Command::Auth(mechanism.auth()?);
+                loop {
+                    let challenge = match self.execute(&command).await? {
+                        Response::AuthChallenge(x) => x,
+                        Response::Auth(_) => break,
+                        _ => return Err(Error::Malformed),
+                    };
+                    command = match mechanism.auth_response(&challenge) {
+                        Ok(x) => Command::AuthResponse(x),
+                        Err(e) => {
+                            let cancel = Command::AuthResponse(AuthResponse::Cancel);
+                            let _ = self.execute(&cancel).await;
+                            return Err(e.into());
+                        }
+                    };
+                }
+                match self.connection.parse(&command)? {
+                    Response::Auth(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// Logs in with the strongest method both offered by the server
+            /// and in `allowed`, see [`negotiation`]($crate::client::negotiation).
+            pub async fn authenticate(
+                &mut self,
+                name: &[u8],
+                password: &[u8],
+                allowed: &[Method],
+            ) -> Result<Negotiated, Error> {
+                let apop = self.greeting().timestamp().is_some();
+                let offer = match self.capa().await {
+                    Ok(capa) => Offer::new(Some(&capa), apop),
+                    Err(Error::Negative(_)) => Offer::new(None, apop),
+                    Err(e) => return Err(e),
+                };
+                let negotiated = offer.choose(allowed).map_err(Error::NoMethod)?;
+                match negotiated.method {
+                    Method::ScramSha256 | Method::ScramSha1 => {
+                        let algorithm = scram_algorithm(negotiated.method);
+                        let mut scram = Scram::new(algorithm, name, password);
+                        self.auth(&mut scram).await?;
+                        if !scram.is_verified() {
+                            return Err(sasl::Error::InvalidServerSignature.into());
+                        }
+                    }
+                    Method::CramMd5 => {
+                        self.auth(&mut CramMd5::new(name, password)).await?;
+                    }
+                    Method::Apop => {
+                        self.apop(name, password).await?;
+                    }
+                    Method::User => {
+                        self.login(name, password).await?;
+                    }
+                }
+                Ok(negotiated)
+            }
+
+            pub async fn stls(&mut self) -> Result<Stls<'_>, Error> {
+                match self.execute(&Command::Stls(command::Stls)).await? {
+                    Response::Stls(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            pub async fn capa(&mut self) -> Result<Capa<'_>, Error> {
+                match self.execute(&Command::Capa(command::Capa)).await? {
+                    Response::Capa(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            pub async fn utf8(&mut self) -> Result<Utf8<'_>, Error> {
+                match self.execute(&Command::Utf8(command::Utf8)).await? {
+                    Response::Utf8(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// LANG, listing the languages if `tag` is None
+            pub async fn lang(&mut self, tag: Option<&[u8]>) -> Result<Lang<'_>, Error> {
+                match self.execute(&Command::Lang(command::Lang { tag })).await? {
+                    Response::Lang(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            pub async fn stat(&mut self) -> Result<Stat<'_>, Error> {
+                match self.execute(&Command::Stat(command::Stat)).await? {
+                    Response::Stat(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// LIST, for all messages if `msg` is None
+            pub async fn list(&mut self, msg: Option<usize>) -> Result<List<'_>, Error> {
+                match self.execute(&Command::List(command::List { msg })).await? {
+                    Response::List(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// UIDL, for all messages if `msg` is None
+            pub async fn uidl(&mut self, msg: Option<usize>) -> Result<Uidl<'_>, Error> {
+                match self.execute(&Command::Uidl(command::Uidl { msg })).await? {
+                    Response::Uidl(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// RETR, the message is left byte-stuffed
+            pub async fn retr(&mut self, msg: usize) -> Result<Retr<'_>, Error> {
+                match self.execute(&Command::Retr(command::Retr { msg })).await? {
+                    Response::Retr(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// TOP, the lines are left byte-stuffed
+            pub async fn top(&mut self, msg: usize, n: usize) -> Result<Top<'_>, Error> {
+                match self.execute(&Command::Top(command::Top { msg, n })).await? {
+                    Response::Top(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            pub async fn dele(&mut self, msg: usize) -> Result<Dele<'_>, Error> {
+                match self.execute(&Command::Dele(command::Dele { msg })).await? {
+                    Response::Dele(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            pub async fn noop(&mut self) -> Result<Noop<'_>, Error> {
+                match self.execute(&Command::Noop(command::Noop)).await? {
+                    Response::Noop(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            pub async fn rset(&mut self) -> Result<Rset<'_>, Error> {
+                match self.execute(&Command::Rset(command::Rset)).await? {
+                    Response::Rset(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+
+            /// QUIT, entering the UPDATE state if the session was authenticated
+            pub async fn quit(&mut self) -> Result<Quit<'_>, Error> {
+                match self.execute(&Command::Quit(command::Quit)).await? {
+                    Response::Quit(x) => Ok(x),
+                    _ => Err(Error::Malformed),
+                }
+            }
+        }
+    };
+}
+
+pub(crate) use async_client;
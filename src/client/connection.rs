@@ -0,0 +1,151 @@
+//! The state of a client connection, independent of how bytes are read
+//! and written.
+//!
+//! Commands are queued for writing along with the shape of the response
+//! they expect, and the bytes read are framed into responses in the
+//! same order.  As the state lives here rather than in the futures of
+//! an asynchronous client, an operation which is cancelled halfway
+//! leaves the connection usable: the unwritten part of its command is
+//! written, and its response is skipped, before the next command.
+use super::Error;
+use crate::common::StatusIndicator;
+use crate::framing::{frame_length, shape};
+use crate::session::response_parser;
+use crate::types::command::{Command, Encode};
+use crate::types::response::{Response, Shape};
+use std::collections::VecDeque;
+
+#[derive(Debug)]
+pub(crate) struct Connection {
+    input: Vec<u8>,
+    /// The length of the last response framed, at the start of `input`
+    response: usize,
+    output: Vec<u8>,
+    /// The shapes of the responses not framed yet, the greeting first
+    expected: VecDeque<Shape>,
+}
+
+impl Default for Connection {
+    fn default() -> Self {
+        Connection {
+            input: vec![],
+            response: 0,
+            output: vec![],
+            expected: VecDeque::from([Shape::OneLine]),
+        }
+    }
+}
+
+impl Connection {
+    /// A connection whose greeting was read already, e.g. after STLS
+    #[cfg(feature = "rustls")]
+    pub(crate) fn established() -> Connection {
+        Connection {
+            expected: VecDeque::new(),
+            ..Connection::default()
+        }
+    }
+
+    /// Queues `command` for writing, or nothing if it cannot be encoded.
+    pub(crate) fn send(&mut self, command: &Command) -> Result<(), Error> {
+        command.encode(&mut self.output)?;
+        self.expected.push_back(shape(command));
+        Ok(())
+    }
+
+    /// The bytes waiting to be written
+    pub(crate) fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Drops the `n` bytes at the start of the output, once written.
+    pub(crate) fn written(&mut self, n: usize) {
+        self.output.drain(..n);
+    }
+
+    /// Appends bytes read from the server.
+    pub(crate) fn received(&mut self, s: &[u8]) {
+        self.input.extend_from_slice(s);
+    }
+
+    /// Frames the next expected response, dropping the previous one.
+    /// Returns true if a response was framed, and more bytes have to be
+    /// read otherwise.
+    pub(crate) fn next_response(&mut self) -> bool {
+        self.input.drain(..self.response);
+        self.response = 0;
+        let Some(shape) = self.expected.front() else {
+            return false;
+        };
+        match frame_length(*shape, &self.input) {
+            Some(length) => {
+                self.response = length;
+                self.expected.pop_front();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the last response framed answers the last command sent
+    pub(crate) fn is_current(&self) -> bool {
+        self.expected.is_empty()
+    }
+
+    /// Whether bytes were received after the last response framed
+    #[cfg(feature = "rustls")]
+    pub(crate) fn is_buffered(&self) -> bool {
+        self.input.len() > self.response
+    }
+
+    /// The last response framed
+    pub(crate) fn response(&self) -> &[u8] {
+        &self.input[..self.response]
+    }
+
+    /// Parses the last response framed as the response to `command`,
+    /// turning -ERR into an error.
+    pub(crate) fn parse(&self, command: &Command) -> Result<Response<'_>, Error> {
+        let (_, response) =
+            response_parser(command, self.response()).map_err(|_| Error::Malformed)?;
+        if response.status_indicator() == Some(&StatusIndicator::ERR) {
+            return Err(Error::Negative(response.information().to_vec()));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::command::{Noop, Retr};
+
+    #[test]
+    fn test_connection() {
+        let mut connection = Connection::default();
+        connection.received(b"+OK ready\r");
+        assert!(!connection.next_response());
+        connection.received(b"\n+OK\r\nSubj");
+        assert!(connection.next_response());
+        assert_eq!(connection.response(), b"+OK ready\r\n");
+
+        let retr = Command::Retr(Retr { msg: 1 });
+        connection.send(&retr).unwrap();
+        connection.send(&Command::Noop(Noop)).unwrap();
+        assert_eq!(connection.output(), b"RETR 1\r\nNOOP\r\n");
+        connection.written(8);
+        assert_eq!(connection.output(), b"NOOP\r\n");
+
+        assert!(!connection.next_response());
+        connection.received(b"ect: a\r\n.\r\n+OK");
+        assert!(connection.next_response());
+        assert!(!connection.is_current());
+        assert!(matches!(connection.parse(&retr), Ok(Response::Retr(_))));
+        assert!(!connection.next_response());
+        connection.received(b"\r\n");
+        assert!(connection.next_response());
+        assert!(connection.is_current());
+        assert_eq!(connection.response(), b"+OK\r\n");
+        assert!(!connection.next_response());
+    }
+}
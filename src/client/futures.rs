@@ -0,0 +1,169 @@
+//! An asynchronous POP3 client on any runtime, over the
+//! `futures::io::{AsyncRead, AsyncWrite}` traits.
+//!
+//! The client is the same as that of `client::tokio`, for the streams of
+//! smol, async-std and other runtimes.  Timeouts are measured with
+//! `futures-timer`, which does not depend on a runtime either.
+//!
+//! **Examples**
+//!
+//! ```rust
+//! use futures_util::io::{AsyncRead, AsyncWrite};
+//! use rfc1939::client::futures::Client;
+//! use rfc1939::client::Error;
+//! use std::time::Duration;
+//!
+//! async fn fetch<S: AsyncRead + AsyncWrite + Unpin>(stream: S) -> Result<Vec<Vec<u8>>, Error> {
+//!     let mut client = Client::with_timeout(stream, Some(Duration::from_secs(30))).await?;
+//!     client.login(b"mrose", b"tanstaaf").await?;
+//!     let mut messages = vec![];
+//!     for msg in 1..=client.stat().await?.number_of_messages {
+//!         messages.push(client.retr(msg).await?.message.unwrap_or_default().to_vec());
+//!     }
+//!     client.quit().await?;
+//!     Ok(messages)
+//! }
+//! ```
+use futures_timer::Delay;
+use futures_util::future::{select, Either};
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::future::Future;
+use std::pin::pin;
+
+super::asynchronous::async_client!();
+
+/// Runs `future` for at most `duration`.
+async fn within<F: Future>(duration: Duration, future: F) -> Result<F::Output, Error> {
+    match select(pin!(future), Delay::new(duration)).await {
+        Either::Left((output, _)) => Ok(output),
+        Either::Right(_) => Err(Error::Timeout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::Mock;
+    use futures_executor::block_on;
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Poll};
+
+    /// A [`Mock`] shared with the test, which is pending once all of its
+    /// input is read
+    #[derive(Debug, Clone)]
+    struct Pipe(Rc<RefCell<Mock>>);
+
+    impl Pipe {
+        fn new(input: &[u8]) -> Pipe {
+            Pipe(Rc::new(RefCell::new(Mock::new(input))))
+        }
+    }
+
+    impl AsyncRead for Pipe {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut mock = self.0.borrow_mut();
+            if mock.position == mock.input.len() {
+                return Poll::Pending;
+            }
+            Poll::Ready(mock.read(buf))
+        }
+    }
+
+    impl AsyncWrite for Pipe {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(self.0.borrow_mut().write(buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_client() {
+        block_on(async {
+            let pipe = Pipe::new(
+                b"+OK POP3 server ready\r\n+OK\r\n+OK maildrop has 2 messages\r\n\
+                  +OK\r\n1 120\r\n2 200\r\n.\r\n+OK\r\nSubject: a\r\n\r\n..b\r\n.\r\n\
+                  -ERR no such message\r\n+OK bye\r\n",
+            );
+            let mut client = Client::new(pipe.clone()).await.unwrap();
+            assert_eq!(client.greeting().information, b"POP3 server ready");
+            client.login(b"mrose", b"tanstaaf").await.unwrap();
+            assert_eq!(
+                client.list(None).await.unwrap().informations,
+                vec![(1, 120), (2, 200)]
+            );
+            assert_eq!(
+                client.retr(1).await.unwrap().message,
+                Some(&b"Subject: a\r\n\r\n..b"[..])
+            );
+            assert!(matches!(client.dele(3).await, Err(Error::Negative(_))));
+            client.quit().await.unwrap();
+            assert_eq!(
+                pipe.0.borrow().output,
+                b"USER mrose\r\nPASS tanstaaf\r\nLIST\r\nRETR 1\r\nDELE 3\r\nQUIT\r\n"
+            );
+        });
+    }
+
+    #[test]
+    fn test_client_injection() {
+        block_on(async {
+            let pipe = Pipe::new(b"+OK\r\n+OK\r\n");
+            let mut client = Client::new(pipe.clone()).await.unwrap();
+            assert!(matches!(
+                client.user(b"a\r\nDELE 1").await,
+                Err(Error::Command(_))
+            ));
+            assert!(matches!(
+                client.pass(b"x\rDELE 1").await,
+                Err(Error::Command(_))
+            ));
+            assert!(matches!(
+                client.login(b"mrose", b"x\0").await,
+                Err(Error::Command(_))
+            ));
+            assert_eq!(pipe.0.borrow().output, b"");
+            client.noop().await.unwrap();
+            assert_eq!(pipe.0.borrow().output, b"NOOP\r\n");
+        });
+    }
+
+    #[test]
+    fn test_client_timeout() {
+        block_on(async {
+            let pipe = Pipe::new(b"+OK\r\n");
+            let mut client = Client::with_timeout(pipe.clone(), Some(Duration::from_millis(50)))
+                .await
+                .unwrap();
+            assert!(matches!(client.stat().await, Err(Error::Timeout)));
+
+            // The response to STAT is skipped
+            pipe.0
+                .borrow_mut()
+                .input
+                .extend_from_slice(b"+OK 2 320\r\n+OK\r\n1 120\r\n.\r\n");
+            assert_eq!(
+                client.list(None).await.unwrap().informations,
+                vec![(1, 120)]
+            );
+            assert_eq!(pipe.0.borrow().output, b"STAT\r\nLIST\r\n");
+        });
+    }
+}
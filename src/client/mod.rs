@@ -0,0 +1,603 @@
+//! A blocking POP3 client over any stream.
+//!
+//! Each operation writes one command and reads until the response to it
+//! is complete, then parses it with the parsers of this crate.  The
+//! responses borrow from the buffer of the client, and are valid until
+//! the next operation.
+//!
+//! **Examples**
+//!
+//! ```rust,no_run
+//! use rfc1939::client::Client;
+//! use std::net::TcpStream;
+//!
+//! let mut client = Client::new(TcpStream::connect("pop.example.com:110")?)?;
+//! client.login(b"mrose", b"tanstaaf")?;
+//! let stat = client.stat()?;
+//! for msg in 1..=stat.number_of_messages {
+//!     let message = client.retr(msg)?.message.unwrap_or_default().to_vec();
+//!     // ...
+//!     client.dele(msg)?;
+//! }
+//! client.quit()?;
+//! # Ok::<(), rfc1939::client::Error>(())
+//! ```
+use crate::authorization::response::greeting_parser;
+use crate::common::StatusIndicator;
+use crate::sasl::{self, cram_md5::CramMd5, scram, Mechanism};
+use crate::types::command::{self, AuthResponse, Command, Encode};
+use crate::types::response::{
+    Apop, Auth, Capa, Dele, Greeting, List, Noop, Pass, Quit, Response, Retr, Rset, Stat, Top, Uidl,
+};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+mod asynchronous;
+mod connection;
+#[cfg(feature = "futures-io")]
+pub mod futures;
+pub mod negotiation;
+#[cfg(feature = "rustls")]
+pub mod tls;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+use connection::Connection;
+use negotiation::{Fallback, Method, Negotiated, Offer};
+
+/// The SASL mechanisms which send the password, or a bearer token, as
+/// it is
+const CLEARTEXT_MECHANISMS: [&[u8]; 4] = [b"PLAIN", b"LOGIN", b"XOAUTH2", b"OAUTHBEARER"];
+
+/// Errors of a client
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The server closed the connection before the response was complete
+    Closed,
+    /// The response does not follow the syntax of the command
+    Malformed,
+    /// The server answered -ERR, with the given information
+    Negative(Vec<u8>),
+    /// The greeting carries no timestamp, APOP is not supported
+    NoTimestamp,
+    /// The SASL mechanism failed, the AUTH exchange was cancelled
+    Sasl(sasl::Error),
+    /// The response did not arrive in time
+    Timeout,
+    /// TLS is required, the credentials are not sent in cleartext
+    Cleartext,
+    /// The server does not announce STLS
+    NoStls,
+    /// No method of logging in is both offered and allowed, with why
+    /// each was skipped
+    NoMethod(Vec<Fallback>),
+    /// An argument would not stay within its command line, nothing was
+    /// written
+    Command(command::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(x) => write!(f, "I/O error: {}", x),
+            Error::Closed => write!(f, "connection closed by the server"),
+            Error::Malformed => write!(f, "malformed response"),
+            Error::Negative(x) => write!(f, "-ERR {}", String::from_utf8_lossy(x)),
+            Error::NoTimestamp => write!(f, "no APOP timestamp in the greeting"),
+            Error::Sasl(x) => write!(f, "SASL: {}", x),
+            Error::Timeout => write!(f, "timed out waiting for the response"),
+            Error::Cleartext => write!(f, "refusing to send the credentials in cleartext"),
+            Error::NoStls => write!(f, "STLS is not announced by the server"),
+            Error::NoMethod(_) => write!(f, "no method of logging in offered and allowed"),
+            Error::Command(x) => write!(f, "command: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(x) => Some(x),
+            Error::Sasl(x) => Some(x),
+            Error::Command(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<sasl::Error> for Error {
+    fn from(error: sasl::Error) -> Self {
+        Error::Sasl(error)
+    }
+}
+
+impl From<command::Error> for Error {
+    fn from(error: command::Error) -> Self {
+        Error::Command(error)
+    }
+}
+
+/// A blocking POP3 client
+#[derive(Debug)]
+pub struct Client<S> {
+    stream: S,
+    connection: Connection,
+    greeting: Vec<u8>,
+    /// Whether the stream is TLS, set by the constructors of `tls`
+    secure: bool,
+    require_tls: bool,
+}
+
+impl<S: Read + Write> Client<S> {
+    /// Reads the greeting of the server.
+    pub fn new(stream: S) -> Result<Client<S>, Error> {
+        let mut client = Client {
+            stream,
+            connection: Connection::default(),
+            greeting: vec![],
+            secure: false,
+            require_tls: false,
+        };
+        while !client.connection.next_response() {
+            client.read()?;
+        }
+        client.greeting = greeting(client.connection.response())?;
+        Ok(client)
+    }
+
+    /// The greeting of the server
+    pub fn greeting(&self) -> Greeting<'_> {
+        greeting_parser(&self.greeting)
+            .expect("the greeting is parsed in Client::new")
+            .1
+    }
+
+    /// Whether the stream is TLS, with implicit TLS or after STLS
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Refuses to send USER and PASS, or AUTH with PLAIN, LOGIN or
+    /// XOAUTH2, unless the stream is TLS.
+    pub fn set_require_tls(&mut self, require_tls: bool) {
+        self.require_tls = require_tls;
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    fn read(&mut self) -> Result<(), Error> {
+        let mut buf = [0; 4096];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => return Err(Error::Closed),
+                Ok(n) => {
+                    self.connection.received(&buf[..n]);
+                    return Ok(());
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Sends `command` and reads the response to it.
+    pub fn execute(&mut self, command: &Command) -> Result<Response<'_>, Error> {
+        if self.require_tls && !self.secure && is_cleartext(command) {
+            return Err(Error::Cleartext);
+        }
+        self.connection.send(command)?;
+        self.stream.write_all(self.connection.output())?;
+        self.connection.written(self.connection.output().len());
+        self.stream.flush()?;
+        loop {
+            while self.connection.next_response() {
+                if self.connection.is_current() {
+                    return self.connection.parse(command);
+                }
+            }
+            self.read()?;
+        }
+    }
+
+    /// USER and PASS. Nothing is written unless both can be encoded.
+    pub fn login(&mut self, name: &[u8], password: &[u8]) -> Result<Pass<'_>, Error> {
+        let pass = Command::Pass(command::Pass { string: password });
+        pass.to_bytes()?;
+        self.execute(&Command::User(command::User { name }))?;
+        match self.execute(&pass)? {
+            Response::Pass(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// APOP, with the digest of the greeting timestamp and `secret`
+    pub fn apop(&mut self, name: &[u8], secret: &[u8]) -> Result<Apop<'_>, Error> {
+        let timestamp = self.greeting().timestamp().ok_or(Error::NoTimestamp)?;
+        let digest = command::Apop::digest(timestamp, secret);
+        match self.execute(&Command::Apop(command::Apop {
+            name,
+            digest: &digest,
+        }))? {
+            Response::Apop(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// AUTH, answering the challenges of the server with `mechanism`.
+    /// The exchange is cancelled if the mechanism fails.
+    pub fn auth(&mut self, mechanism: &mut dyn Mechanism) -> Result<Auth<'_>, Error> {
+        let mut command = Command::Auth(mechanism.auth()?);
+        loop {
+            let challenge = match self.execute(&command)? {
+                Response::AuthChallenge(x) => x,
+                Response::Auth(_) => break,
+                _ => return Err(Error::Malformed),
+            };
+            command = match mechanism.auth_response(&challenge) {
+                Ok(x) => Command::AuthResponse(x),
+                Err(e) => {
+                    let _ = self.execute(&Command::AuthResponse(AuthResponse::Cancel));
+                    return Err(e.into());
+                }
+            };
+        }
+        match self.connection.parse(&command)? {
+            Response::Auth(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// Logs in with the strongest method both offered by the server and
+    /// in `allowed`, see [`negotiation`].
+    pub fn authenticate(
+        &mut self,
+        name: &[u8],
+        password: &[u8],
+        allowed: &[Method],
+    ) -> Result<Negotiated, Error> {
+        let apop = self.greeting().timestamp().is_some();
+        let offer = match self.capa() {
+            Ok(capa) => Offer::new(Some(&capa), apop),
+            Err(Error::Negative(_)) => Offer::new(None, apop),
+            Err(e) => return Err(e),
+        };
+        let negotiated = offer.choose(allowed).map_err(Error::NoMethod)?;
+        match negotiated.method {
+            Method::ScramSha256 | Method::ScramSha1 => {
+                let mut scram =
+                    scram::Scram::new(scram_algorithm(negotiated.method), name, password);
+                self.auth(&mut scram)?;
+                if !scram.is_verified() {
+                    return Err(sasl::Error::InvalidServerSignature.into());
+                }
+            }
+            Method::CramMd5 => {
+                self.auth(&mut CramMd5::new(name, password))?;
+            }
+            Method::Apop => {
+                self.apop(name, password)?;
+            }
+            Method::User => {
+                self.login(name, password)?;
+            }
+        }
+        Ok(negotiated)
+    }
+
+    pub fn capa(&mut self) -> Result<Capa<'_>, Error> {
+        match self.execute(&Command::Capa(command::Capa))? {
+            Response::Capa(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    pub fn stat(&mut self) -> Result<Stat<'_>, Error> {
+        match self.execute(&Command::Stat(command::Stat))? {
+            Response::Stat(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// LIST, for all messages if `msg` is None
+    pub fn list(&mut self, msg: Option<usize>) -> Result<List<'_>, Error> {
+        match self.execute(&Command::List(command::List { msg }))? {
+            Response::List(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// UIDL, for all messages if `msg` is None
+    pub fn uidl(&mut self, msg: Option<usize>) -> Result<Uidl<'_>, Error> {
+        match self.execute(&Command::Uidl(command::Uidl { msg }))? {
+            Response::Uidl(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// RETR, the message is left byte-stuffed
+    pub fn retr(&mut self, msg: usize) -> Result<Retr<'_>, Error> {
+        match self.execute(&Command::Retr(command::Retr { msg }))? {
+            Response::Retr(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// TOP, the lines are left byte-stuffed
+    pub fn top(&mut self, msg: usize, n: usize) -> Result<Top<'_>, Error> {
+        match self.execute(&Command::Top(command::Top { msg, n }))? {
+            Response::Top(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    pub fn dele(&mut self, msg: usize) -> Result<Dele<'_>, Error> {
+        match self.execute(&Command::Dele(command::Dele { msg }))? {
+            Response::Dele(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    pub fn noop(&mut self) -> Result<Noop<'_>, Error> {
+        match self.execute(&Command::Noop(command::Noop))? {
+            Response::Noop(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    pub fn rset(&mut self) -> Result<Rset<'_>, Error> {
+        match self.execute(&Command::Rset(command::Rset))? {
+            Response::Rset(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// QUIT, entering the UPDATE state if the session was authenticated
+    pub fn quit(&mut self) -> Result<Quit<'_>, Error> {
+        match self.execute(&Command::Quit(command::Quit))? {
+            Response::Quit(x) => Ok(x),
+            _ => Err(Error::Malformed),
+        }
+    }
+}
+
+/// Whether `command` sends credentials as they are
+fn is_cleartext(command: &Command) -> bool {
+    match command {
+        Command::User(_) | Command::Pass(_) => true,
+        Command::Auth(x) => CLEARTEXT_MECHANISMS
+            .iter()
+            .any(|y| x.mechanism.eq_ignore_ascii_case(y)),
+        _ => false,
+    }
+}
+
+/// The SCRAM algorithm of a method
+fn scram_algorithm(method: Method) -> scram::Algorithm {
+    match method {
+        Method::ScramSha1 => scram::Algorithm::Sha1,
+        _ => scram::Algorithm::Sha256,
+    }
+}
+
+/// Checks the greeting of a server, returning a copy of it.
+fn greeting(s: &[u8]) -> Result<Vec<u8>, Error> {
+    let (_, greeting) = greeting_parser(s).map_err(|_| Error::Malformed)?;
+    if greeting.status_indicator != StatusIndicator::OK {
+        return Err(Error::Negative(greeting.information.to_vec()));
+    }
+    Ok(s.to_vec())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A stream which plays the responses of a server, a few bytes per
+    /// read, and records the commands written to it
+    #[derive(Debug, Default)]
+    pub(crate) struct Mock {
+        pub(crate) input: Vec<u8>,
+        pub(crate) position: usize,
+        pub(crate) output: Vec<u8>,
+    }
+
+    impl Mock {
+        pub(crate) fn new(input: &[u8]) -> Mock {
+            Mock {
+                input: input.to_vec(),
+                ..Mock::default()
+            }
+        }
+    }
+
+    impl Read for Mock {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(7).min(self.input.len() - self.position);
+            buf[..n].copy_from_slice(&self.input[self.position..self.position + n]);
+            self.position += n;
+            Ok(n)
+        }
+    }
+
+    impl Write for Mock {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_client() {
+        let mut client = Client::new(Mock::new(
+            b"+OK POP3 server ready\r\n+OK\r\n+OK maildrop has 2 messages\r\n\
+              +OK 2 320\r\n+OK 2 messages\r\n1 120\r\n2 200\r\n.\r\n\
+              +OK\r\n1 whqtswO00WBw418f9t5JxYwZ\r\n2 QhdPYR:00WBw1Ph7x7\r\n.\r\n\
+              +OK 120 octets\r\nSubject: a\r\n\r\n..b\r\n.\r\n+OK\r\nSubject: a\r\n.\r\n\
+              +OK message 1 deleted\r\n+OK\r\n+OK\r\n+OK 1 120\r\n+OK bye\r\n",
+        ))
+        .unwrap();
+        assert_eq!(client.greeting().information, b"POP3 server ready");
+        assert_eq!(
+            client.login(b"mrose", b"tanstaaf").unwrap().information,
+            b"maildrop has 2 messages"
+        );
+        let stat = client.stat().unwrap();
+        assert_eq!((stat.number_of_messages, stat.size_in_octets), (2, 320));
+        assert_eq!(
+            client.list(None).unwrap().informations,
+            vec![(1, 120), (2, 200)]
+        );
+        assert_eq!(
+            client.uidl(None).unwrap().informations,
+            vec![
+                (1, &b"whqtswO00WBw418f9t5JxYwZ"[..]),
+                (2, &b"QhdPYR:00WBw1Ph7x7"[..])
+            ]
+        );
+        assert_eq!(
+            client.retr(1).unwrap(),
+            Retr {
+                status_indicator: StatusIndicator::OK,
+                message: Some(b"Subject: a\r\n\r\n..b"),
+                information: b"120 octets"
+            }
+        );
+        assert_eq!(client.top(1, 0).unwrap().message, Some(&b"Subject: a"[..]));
+        assert!(client.dele(1).is_ok());
+        assert!(client.rset().is_ok());
+        assert!(client.noop().is_ok());
+        assert_eq!(client.list(Some(1)).unwrap().informations, vec![(1, 120)]);
+        assert_eq!(client.quit().unwrap().information, b"bye");
+        assert_eq!(
+            client.into_inner().output,
+            b"USER mrose\r\nPASS tanstaaf\r\nSTAT\r\nLIST\r\nUIDL\r\nRETR 1\r\nTOP 1 0\r\n\
+              DELE 1\r\nRSET\r\nNOOP\r\nLIST 1\r\nQUIT\r\n"
+        );
+    }
+
+    #[test]
+    fn test_client_apop() {
+        let mut client = Client::new(Mock::new(
+            b"+OK POP3 server ready <1896.697170952@dbc.mtview.ca.us>\r\n+OK\r\n",
+        ))
+        .unwrap();
+        assert!(client.apop(b"mrose", b"tanstaaf").is_ok());
+        assert_eq!(
+            client.get_ref().output,
+            b"APOP mrose c4c9334bac560ecc979e58001b3e22fb\r\n"
+        );
+
+        let mut client = Client::new(Mock::new(b"+OK POP3 server ready\r\n")).unwrap();
+        assert!(matches!(
+            client.apop(b"mrose", b"tanstaaf"),
+            Err(Error::NoTimestamp)
+        ));
+    }
+
+    #[test]
+    fn test_client_injection() {
+        let mut client = Client::new(Mock::new(
+            b"+OK POP3 server ready <1896.697170952@dbc.mtview.ca.us>\r\n+OK\r\n",
+        ))
+        .unwrap();
+        assert!(matches!(
+            client.login(b"a\r\nDELE 1", b"x"),
+            Err(Error::Command(command::Error::ControlCharacter))
+        ));
+        assert!(matches!(
+            client.login(b"mrose", b"x\nDELE 1"),
+            Err(Error::Command(command::Error::ControlCharacter))
+        ));
+        assert!(matches!(
+            client.apop(b"mrose DELE", b"tanstaaf"),
+            Err(Error::Command(command::Error::Space))
+        ));
+        assert!(matches!(
+            client.execute(&Command::Unknown(command::Unknown {
+                keyword: b"XSENDER",
+                args: vec![b"1\0"]
+            })),
+            Err(Error::Command(command::Error::ControlCharacter))
+        ));
+        assert_eq!(client.get_ref().output, b"");
+        assert!(client.noop().is_ok());
+        assert_eq!(client.get_ref().output, b"NOOP\r\n");
+    }
+
+    #[test]
+    fn test_client_authenticate() {
+        use negotiation::Reason;
+
+        let mut client = Client::new(Mock::new(
+            b"+OK POP3 server ready <1896.697170952@dbc.mtview.ca.us>\r\n\
+              +OK\r\nUSER\r\nSASL PLAIN\r\n.\r\n+OK\r\n",
+        ))
+        .unwrap();
+        let negotiated = client
+            .authenticate(b"mrose", b"tanstaaf", &Method::ALL)
+            .unwrap();
+        assert_eq!(negotiated.method, Method::Apop);
+        assert_eq!(negotiated.fallbacks.len(), 3);
+        assert_eq!(
+            client.get_ref().output,
+            b"CAPA\r\nAPOP mrose c4c9334bac560ecc979e58001b3e22fb\r\n"
+        );
+
+        // USER is assumed without CAPA
+        let mut client = Client::new(Mock::new(
+            b"+OK POP3 server ready\r\n-ERR\r\n+OK\r\n+OK\r\n-ERR\r\n",
+        ))
+        .unwrap();
+        let allowed = [Method::CramMd5, Method::User];
+        let negotiated = client
+            .authenticate(b"mrose", b"tanstaaf", &allowed)
+            .unwrap();
+        assert_eq!(negotiated.method, Method::User);
+        assert_eq!(negotiated.fallbacks[2].reason, Reason::NotOffered);
+        assert!(matches!(
+            client.authenticate(b"mrose", b"tanstaaf", &[Method::ScramSha256]),
+            Err(Error::NoMethod(x)) if x.len() == 5
+        ));
+    }
+
+    #[test]
+    fn test_client_errors() {
+        assert!(matches!(
+            Client::new(Mock::new(b"-ERR busy\r\n")),
+            Err(Error::Negative(x)) if x == b"busy"
+        ));
+        assert!(matches!(
+            Client::new(Mock::new(b"* OK IMAP4rev1\r\n")),
+            Err(Error::Malformed)
+        ));
+        assert!(matches!(Client::new(Mock::new(b"+OK")), Err(Error::Closed)));
+
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n-ERR no such message\r\n+OK\r\nSubject: a\r\n",
+        ))
+        .unwrap();
+        assert_eq!(
+            client.retr(3).unwrap_err().to_string(),
+            "-ERR no such message"
+        );
+        assert!(matches!(client.retr(1), Err(Error::Closed)));
+    }
+}
@@ -0,0 +1,213 @@
+//! Choosing how to log in, from what a server offers.
+//!
+//! The strongest method is chosen, in order: SCRAM-SHA-256, SCRAM-SHA-1,
+//! CRAM-MD5, APOP, then USER and PASS.  SASL mechanisms are offered by
+//! the SASL capability of CAPA, APOP by a timestamp in the greeting, and
+//! USER by its capability, or assumed if the server does not support
+//! CAPA.  Every method stronger than the one chosen is reported with the
+//! reason it was skipped, to audit weak logins.
+//!
+//! A method which fails is not followed by a weaker one: the server
+//! rejected the credentials, or an attacker is forcing a downgrade.
+//!
+//! **Examples**
+//!
+//! ```rust
+//! use rfc1939::client::negotiation::{Fallback, Method, Offer, Reason};
+//! let offer = Offer {
+//!     mechanisms: vec![b"PLAIN".to_vec(), b"CRAM-MD5".to_vec()],
+//!     apop: true,
+//!     user: true,
+//! };
+//! let negotiated = offer.choose(&Method::ALL).unwrap();
+//! assert_eq!(negotiated.method, Method::CramMd5);
+//! assert_eq!(
+//!     negotiated.fallbacks[0],
+//!     Fallback { method: Method::ScramSha256, reason: Reason::NotOffered }
+//! );
+//! // Without CRAM-MD5 in the allow-list
+//! assert_eq!(offer.choose(&[Method::Apop, Method::User]).unwrap().method, Method::Apop);
+//! ```
+use crate::types::response::{Capa, Capability};
+
+/// A method of logging in
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Method {
+    ScramSha256,
+    ScramSha1,
+    CramMd5,
+    Apop,
+    /// USER and PASS, the password is sent as it is
+    User,
+}
+
+impl Method {
+    /// All methods, strongest first
+    pub const ALL: [Method; 5] = [
+        Method::ScramSha256,
+        Method::ScramSha1,
+        Method::CramMd5,
+        Method::Apop,
+        Method::User,
+    ];
+
+    /// The name of the SASL mechanism or of the command
+    pub fn name(&self) -> &'static [u8] {
+        match self {
+            Method::ScramSha256 => b"SCRAM-SHA-256",
+            Method::ScramSha1 => b"SCRAM-SHA-1",
+            Method::CramMd5 => b"CRAM-MD5",
+            Method::Apop => b"APOP",
+            Method::User => b"USER",
+        }
+    }
+}
+
+/// Why a method was skipped
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Reason {
+    /// The method is not in the allow-list
+    NotAllowed,
+    /// The server does not offer the method
+    NotOffered,
+}
+
+/// A method stronger than the one chosen, and why it was skipped
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Fallback {
+    pub method: Method,
+    pub reason: Reason,
+}
+
+/// The method chosen, and the stronger methods skipped
+#[derive(Debug, PartialEq, Clone)]
+pub struct Negotiated {
+    pub method: Method,
+    pub fallbacks: Vec<Fallback>,
+}
+
+/// What a server offers
+///
+/// Vec<Vec<u8>>, the SASL mechanisms listed by CAPA
+///
+/// bool, whether the greeting carries an APOP timestamp
+///
+/// bool, whether USER is supported
+#[derive(Debug, PartialEq, Clone)]
+pub struct Offer {
+    pub mechanisms: Vec<Vec<u8>>,
+    pub apop: bool,
+    pub user: bool,
+}
+
+impl Offer {
+    /// The offer of a server from its response to CAPA, None if it does
+    /// not support CAPA, and whether its greeting carries a timestamp.
+    pub fn new(capa: Option<&Capa>, apop: bool) -> Offer {
+        let Some(capa) = capa else {
+            return Offer {
+                mechanisms: vec![],
+                apop,
+                user: true,
+            };
+        };
+        let mut mechanisms = vec![];
+        for capability in &capa.capabilities {
+            if let Capability::Sasl(x) = capability {
+                mechanisms.extend(x.iter().map(|x| x.to_vec()));
+            }
+        }
+        Offer {
+            mechanisms,
+            apop,
+            user: capa.capabilities.contains(&Capability::User),
+        }
+    }
+
+    /// Whether the server offers `method`
+    pub fn offers(&self, method: Method) -> bool {
+        match method {
+            Method::Apop => self.apop,
+            Method::User => self.user,
+            _ => self
+                .mechanisms
+                .iter()
+                .any(|x| x.eq_ignore_ascii_case(method.name())),
+        }
+    }
+
+    /// Chooses the strongest method both offered and in `allowed`, or
+    /// reports why each method was skipped if there is none.
+    pub fn choose(&self, allowed: &[Method]) -> Result<Negotiated, Vec<Fallback>> {
+        let mut fallbacks = vec![];
+        for method in Method::ALL {
+            let reason = if !allowed.contains(&method) {
+                Reason::NotAllowed
+            } else if !self.offers(method) {
+                Reason::NotOffered
+            } else {
+                return Ok(Negotiated { method, fallbacks });
+            };
+            fallbacks.push(Fallback { method, reason });
+        }
+        Err(fallbacks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extension::response::capa;
+
+    #[test]
+    fn test_offer() {
+        let sasl = capa(b"+OK\r\nUSER\r\nSASL scram-sha-1 PLAIN\r\n.\r\n").unwrap();
+        let offer = Offer::new(Some(&sasl), false);
+        assert!(offer.offers(Method::ScramSha1));
+        assert!(!offer.offers(Method::ScramSha256));
+        assert!(!offer.offers(Method::Apop));
+        let negotiated = offer.choose(&Method::ALL).unwrap();
+        assert_eq!(negotiated.method, Method::ScramSha1);
+        assert_eq!(
+            negotiated.fallbacks,
+            vec![Fallback {
+                method: Method::ScramSha256,
+                reason: Reason::NotOffered
+            }]
+        );
+
+        let top = capa(b"+OK\r\nTOP\r\n.\r\n").unwrap();
+        assert!(!Offer::new(Some(&top), false).offers(Method::User));
+        assert!(Offer::new(None, false).offers(Method::User));
+    }
+
+    #[test]
+    fn test_choose_none() {
+        let offer = Offer::new(None, false);
+        assert_eq!(
+            offer.choose(&[Method::CramMd5, Method::Apop]),
+            Err(vec![
+                Fallback {
+                    method: Method::ScramSha256,
+                    reason: Reason::NotAllowed
+                },
+                Fallback {
+                    method: Method::ScramSha1,
+                    reason: Reason::NotAllowed
+                },
+                Fallback {
+                    method: Method::CramMd5,
+                    reason: Reason::NotOffered
+                },
+                Fallback {
+                    method: Method::Apop,
+                    reason: Reason::NotOffered
+                },
+                Fallback {
+                    method: Method::User,
+                    reason: Reason::NotAllowed
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! TLS for the blocking client, with rustls: implicit TLS on port 995
+//! (POP3S) and STLS on port 110 (RFC 2595).
+//!
+//! Certificates are verified against the roots given to [`config`],
+//! e.g. the roots of the system or a private certificate authority.
+//! With [`Client::set_require_tls`], a client refuses to send USER,
+//! PASS or a cleartext SASL mechanism before the stream is TLS.
+//!
+//! **Examples**
+//!
+//! ```rust,no_run
+//! use rfc1939::client::tls::{config, rustls::RootCertStore};
+//! use rfc1939::client::Client;
+//! use std::net::TcpStream;
+//!
+//! # fn run(roots: RootCertStore) -> Result<(), Box<dyn std::error::Error>> {
+//! let config = config(roots);
+//! // POP3S
+//! let stream = TcpStream::connect("pop.example.com:995")?;
+//! let mut client = Client::connect_tls(stream, config.clone(), "pop.example.com".try_into()?)?;
+//! // STLS
+//! let stream = TcpStream::connect("pop.example.com:110")?;
+//! let mut client = Client::new(stream)?
+//!     .stls(config, "pop.example.com".try_into()?)
+//!     .map_err(|(e, _)| e)?;
+//! client.set_require_tls(true);
+//! client.login(b"mrose", b"tanstaaf")?;
+//! # Ok(())
+//! # }
+//! ```
+pub use rustls;
+
+use super::{Client, Connection, Error};
+use crate::types::command::{self, Command};
+use crate::types::response::Capability;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+
+/// A stream encrypted with TLS
+pub type TlsStream<S> = StreamOwned<ClientConnection, S>;
+
+/// A configuration verifying the certificates of servers against
+/// `roots`, with the cryptography of ring
+pub fn config(roots: RootCertStore) -> Arc<ClientConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Arc::new(config)
+}
+
+/// A TLS session, whose handshake is done on the first read or write of
+/// the stream it is put on.
+fn tls_connection(
+    config: Arc<ClientConfig>,
+    server_name: ServerName<'static>,
+) -> Result<ClientConnection, Error> {
+    ClientConnection::new(config, server_name)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e).into())
+}
+
+impl<S: Read + Write> Client<TlsStream<S>> {
+    /// Does the TLS handshake on `stream`, then reads the greeting of
+    /// the server.
+    pub fn connect_tls(
+        stream: S,
+        config: Arc<ClientConfig>,
+        server_name: ServerName<'static>,
+    ) -> Result<Client<TlsStream<S>>, Error> {
+        let connection = tls_connection(config, server_name)?;
+        let mut client = Client::new(StreamOwned::new(connection, stream))?;
+        client.secure = true;
+        Ok(client)
+    }
+}
+
+impl<S: Read + Write> Client<S> {
+    /// CAPA then STLS, and the TLS handshake.  Fails with
+    /// [`Error::NoStls`] if the server does not announce STLS.  The
+    /// client is given back with the error, e.g. to go on without TLS.
+    ///
+    /// Bytes the server sent after its response to STLS would be taken
+    /// as if they were sent over TLS, the response is malformed then.
+    // The client is given back as it is; STLS fails once per connection
+    // at most, so boxing it would not save anything
+    #[allow(clippy::result_large_err)]
+    pub fn stls(
+        mut self,
+        config: Arc<ClientConfig>,
+        server_name: ServerName<'static>,
+    ) -> Result<Client<TlsStream<S>>, (Error, Client<S>)> {
+        let connection = match self
+            .start_tls()
+            .and_then(|_| tls_connection(config, server_name))
+        {
+            Ok(x) => x,
+            Err(e) => return Err((e, self)),
+        };
+        Ok(Client {
+            stream: StreamOwned::new(connection, self.stream),
+            connection: Connection::established(),
+            greeting: self.greeting,
+            secure: true,
+            require_tls: self.require_tls,
+        })
+    }
+
+    /// CAPA then STLS, leaving the stream to the TLS handshake
+    fn start_tls(&mut self) -> Result<(), Error> {
+        if !self.capa()?.capabilities.contains(&Capability::Stls) {
+            return Err(Error::NoStls);
+        }
+        self.execute(&Command::Stls(command::Stls))?;
+        if self.connection.is_buffered() {
+            return Err(Error::Malformed);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::Mock;
+    use rcgen::{generate_simple_self_signed, CertifiedKey};
+    use rustls::pki_types::PrivateKeyDer;
+    use rustls::{ServerConfig, ServerConnection};
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    /// Both ends of a connection over the loopback interface
+    fn pair() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        (client, listener.accept().unwrap().0)
+    }
+
+    fn certified_key() -> CertifiedKey {
+        generate_simple_self_signed(vec!["localhost".to_string()]).unwrap()
+    }
+
+    fn roots(certified_key: &CertifiedKey) -> RootCertStore {
+        let mut roots = RootCertStore::empty();
+        roots.add(certified_key.cert.der().clone()).unwrap();
+        roots
+    }
+
+    fn server_tls(certified_key: &CertifiedKey, stream: TcpStream) -> impl Read + Write {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let key = PrivateKeyDer::Pkcs8(certified_key.key_pair.serialize_der().into());
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(vec![certified_key.cert.der().clone()], key)
+            .unwrap();
+        let connection = ServerConnection::new(Arc::new(config)).unwrap();
+        StreamOwned::new(connection, stream)
+    }
+
+    /// Answers each command of `script` with its response, after the
+    /// greeting if any.
+    fn serve<S: Read + Write>(stream: S, greeting: &[u8], script: &[(&str, &[u8])]) -> S {
+        let mut stream = BufReader::new(stream);
+        stream.get_mut().write_all(greeting).unwrap();
+        for (command, response) in script {
+            let mut line = String::new();
+            stream.read_line(&mut line).unwrap();
+            assert_eq!(line, *command);
+            stream.get_mut().write_all(response).unwrap();
+        }
+        stream.into_inner()
+    }
+
+    fn localhost() -> ServerName<'static> {
+        ServerName::try_from("localhost").unwrap()
+    }
+
+    #[test]
+    fn test_connect_tls() {
+        let certified_key = certified_key();
+        let config = config(roots(&certified_key));
+        let (client, server) = pair();
+        let server = thread::spawn(move || {
+            let script = [
+                ("USER mrose\r\n", &b"+OK\r\n"[..]),
+                ("PASS tanstaaf\r\n", b"+OK\r\n"),
+            ];
+            serve(
+                server_tls(&certified_key, server),
+                b"+OK ready\r\n",
+                &script,
+            );
+        });
+        let mut client = Client::connect_tls(client, config, localhost()).unwrap();
+        client.set_require_tls(true);
+        assert!(client.is_secure());
+        assert_eq!(client.greeting().information, b"ready");
+        client.login(b"mrose", b"tanstaaf").unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_connect_tls_unknown_root() {
+        let (client, server) = pair();
+        let server_key = certified_key();
+        thread::spawn(move || {
+            let _ = server_tls(&server_key, server).write_all(b"+OK\r\n");
+        });
+        let config = config(roots(&certified_key()));
+        assert!(matches!(
+            Client::connect_tls(client, config, localhost()),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_stls() {
+        let certified_key = certified_key();
+        let config = config(roots(&certified_key));
+        let (client, server) = pair();
+        let server = thread::spawn(move || {
+            let script = [
+                ("CAPA\r\n", &b"+OK\r\nUSER\r\nSTLS\r\n.\r\n"[..]),
+                ("STLS\r\n", b"+OK begin TLS negotiation\r\n"),
+            ];
+            let server = serve(server, b"+OK ready\r\n", &script);
+            let script = [
+                ("USER mrose\r\n", &b"+OK\r\n"[..]),
+                ("PASS tanstaaf\r\n", b"+OK\r\n"),
+            ];
+            serve(server_tls(&certified_key, server), b"", &script);
+        });
+        let mut client = Client::new(client).unwrap();
+        client.set_require_tls(true);
+        assert!(!client.is_secure());
+        let mut client = client.stls(config, localhost()).unwrap();
+        assert!(client.is_secure());
+        assert_eq!(client.greeting().information, b"ready");
+        client.login(b"mrose", b"tanstaaf").unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_stls_errors() {
+        let config = config(RootCertStore::empty());
+        let client = Client::new(Mock::new(b"+OK\r\n+OK\r\nUSER\r\n.\r\n+OK\r\n")).unwrap();
+        let Err((Error::NoStls, mut client)) = client.stls(config.clone(), localhost()) else {
+            panic!("STLS is not announced");
+        };
+        // The client is still usable without TLS
+        assert!(client.noop().is_ok());
+        assert_eq!(client.get_ref().output, b"CAPA\r\nNOOP\r\n");
+
+        let client = Client::new(Mock::new(b"+OK\r\n+OK\r\nSTLS\r\n.\r\n-ERR busy\r\n")).unwrap();
+        assert!(matches!(
+            client.stls(config.clone(), localhost()),
+            Err((Error::Negative(_), _))
+        ));
+
+        // Injected before the handshake
+        let client = Client::new(Mock::new(
+            b"+OK\r\n+OK\r\nSTLS\r\n.\r\n+OK\r\n+OK logged in\r\n",
+        ))
+        .unwrap();
+        assert!(matches!(
+            client.stls(config, localhost()),
+            Err((Error::Malformed, _))
+        ));
+    }
+
+    #[test]
+    fn test_require_tls() {
+        use crate::sasl::{login::Login, oauth::OAuthBearer, plain::Plain, scram};
+
+        let mut client = Client::new(Mock::new(b"+OK\r\n+ \r\n")).unwrap();
+        client.set_require_tls(true);
+        assert!(matches!(
+            client.login(b"mrose", b"tanstaaf"),
+            Err(Error::Cleartext)
+        ));
+        assert!(matches!(
+            client.auth(&mut Plain::new(b"mrose", b"tanstaaf")),
+            Err(Error::Cleartext)
+        ));
+        assert!(matches!(
+            client.auth(&mut Login::new(b"mrose", b"tanstaaf")),
+            Err(Error::Cleartext)
+        ));
+        assert!(matches!(
+            client.auth(&mut OAuthBearer::new(b"mrose", b"vF9dft4qmT")),
+            Err(Error::Cleartext)
+        ));
+        assert_eq!(client.get_ref().output, b"");
+
+        // SCRAM does not send the password
+        let mut scram = scram::Scram::new(scram::Algorithm::Sha256, b"mrose", b"tanstaaf");
+        assert!(client.auth(&mut scram).is_err());
+        assert!(client.get_ref().output.starts_with(b"AUTH SCRAM-SHA-256 "));
+    }
+}
@@ -0,0 +1,173 @@
+//! An asynchronous POP3 client on tokio.
+//!
+//! The operations are those of the blocking [`Client`](super::Client),
+//! along with the other commands of [`types::command`](crate::types::command).
+//!
+//! Every operation is cancellation-safe: if its future is dropped, e.g.
+//! by `tokio::select!` or a timeout, the command is still written in
+//! full and its response is skipped before the next operation.  A
+//! timeout set with [`Client::set_timeout`] applies to each operation.
+//!
+//! **Examples**
+//!
+//! ```rust,no_run
+//! use rfc1939::client::tokio::Client;
+//! use std::time::Duration;
+//! use tokio::net::TcpStream;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut client = Client::new(TcpStream::connect("pop.example.com:110").await?).await?;
+//! client.set_timeout(Some(Duration::from_secs(30)));
+//! client.login(b"mrose", b"tanstaaf").await?;
+//! let stat = client.stat().await?;
+//! for msg in 1..=stat.number_of_messages {
+//!     let message = client.retr(msg).await?.message.unwrap_or_default().to_vec();
+//!     // ...
+//!     client.dele(msg).await?;
+//! }
+//! client.quit().await?;
+//! # Ok(())
+//! # }
+//! ```
+use ::tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::future::Future;
+
+super::asynchronous::async_client!();
+
+/// Runs `future` for at most `duration`.
+async fn within<F: Future>(duration: Duration, future: F) -> Result<F::Output, Error> {
+    ::tokio::time::timeout(duration, future)
+        .await
+        .map_err(|_| Error::Timeout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sasl::cram_md5::CramMd5;
+    use crate::sasl::plain::Plain;
+    use ::tokio::io::{duplex, DuplexStream};
+
+    async fn server(responses: &[u8]) -> (Client<DuplexStream>, DuplexStream) {
+        let (client, mut server) = duplex(4096);
+        server.write_all(responses).await.unwrap();
+        (Client::new(client).await.unwrap(), server)
+    }
+
+    async fn received(server: &mut DuplexStream, n: usize) -> Vec<u8> {
+        let mut buf = vec![0; n];
+        server.read_exact(&mut buf).await.unwrap();
+        buf
+    }
+
+    #[::tokio::test]
+    async fn test_client() {
+        let (mut client, mut server) = server(
+            b"+OK POP3 server ready\r\n+OK\r\n+OK maildrop has 2 messages\r\n\
+              +OK 2 320\r\n+OK\r\nSubject: a\r\n\r\n..b\r\n.\r\n+OK\r\n",
+        )
+        .await;
+        assert_eq!(client.greeting().information, b"POP3 server ready");
+        assert_eq!(
+            client
+                .login(b"mrose", b"tanstaaf")
+                .await
+                .unwrap()
+                .information,
+            b"maildrop has 2 messages"
+        );
+        assert_eq!(client.stat().await.unwrap().size_in_octets, 320);
+        assert_eq!(
+            client.retr(1).await.unwrap().message,
+            Some(&b"Subject: a\r\n\r\n..b"[..])
+        );
+        server.write_all(b"-ERR no such message\r\n").await.unwrap();
+        assert!(client.dele(1).await.is_ok());
+        assert_eq!(
+            client.dele(3).await.unwrap_err().to_string(),
+            "-ERR no such message"
+        );
+        assert_eq!(
+            received(&mut server, 57).await,
+            b"USER mrose\r\nPASS tanstaaf\r\nSTAT\r\nRETR 1\r\nDELE 1\r\nDELE 3\r\n"
+        );
+    }
+
+    #[::tokio::test]
+    async fn test_client_timeout() {
+        let (mut client, mut server) = server(b"+OK\r\n").await;
+        client.set_timeout(Some(Duration::from_millis(50)));
+        assert!(matches!(client.noop().await, Err(Error::Timeout)));
+
+        // The response to the first NOOP is skipped
+        server
+            .write_all(b"+OK first\r\n+OK second\r\n")
+            .await
+            .unwrap();
+        assert_eq!(client.noop().await.unwrap().information, b"second");
+        assert_eq!(received(&mut server, 12).await, b"NOOP\r\nNOOP\r\n");
+
+        assert!(matches!(
+            Client::with_timeout(duplex(4096).0, Some(Duration::from_millis(50))).await,
+            Err(Error::Timeout)
+        ));
+    }
+
+    #[::tokio::test]
+    async fn test_client_cancel() {
+        let (mut client, mut server) = server(b"+OK\r\n").await;
+        ::tokio::select! {
+            _ = client.list(None) => panic!(),
+            _ = ::tokio::time::sleep(Duration::from_millis(10)) => {}
+        }
+        server
+            .write_all(b"+OK\r\n1 120\r\n.\r\n+OK 1 120\r\n")
+            .await
+            .unwrap();
+        assert_eq!(client.stat().await.unwrap().number_of_messages, 1);
+    }
+
+    #[::tokio::test]
+    async fn test_client_authenticate() {
+        let (mut client, mut server) = server(
+            b"+OK <1896.697170952@dbc.mtview.ca.us>\r\n+OK\r\nSASL CRAM-MD5 PLAIN\r\n.\r\n\
+              + PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n+OK\r\n",
+        )
+        .await;
+        let negotiated = client
+            .authenticate(b"tim", b"tanstaaftanstaaf", &Method::ALL)
+            .await
+            .unwrap();
+        assert_eq!(negotiated.method, Method::CramMd5);
+        assert_eq!(
+            received(&mut server, 71).await,
+            b"CAPA\r\nAUTH CRAM-MD5\r\ndGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\n"
+        );
+    }
+
+    #[::tokio::test]
+    async fn test_client_auth() {
+        let (mut client, mut server) = server(
+            b"+OK\r\n+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n\
+              +OK CRAM-MD5 authentication successful\r\n",
+        )
+        .await;
+        let mut cram_md5 = CramMd5::new(b"tim", b"tanstaaftanstaaf");
+        assert_eq!(
+            client.auth(&mut cram_md5).await.unwrap().information,
+            b"CRAM-MD5 authentication successful"
+        );
+        assert_eq!(
+            received(&mut server, 65).await,
+            b"AUTH CRAM-MD5\r\ndGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\n"
+        );
+
+        // PLAIN sends no response to a challenge
+        server
+            .write_all(b"+ Y2hhbGxlbmdl\r\n-ERR cancelled\r\n")
+            .await
+            .unwrap();
+        let mut plain = Plain::new(b"tim", b"secret");
+        assert!(matches!(client.auth(&mut plain).await, Err(Error::Sasl(_))));
+    }
+}
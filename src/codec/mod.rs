@@ -0,0 +1,560 @@
+//! Codecs for `tokio_util::codec`, framing a connection into commands
+//! or responses.
+//!
+//! [`Pop3ClientCodec`] encodes commands and decodes the responses to
+//! them, [`Pop3ServerCodec`] decodes commands and encodes the replies of
+//! a server.  The frames own their bytes and are parsed on demand, as
+//! the types of this crate borrow from what they are parsed from.
+//!
+//! Whether a response is multi-line is known from the commands encoded
+//! before it, which the client codec queues.  A frame longer than the
+//! maximum size of the codec, e.g. the body of a huge message, is an
+//! error rather than buffered without bound.
+//!
+//! **Examples**
+//!
+//! ```rust,no_run
+//! use futures_util::{SinkExt, StreamExt};
+//! use rfc1939::codec::Pop3ClientCodec;
+//! use rfc1939::types::command::{Command, Stat};
+//! use rfc1939::types::response::Response;
+//! use tokio::net::TcpStream;
+//! use tokio_util::codec::Framed;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let stream = TcpStream::connect("pop.example.com:110").await?;
+//! let mut framed = Framed::new(stream, Pop3ClientCodec::new());
+//! let greeting = framed.next().await.ok_or("closed")??;
+//! println!("{:?}", greeting.greeting());
+//! framed.send(Command::Stat(Stat)).await?;
+//! let frame = framed.next().await.ok_or("closed")??;
+//! if let Some(Response::Stat(stat)) = frame.response() {
+//!     println!("{} messages", stat.number_of_messages);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::authorization::command::auth_response;
+use crate::authorization::response::greeting;
+use crate::common::StatusIndicator;
+use crate::framing::{body_length, find, line_length, shape};
+use crate::session::registry::Registry;
+use crate::session::{command, response};
+use crate::types::command::{AuthResponse, Command, Custom, Encode};
+use crate::types::response::{Greeting, Response, Shape};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The maximum size of a frame the client codec decodes by default
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The maximum size of a frame the server codec decodes by default
+pub const DEFAULT_MAX_COMMAND_SIZE: usize = 8 * 1024;
+
+/// Errors of a codec
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// A frame is longer than the maximum size of the codec
+    FrameTooLong,
+    /// A command cannot be encoded
+    Command(crate::types::command::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(x) => write!(f, "I/O error: {}", x),
+            Error::FrameTooLong => write!(f, "frame longer than the maximum size"),
+            Error::Command(x) => write!(f, "command: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(x) => Some(x),
+            Error::Command(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// What a response from the server answers
+#[derive(Debug, Clone, PartialEq)]
+enum Expected {
+    Greeting,
+    /// A command, as encoded
+    Command(Bytes),
+    /// A command of a registry, as encoded
+    Custom(Bytes, Shape),
+    AuthResponse,
+    /// Nothing, the server sent a line on its own
+    Nothing,
+}
+
+impl Expected {
+    fn shape(&self) -> Shape {
+        match self {
+            Expected::Command(x) => command(x).map_or(Shape::OneLine, |x| shape(&x)),
+            Expected::Custom(_, x) => *x,
+            _ => Shape::OneLine,
+        }
+    }
+}
+
+/// A response decoded by [`Pop3ClientCodec`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseFrame {
+    expected: Expected,
+    bytes: Bytes,
+}
+
+impl ResponseFrame {
+    /// The bytes of the response, including its termination line
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The greeting, if this is the first response of the connection
+    pub fn greeting(&self) -> Option<Greeting<'_>> {
+        match self.expected {
+            Expected::Greeting => greeting(&self.bytes),
+            _ => None,
+        }
+    }
+
+    /// The response, parsed as the answer to the command it follows
+    pub fn response(&self) -> Option<Response<'_>> {
+        match &self.expected {
+            Expected::Command(x) => response(&command(x)?, &self.bytes),
+            Expected::Custom(x, shape) => match command(x)? {
+                Command::Unknown(x) => {
+                    let custom = Custom {
+                        keyword: x.keyword,
+                        args: x.args,
+                        shape: *shape,
+                    };
+                    response(&Command::Custom(custom), &self.bytes)
+                }
+                _ => None,
+            },
+            Expected::AuthResponse => {
+                response(&Command::AuthResponse(AuthResponse::Cancel), &self.bytes)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The client side of a connection: encodes commands and decodes the
+/// responses to them
+#[derive(Debug)]
+pub struct Pop3ClientCodec {
+    expected: VecDeque<Expected>,
+    /// The length of the current frame searched for its end
+    scanned: usize,
+    max_frame_size: usize,
+}
+
+impl Default for Pop3ClientCodec {
+    fn default() -> Self {
+        Pop3ClientCodec::with_max_frame_size(DEFAULT_MAX_RESPONSE_SIZE)
+    }
+}
+
+impl Pop3ClientCodec {
+    /// A codec for a new connection, whose first response is the
+    /// greeting
+    pub fn new() -> Pop3ClientCodec {
+        Pop3ClientCodec::default()
+    }
+
+    pub fn with_max_frame_size(max_frame_size: usize) -> Pop3ClientCodec {
+        Pop3ClientCodec {
+            expected: VecDeque::from([Expected::Greeting]),
+            scanned: 0,
+            max_frame_size,
+        }
+    }
+
+    /// The number of responses not decoded yet
+    pub fn pending(&self) -> usize {
+        self.expected.len()
+    }
+
+    /// The length of the next frame in `buf`, searching the bytes
+    /// received since the last call only.
+    fn frame_length(&mut self, buf: &[u8]) -> Option<usize> {
+        let status_line = line_length(buf)?;
+        let shape = self
+            .expected
+            .front()
+            .map_or(Shape::OneLine, Expected::shape);
+        let positive = buf.len() >= 3 && buf[..3].eq_ignore_ascii_case(b"+OK");
+        if !positive || shape == Shape::OneLine {
+            return Some(status_line);
+        }
+        // The termination line may have started in the bytes searched
+        let from = self.scanned.saturating_sub(4).max(status_line);
+        let length = match from {
+            _ if from == status_line => body_length(&buf[from..]).map(|x| from + x),
+            _ => find(&buf[from..], b"\r\n.\r\n").map(|x| from + x + 5),
+        };
+        self.scanned = buf.len();
+        length
+    }
+}
+
+impl Decoder for Pop3ClientCodec {
+    type Item = ResponseFrame;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<ResponseFrame>, Error> {
+        let Some(length) = self.frame_length(buf) else {
+            if buf.len() > self.max_frame_size {
+                return Err(Error::FrameTooLong);
+            }
+            return Ok(None);
+        };
+        if length > self.max_frame_size {
+            return Err(Error::FrameTooLong);
+        }
+        self.scanned = 0;
+        Ok(Some(ResponseFrame {
+            expected: self.expected.pop_front().unwrap_or(Expected::Nothing),
+            bytes: buf.split_to(length).freeze(),
+        }))
+    }
+}
+
+impl Encoder<Command<'_>> for Pop3ClientCodec {
+    type Error = Error;
+
+    fn encode(&mut self, command: Command<'_>, buf: &mut BytesMut) -> Result<(), Error> {
+        let mut line = vec![];
+        command.encode(&mut line).map_err(Error::Command)?;
+        buf.put_slice(&line);
+        self.expected.push_back(match command {
+            Command::AuthResponse(_) => Expected::AuthResponse,
+            Command::Custom(x) => Expected::Custom(line.into(), x.shape),
+            _ => Expected::Command(line.into()),
+        });
+        Ok(())
+    }
+}
+
+/// A command decoded by [`Pop3ServerCodec`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandFrame {
+    /// Whether the line answers a challenge of an AUTH exchange
+    auth_response: bool,
+    bytes: Bytes,
+}
+
+impl CommandFrame {
+    /// The bytes of the command, including the CRLF pair
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The command, or the answer to a challenge if the server sent one
+    pub fn command(&self) -> Option<Command<'_>> {
+        match self.auth_response {
+            true => auth_response(&self.bytes).map(Command::AuthResponse),
+            false => command(&self.bytes),
+        }
+    }
+
+    /// The command, parsing the commands of `registry` as well
+    pub fn command_with(&self, registry: &Registry) -> Option<Command<'_>> {
+        match self.auth_response {
+            true => self.command(),
+            false => registry.command(&self.bytes),
+        }
+    }
+}
+
+/// A reply encoded by [`Pop3ServerCodec`]
+#[derive(Debug, PartialEq)]
+pub enum Reply<'a> {
+    /// A status line, "+OK" or "-ERR" and the information
+    OneLine {
+        status_indicator: StatusIndicator,
+        information: &'a [u8],
+    },
+    /// "+OK", the information and the lines of `body`, which are
+    /// byte-stuffed and terminated
+    MultiLine {
+        information: &'a [u8],
+        body: &'a [u8],
+    },
+    /// A continuation line of an AUTH exchange, with the challenge
+    /// before base64 encoding
+    Challenge { challenge: &'a [u8] },
+}
+
+/// The server side of a connection: decodes commands and encodes
+/// replies
+#[derive(Debug)]
+pub struct Pop3ServerCodec {
+    /// Whether the last reply was a challenge
+    auth: bool,
+    /// The length of the current frame searched for its end
+    scanned: usize,
+    max_frame_size: usize,
+}
+
+impl Default for Pop3ServerCodec {
+    fn default() -> Self {
+        Pop3ServerCodec::with_max_frame_size(DEFAULT_MAX_COMMAND_SIZE)
+    }
+}
+
+impl Pop3ServerCodec {
+    pub fn new() -> Pop3ServerCodec {
+        Pop3ServerCodec::default()
+    }
+
+    pub fn with_max_frame_size(max_frame_size: usize) -> Pop3ServerCodec {
+        Pop3ServerCodec {
+            auth: false,
+            scanned: 0,
+            max_frame_size,
+        }
+    }
+}
+
+impl Decoder for Pop3ServerCodec {
+    type Item = CommandFrame;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<CommandFrame>, Error> {
+        let from = self.scanned.saturating_sub(1);
+        let Some(length) = line_length(&buf[from..]).map(|x| from + x) else {
+            if buf.len() > self.max_frame_size {
+                return Err(Error::FrameTooLong);
+            }
+            self.scanned = buf.len();
+            return Ok(None);
+        };
+        if length > self.max_frame_size {
+            return Err(Error::FrameTooLong);
+        }
+        self.scanned = 0;
+        Ok(Some(CommandFrame {
+            auth_response: std::mem::take(&mut self.auth),
+            bytes: buf.split_to(length).freeze(),
+        }))
+    }
+}
+
+impl Encoder<Reply<'_>> for Pop3ServerCodec {
+    type Error = Error;
+
+    fn encode(&mut self, reply: Reply<'_>, buf: &mut BytesMut) -> Result<(), Error> {
+        self.auth = false;
+        match reply {
+            Reply::OneLine {
+                status_indicator,
+                information,
+            } => status_line(buf, status_indicator, information),
+            Reply::MultiLine { information, body } => {
+                status_line(buf, StatusIndicator::OK, information);
+                for line in body.split_inclusive(|x| *x == b'\n') {
+                    if line.starts_with(b".") {
+                        buf.put_u8(b'.');
+                    }
+                    buf.put_slice(line);
+                }
+                if !body.is_empty() && !body.ends_with(b"\r\n") {
+                    buf.put_slice(b"\r\n");
+                }
+                buf.put_slice(b".\r\n");
+            }
+            Reply::Challenge { challenge } => {
+                buf.put_slice(b"+ ");
+                buf.put_slice(STANDARD.encode(challenge).as_bytes());
+                buf.put_slice(b"\r\n");
+                self.auth = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn status_line(buf: &mut BytesMut, status_indicator: StatusIndicator, information: &[u8]) {
+    buf.put_slice(match status_indicator {
+        StatusIndicator::OK => b"+OK",
+        StatusIndicator::ERR => b"-ERR",
+    });
+    if !information.is_empty() {
+        buf.put_u8(b' ');
+        buf.put_slice(information);
+    }
+    buf.put_slice(b"\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::command::{Auth, List, Retr, Stat};
+    use crate::types::response::List as ListResponse;
+
+    #[test]
+    fn test_client_codec() {
+        let mut codec = Pop3ClientCodec::new();
+        let mut buf = BytesMut::new();
+        codec
+            .encode(Command::Retr(Retr { msg: 1 }), &mut buf)
+            .unwrap();
+        codec
+            .encode(Command::List(List { msg: None }), &mut buf)
+            .unwrap();
+        codec.encode(Command::Stat(Stat), &mut buf).unwrap();
+        assert_eq!(&buf[..], b"RETR 1\r\nLIST\r\nSTAT\r\n");
+        assert_eq!(codec.pending(), 4);
+
+        let mut buf = BytesMut::from(&b"+OK POP3 server ready\r\n+OK\r\nSubject: a\r\n"[..]);
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.greeting().unwrap().information, b"POP3 server ready");
+        assert_eq!(frame.response(), None);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.put_slice(b"\r\n.");
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.put_slice(b"\r\n+OK\r\n1 120\r\n.\r\n+OK 1 120\r\n");
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.as_bytes(), b"+OK\r\nSubject: a\r\n\r\n.\r\n");
+        assert!(matches!(frame.response(), Some(Response::Retr(_))));
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(matches!(
+            frame.response(),
+            Some(Response::List(ListResponse { informations, .. })) if informations == vec![(1, 120)]
+        ));
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(matches!(frame.response(), Some(Response::Stat(_))));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(codec.pending(), 0);
+    }
+
+    #[test]
+    fn test_client_codec_auth() {
+        let mut codec = Pop3ClientCodec::new();
+        let mut buf = BytesMut::new();
+        let auth = Auth {
+            mechanism: b"CRAM-MD5",
+            initial_response: None,
+        };
+        codec.encode(Command::Auth(auth), &mut buf).unwrap();
+        let response = AuthResponse::Data(b"tim b913a602c7eda7a495b4e6e7334d3890".to_vec());
+        codec
+            .encode(Command::AuthResponse(response), &mut buf)
+            .unwrap();
+        let mut buf = BytesMut::from(
+            &b"+OK\r\n+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n+OK\r\n"[..],
+        );
+        codec.decode(&mut buf).unwrap().unwrap();
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(matches!(frame.response(), Some(Response::AuthChallenge(_))));
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(matches!(frame.response(), Some(Response::Auth(_))));
+    }
+
+    #[test]
+    fn test_client_codec_custom() {
+        let mut codec = Pop3ClientCodec::new();
+        let mut buf = BytesMut::new();
+        let custom = Custom {
+            keyword: b"XLIST",
+            args: vec![],
+            shape: Shape::MultiLine,
+        };
+        codec.encode(Command::Custom(custom), &mut buf).unwrap();
+        let mut buf = BytesMut::from(&b"+OK\r\n+OK\r\na\r\n.\r\n"[..]);
+        codec.decode(&mut buf).unwrap().unwrap();
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        match frame.response() {
+            Some(Response::Custom(x)) => assert_eq!(x.body, Some(&b"a"[..])),
+            x => panic!("{:?}", x),
+        }
+    }
+
+    #[test]
+    fn test_client_codec_max_frame_size() {
+        let mut codec = Pop3ClientCodec::with_max_frame_size(24);
+        let mut buf = BytesMut::new();
+        codec
+            .encode(Command::Retr(Retr { msg: 1 }), &mut buf)
+            .unwrap();
+        let mut buf = BytesMut::from(&b"+OK\r\n+OK\r\nSubject: a\r\n"[..]);
+        codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.put_slice(b"From: b\r\n");
+        assert!(matches!(codec.decode(&mut buf), Err(Error::FrameTooLong)));
+    }
+
+    #[test]
+    fn test_server_codec() {
+        let mut codec = Pop3ServerCodec::new();
+        let mut buf = BytesMut::from(&b"AUTH CRAM-MD5\r\ndGlt"[..]);
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(matches!(frame.command(), Some(Command::Auth(_))));
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        let mut out = BytesMut::new();
+        let challenge = Reply::Challenge {
+            challenge: b"<1896.697170952@postoffice.reston.mci.net>",
+        };
+        codec.encode(challenge, &mut out).unwrap();
+        assert_eq!(
+            &out[..],
+            b"+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n"
+        );
+        buf.put_slice(b"IGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\nSTAT\r\n");
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(
+            frame.command(),
+            Some(Command::AuthResponse(AuthResponse::Data(
+                b"tim b913a602c7eda7a495b4e6e7334d3890".to_vec()
+            )))
+        );
+        let frame = codec.decode(&mut buf).unwrap().unwrap();
+        assert_eq!(frame.command(), Some(Command::Stat(Stat)));
+
+        let mut out = BytesMut::new();
+        let multi_line = Reply::MultiLine {
+            information: b"",
+            body: b"Subject: a\r\n\r\n.b\r\nc",
+        };
+        codec.encode(multi_line, &mut out).unwrap();
+        let error = Reply::OneLine {
+            status_indicator: StatusIndicator::ERR,
+            information: b"no such message",
+        };
+        codec.encode(error, &mut out).unwrap();
+        assert_eq!(
+            &out[..],
+            b"+OK\r\nSubject: a\r\n\r\n..b\r\nc\r\n.\r\n-ERR no such message\r\n"
+        );
+    }
+
+    #[test]
+    fn test_server_codec_max_frame_size() {
+        let mut codec = Pop3ServerCodec::with_max_frame_size(8);
+        let mut buf = BytesMut::from(&b"USER mro"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        buf.put_slice(b"se\r\n");
+        assert!(matches!(codec.decode(&mut buf), Err(Error::FrameTooLong)));
+    }
+}
@@ -1,12 +1,14 @@
 //! Common functions
 
 use crate::types::response::*;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use nom::{
     branch::alt,
-    bytes::complete::{tag, tag_no_case, take_until},
-    combinator::{map, opt},
+    bytes::complete::{tag, tag_no_case, take_until, take_while, take_while1},
+    combinator::{map, map_res, opt},
+    multi::separated_list1,
     sequence::terminated,
-    sequence::{preceded, tuple},
+    sequence::{delimited, preceded, tuple},
     IResult,
 };
 use std::str::from_utf8;
@@ -33,59 +35,103 @@ pub(crate) fn take_until_sp(s: &[u8]) -> IResult<&[u8], &[u8]> {
 /// in which status indicator and messages exist.
 pub(crate) fn one_line_response_two_parts_parser<'a, T: OneLine<'a> + Default>(
     s: &'a [u8],
-) -> IResult<&[u8], T> {
+) -> IResult<&'a [u8], T> {
     map(
         tuple((
             alt((
                 map(tag_no_case(b"+OK"), |_| StatusIndicator::OK),
                 map(tag_no_case(b"-ERR"), |_| StatusIndicator::ERR),
             )),
-            opt(preceded(tag(b" "), take_until_crlf_consume_crlf)),
+            alt((
+                preceded(tag(b" "), take_until_crlf_consume_crlf),
+                map(tag(b"\r\n"), |_| &b""[..]),
+            )),
         )),
         |(si, information)| {
             let mut response = T::default();
             response.set_status_indicator(si);
-            response.set_information(if let Some(information) = information {
-                information
-            } else {
-                &[]
-            });
+            response.set_information(information);
             response
         },
     )(s)
 }
 
+/// A parser parses the extended response code (RFC 2449) at the start of
+/// the information of a response, along with the single space following
+/// it.
+pub(crate) fn response_code_parser(s: &[u8]) -> IResult<&[u8], ResponseCode<'_>> {
+    map(
+        terminated(
+            delimited(
+                tag(b"["),
+                separated_list1(
+                    tag(b"/"),
+                    take_while1(|x: u8| x.is_ascii_graphic() && x != b'/' && x != b']'),
+                ),
+                tag(b"]"),
+            ),
+            opt(tag(b" ")),
+        ),
+        |levels: Vec<&[u8]>| {
+            let level = |i: usize| levels.get(i).map(|x| x.to_ascii_uppercase());
+            match level(0).as_deref() {
+                Some(b"LOGIN-DELAY") => ResponseCode::LoginDelay,
+                Some(b"IN-USE") => ResponseCode::InUse,
+                Some(b"SYS") => ResponseCode::Sys(match level(1).as_deref() {
+                    Some(b"TEMP") => SysCode::Temp,
+                    Some(b"PERM") => SysCode::Perm,
+                    _ => SysCode::Unspecified,
+                }),
+                Some(b"AUTH") => ResponseCode::Auth,
+                Some(b"UTF8") => ResponseCode::Utf8,
+                _ => ResponseCode::Unknown(levels),
+            }
+        },
+    )(s)
+}
+
+/// A parser parses base64 encoded data (RFC 4648), possibly empty, and
+/// decodes it.
+pub(crate) fn base64_parser(s: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    map_res(
+        take_while(|x: u8| x.is_ascii_alphanumeric() || x == b'+' || x == b'/' || x == b'='),
+        |x| STANDARD.decode(x),
+    )(s)
+}
+
 pub(crate) fn parse_u8_slice_to_usize_or_0(s: &[u8]) -> usize {
     if let Ok(str) = from_utf8(s) {
-        if let Ok(digit) = str::parse::<usize>(str) {
-            digit
-        } else {
-            0
-        }
+        str::parse::<usize>(str).unwrap_or_default()
     } else {
         0
     }
 }
 
-pub(crate) fn retr_message_parser<'a, T: HaveMessageBody<'a>>(s: &'a [u8]) -> IResult<&[u8], T> {
-    map(
-        tuple((
-            alt((
-                map(tag_no_case(b"+OK"), |_| StatusIndicator::OK),
-                map(tag_no_case(b"-ERR"), |_| StatusIndicator::ERR),
-            )),
-            tag(b" "),
-            take_until_crlf_consume_crlf,
-            opt(terminated(take_until("\r\n.\r\n"), tag(b"\r\n.\r\n"))),
+/// A parser parses a multi-line response carrying a message, whose body
+/// may be empty.  A negative response has no body.
+pub(crate) fn retr_message_parser<'a, T: HaveMessageBody<'a>>(s: &'a [u8]) -> IResult<&'a [u8], T> {
+    let (s, (si, information)) = tuple((
+        alt((
+            map(tag_no_case(b"+OK"), |_| StatusIndicator::OK),
+            map(tag_no_case(b"-ERR"), |_| StatusIndicator::ERR),
         )),
-        |(si, _, information, message)| {
-            let mut tmp_message = T::default();
-            tmp_message.set_status_indicator(si);
-            tmp_message.set_information(information);
-            tmp_message.set_message(message);
-            tmp_message
-        },
-    )(s)
+        alt((
+            preceded(tag(b" "), take_until_crlf_consume_crlf),
+            map(tag(b"\r\n"), |_| &b""[..]),
+        )),
+    ))(s)?;
+    let (s, message) = match si {
+        StatusIndicator::OK => opt(alt((
+            map(tag(b".\r\n"), |_| &b""[..]),
+            terminated(take_until("\r\n.\r\n"), tag(b"\r\n.\r\n")),
+        )))(s)?,
+        StatusIndicator::ERR => (s, None),
+    };
+    let mut tmp_message = T::default();
+    tmp_message.set_status_indicator(si);
+    tmp_message.set_information(information);
+    tmp_message.set_message(message);
+    Ok((s, tmp_message))
 }
 
 #[cfg(test)]
@@ -98,6 +144,38 @@ mod tests {
         assert_eq!(take_until_crlf(b"\r\n").unwrap().1, b"");
     }
 
+    #[test]
+    fn test_response_code_parser() {
+        assert_eq!(
+            response_code_parser(b"[IN-USE] maildrop locked").unwrap(),
+            (&b"maildrop locked"[..], ResponseCode::InUse)
+        );
+        assert_eq!(
+            response_code_parser(b"[sys/temp]").unwrap(),
+            (&b""[..], ResponseCode::Sys(SysCode::Temp))
+        );
+        assert_eq!(
+            response_code_parser(b"[SYS/X-DISK] full").unwrap().1,
+            ResponseCode::Sys(SysCode::Unspecified)
+        );
+        assert_eq!(
+            response_code_parser(b"[X-VENDOR/QUOTA] over quota").unwrap().1,
+            ResponseCode::Unknown(vec![b"X-VENDOR", b"QUOTA"])
+        );
+        assert!(response_code_parser(b"[no code] here").is_err());
+        assert!(response_code_parser(b"maildrop locked").is_err());
+    }
+
+    #[test]
+    fn test_base64_parser() {
+        assert_eq!(
+            base64_parser(b"dGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\n").unwrap(),
+            (&b"\r\n"[..], b"tim b913a602c7eda7a495b4e6e7334d3890".to_vec())
+        );
+        assert_eq!(base64_parser(b"\r\n").unwrap().1, b"");
+        assert!(base64_parser(b"dGlt=\r\n").is_err());
+    }
+
     #[test]
     fn test_one_line_response_two_parts_parser() {
         assert_eq!(
@@ -108,6 +186,17 @@ mod tests {
                 status_indicator: StatusIndicator::OK,
                 information: b"POP3 server ready"
             }
-        )
+        );
+        assert_eq!(
+            one_line_response_two_parts_parser::<Greeting>(b"+OK\r\n+OK\r\n").unwrap(),
+            (
+                &b"+OK\r\n"[..],
+                Greeting {
+                    status_indicator: StatusIndicator::OK,
+                    information: b""
+                }
+            )
+        );
+        assert!(one_line_response_two_parts_parser::<Greeting>(b"+OKAY\r\n").is_err());
     }
 }
@@ -0,0 +1,176 @@
+use crate::common::take_until_crlf_consume_crlf;
+use crate::types::command::{Capa, Lang, Unknown};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_while1},
+    combinator::{map, opt},
+    sequence::{delimited, pair, preceded, terminated},
+    IResult,
+};
+
+// ################################################################################
+/// CAPA
+///
+/// **Arguments** none
+///
+/// **Restrictions** none
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: CAPA
+/// use rfc1939::extension::command::capa;
+/// use rfc1939::types::command::Capa;
+/// assert_eq!(capa(b"CAPA\r\n").unwrap(), Capa)
+/// ```
+// ################################################################################
+pub fn capa(s: &[u8]) -> Option<Capa> {
+    match capa_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn capa_parser(s: &[u8]) -> IResult<&[u8], Capa> {
+    map(terminated(tag_no_case(b"CAPA"), tag(b"\r\n")), |_| Capa)(s)
+}
+
+// ################################################################################
+/// LANG \[*lang-range*\]
+///
+/// **Arguments**
+///
+/// A language tag, or "*" for the client's default language
+/// (optional)
+///
+/// **Restrictions** none
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: LANG fr
+/// use rfc1939::extension::command::lang;
+/// use rfc1939::types::command::Lang;
+/// assert_eq!(lang(b"LANG\r\n").unwrap(), Lang { tag: None });
+/// assert_eq!(lang(b"LANG fr\r\n").unwrap(), Lang { tag: Some(b"fr") });
+/// ```
+// ################################################################################
+pub fn lang(s: &[u8]) -> Option<Lang<'_>> {
+    match lang_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn lang_parser(s: &[u8]) -> IResult<&[u8], Lang<'_>> {
+    map(
+        delimited(
+            tag_no_case(b"LANG"),
+            opt(preceded(
+                tag(b" "),
+                take_while1(|x: u8| x.is_ascii_alphanumeric() || x == b'-' || x == b'*'),
+            )),
+            tag(b"\r\n"),
+        ),
+        |tag| Lang { tag },
+    )(s)
+}
+
+// ################################################################################
+/// Any other command
+///
+/// **Arguments**
+///
+/// a keyword, followed by arguments separated by spaces
+///
+/// **Discussion**
+///
+/// Parses the commands this crate does not know, such as vendor
+/// extensions, so that they can still be correlated with their
+/// response and forwarded.  The keyword is made of letters, digits,
+/// "-" and "_".
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: XSENDER 1
+/// use rfc1939::extension::command::unknown;
+/// use rfc1939::types::command::Unknown;
+/// assert_eq!(
+///     unknown(b"XSENDER 1\r\n").unwrap(),
+///     Unknown { keyword: b"XSENDER", args: vec![b"1"] }
+/// )
+/// ```
+// ################################################################################
+pub fn unknown(s: &[u8]) -> Option<Unknown<'_>> {
+    match unknown_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn unknown_parser(s: &[u8]) -> IResult<&[u8], Unknown<'_>> {
+    map(
+        pair(
+            take_while1(|x: u8| x.is_ascii_alphanumeric() || x == b'-' || x == b'_'),
+            alt((
+                preceded(tag(b" "), take_until_crlf_consume_crlf),
+                map(tag(b"\r\n"), |_| &b""[..]),
+            )),
+        ),
+        |(keyword, args): (&[u8], &[u8])| Unknown {
+            keyword,
+            args: if args.is_empty() {
+                vec![]
+            } else {
+                args.split(|x| *x == b' ').collect()
+            },
+        },
+    )(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capa() {
+        assert_eq!(capa(b"CAPA\r\n").unwrap(), Capa);
+        assert_eq!(capa(b"capa\r\n").unwrap(), Capa);
+        assert_eq!(capa(b"CAPA 1\r\n"), None);
+    }
+
+    #[test]
+    fn test_unknown() {
+        assert_eq!(
+            unknown(b"ID\r\n").unwrap(),
+            Unknown {
+                keyword: b"ID",
+                args: vec![]
+            }
+        );
+        assert_eq!(
+            unknown(b"X-PRIV a b\r\n").unwrap(),
+            Unknown {
+                keyword: b"X-PRIV",
+                args: vec![b"a", b"b"]
+            }
+        );
+        assert_eq!(unknown(b"\x16\x03\x01\r\n"), None);
+        assert_eq!(unknown(b"XSENDER 1"), None);
+    }
+
+    #[test]
+    fn test_lang() {
+        assert_eq!(lang(b"LANG\r\n").unwrap(), Lang { tag: None });
+        assert_eq!(lang(b"lang *\r\n").unwrap(), Lang { tag: Some(b"*") });
+        assert_eq!(
+            lang(b"LANG en-US\r\n").unwrap(),
+            Lang {
+                tag: Some(b"en-US")
+            }
+        );
+        assert_eq!(lang(b"LANG \r\n"), None);
+        assert_eq!(lang(b"LANG en fr\r\n"), None);
+    }
+}
@@ -0,0 +1,5 @@
+//! Commands introduced by extensions to POP3 (RFC 2449 and later) which
+//! may be given in both the AUTHORIZATION and the TRANSACTION state.
+
+pub mod command;
+pub mod response;
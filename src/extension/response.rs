@@ -0,0 +1,566 @@
+use crate::common::{
+    one_line_response_two_parts_parser, parse_u8_slice_to_usize_or_0, take_until_crlf_consume_crlf,
+    StatusIndicator,
+};
+use crate::types::response::{Capa, Capability, Expire, Lang, Language, OneLineTwoParts, Unknown};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_until},
+    combinator::{map, verify},
+    multi::many0,
+    sequence::{preceded, terminated, tuple},
+    IResult,
+};
+
+// ################################################################################
+/// CAPA
+///
+/// **Restrictions** none
+///
+/// **Discussion**
+///
+/// An -ERR response indicates the capability command is not
+/// implemented and the client will have to probe for
+/// capabilities as before.
+///
+/// An +OK response is followed by a list of capabilities, one
+/// per line.  Each capability name MAY be followed by a single
+/// space and a space-separated list of parameters.  Each
+/// capability line is limited to 512 octets (including the
+/// CRLF).  The capability list is terminated by a line
+/// containing a termination octet (".") and a CRLF pair.
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK -ERR
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::common::StatusIndicator;
+/// use rfc1939::extension::response::capa;
+/// use rfc1939::types::response::{Capa, Capability};
+/// // S: +OK Capability list follows
+/// // S: TOP
+/// // S: SASL CRAM-MD5 KERBEROS_V4
+/// // S: .
+/// assert_eq!(
+///     capa(b"+OK Capability list follows\r\nTOP\r\nSASL CRAM-MD5 KERBEROS_V4\r\n.\r\n").unwrap(),
+///     Capa {
+///         status_indicator: StatusIndicator::OK,
+///         capabilities: vec![
+///             Capability::Top,
+///             Capability::Sasl(vec![b"CRAM-MD5", b"KERBEROS_V4"]),
+///         ],
+///         information: b"Capability list follows"
+///     }
+/// )
+/// ```
+// ################################################################################
+pub fn capa(s: &[u8]) -> Option<Capa<'_>> {
+    match capa_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn capa_parser(s: &[u8]) -> IResult<&[u8], Capa<'_>> {
+    alt((
+        capa_multi_line_parser,
+        map(one_line_response_two_parts_parser::<OneLineTwoParts>, |x| {
+            Capa {
+                status_indicator: x.left,
+                capabilities: vec![],
+                information: x.right,
+            }
+        }),
+    ))(s)
+}
+
+fn capa_multi_line_parser(s: &[u8]) -> IResult<&[u8], Capa<'_>> {
+    map(
+        tuple((
+            map(tag_no_case(b"+OK"), |_| StatusIndicator::OK),
+            alt((
+                preceded(tag(b" "), take_until_crlf_consume_crlf),
+                map(tag(b"\r\n"), |_| &b""[..]),
+            )),
+            many0(map(
+                verify(take_until_crlf_consume_crlf, |x: &[u8]| x != b"."),
+                capability,
+            )),
+            tag(b".\r\n"),
+        )),
+        |(si, information, capabilities, _)| Capa {
+            status_indicator: si,
+            capabilities,
+            information,
+        },
+    )(s)
+}
+
+/// Parses one capability line, keeping capabilities which are unknown
+/// or have unexpected parameters as `Capability::Unknown`.
+fn capability(line: &[u8]) -> Capability<'_> {
+    let mut words = line.split(|x| *x == b' ').filter(|x| !x.is_empty());
+    let name = words.next().unwrap_or_default();
+    let parameters: Vec<&[u8]> = words.collect();
+    let is_user = |x: &[u8]| x.eq_ignore_ascii_case(b"USER");
+    match (name.to_ascii_uppercase().as_slice(), parameters.as_slice()) {
+        (b"TOP", []) => Capability::Top,
+        (b"USER", []) => Capability::User,
+        (b"SASL", _) => Capability::Sasl(parameters),
+        (b"RESP-CODES", []) => Capability::RespCodes,
+        (b"LOGIN-DELAY", [delay]) if is_number(delay) => Capability::LoginDelay {
+            delay: parse_u8_slice_to_usize_or_0(delay),
+            per_user: false,
+        },
+        (b"LOGIN-DELAY", [delay, user]) if is_number(delay) && is_user(user) => {
+            Capability::LoginDelay {
+                delay: parse_u8_slice_to_usize_or_0(delay),
+                per_user: true,
+            }
+        }
+        (b"PIPELINING", []) => Capability::Pipelining,
+        (b"EXPIRE", [expire]) if is_expire(expire) => Capability::Expire {
+            expire: expire_policy(expire),
+            per_user: false,
+        },
+        (b"EXPIRE", [expire, user]) if is_expire(expire) && is_user(user) => Capability::Expire {
+            expire: expire_policy(expire),
+            per_user: true,
+        },
+        (b"UIDL", []) => Capability::Uidl,
+        (b"IMPLEMENTATION", [_, ..]) => {
+            // `name` is a part of `line`, the text follows it
+            let end = name.as_ptr() as usize - line.as_ptr() as usize + name.len();
+            Capability::Implementation(line[end..].trim_ascii())
+        }
+        (b"STLS", []) => Capability::Stls,
+        (b"UTF8", []) => Capability::Utf8 { user: false },
+        (b"UTF8", [user]) if is_user(user) => Capability::Utf8 { user: true },
+        (b"LANG", []) => Capability::Lang,
+        _ => Capability::Unknown { name, parameters },
+    }
+}
+
+// ################################################################################
+/// LANG
+///
+/// **Restrictions** none
+///
+/// **Discussion**
+///
+/// Without an argument, an +OK response is followed by the
+/// languages the server supports, one per line: a language tag, a
+/// space and a description of the language in UTF-8.  The list
+/// is terminated by a line containing a termination octet (".")
+/// and a CRLF pair.
+///
+/// With an argument, the response is one line; an +OK response
+/// is already in the language selected, see [`lang_selected`].
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK -ERR
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::common::StatusIndicator;
+/// use rfc1939::extension::response::lang;
+/// use rfc1939::types::response::{Lang, Language};
+/// // C: LANG
+/// // S: +OK Language listing follows:
+/// // S: en English
+/// // S: es Español
+/// // S: .
+/// assert_eq!(
+///     lang("+OK Language listing follows:\r\nen English\r\nes Español\r\n.\r\n".as_bytes())
+///         .unwrap(),
+///     Lang {
+///         status_indicator: StatusIndicator::OK,
+///         languages: vec![
+///             Language { tag: b"en", description: b"English" },
+///             Language { tag: b"es", description: "Español".as_bytes() },
+///         ],
+///         information: b"Language listing follows:"
+///     }
+/// )
+/// ```
+// ################################################################################
+pub fn lang(s: &[u8]) -> Option<Lang<'_>> {
+    match lang_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn lang_parser(s: &[u8]) -> IResult<&[u8], Lang<'_>> {
+    alt((lang_multi_line_parser, lang_selected_parser))(s)
+}
+
+fn lang_multi_line_parser(s: &[u8]) -> IResult<&[u8], Lang<'_>> {
+    map(
+        tuple((
+            map(tag_no_case(b"+OK"), |_| StatusIndicator::OK),
+            alt((
+                preceded(tag(b" "), take_until_crlf_consume_crlf),
+                map(tag(b"\r\n"), |_| &b""[..]),
+            )),
+            many0(map(
+                verify(take_until_crlf_consume_crlf, |x: &[u8]| x != b"."),
+                language,
+            )),
+            tag(b".\r\n"),
+        )),
+        |(si, information, languages, _)| Lang {
+            status_indicator: si,
+            languages,
+            information,
+        },
+    )(s)
+}
+
+fn language(line: &[u8]) -> Language<'_> {
+    match line.iter().position(|x| *x == b' ') {
+        Some(i) => Language {
+            tag: &line[..i],
+            description: &line[i + 1..],
+        },
+        None => Language {
+            tag: line,
+            description: &[],
+        },
+    }
+}
+
+// ################################################################################
+/// LANG *lang-range*
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK -ERR
+///
+/// **Examples**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;C: LANG fr
+/// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK Les réponses sont maintenant en français
+// ################################################################################
+pub fn lang_selected(s: &[u8]) -> Option<Lang<'_>> {
+    match lang_selected_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn lang_selected_parser(s: &[u8]) -> IResult<&[u8], Lang<'_>> {
+    map(one_line_response_two_parts_parser::<OneLineTwoParts>, |x| {
+        Lang {
+            status_indicator: x.left,
+            languages: vec![],
+            information: x.right,
+        }
+    })(s)
+}
+
+// ################################################################################
+/// The response to any other command
+///
+/// **Discussion**
+///
+/// Without knowing the command, a response can not be told to be
+/// single-line or multi-line from its status line alone.  An -ERR
+/// response is always single-line.  An +OK response is taken to be
+/// multi-line when the line following it is neither empty nor another
+/// status line, and a termination line is found; otherwise it is
+/// single-line.
+///
+/// The body of a multi-line response is kept byte-stuffed, so that it
+/// can be forwarded as is.
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK -ERR
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::common::StatusIndicator;
+/// use rfc1939::extension::response::unknown;
+/// use rfc1939::types::response::Unknown;
+/// // C: XSENDER 1
+/// // S: +OK <sender@example.com>
+/// assert_eq!(
+///     unknown(b"+OK <sender@example.com>\r\n").unwrap(),
+///     Unknown {
+///         status_indicator: StatusIndicator::OK,
+///         body: None,
+///         information: b"<sender@example.com>"
+///     }
+/// );
+/// // C: XLIST
+/// // S: +OK
+/// // S: a
+/// // S: ..b
+/// // S: .
+/// assert_eq!(
+///     unknown(b"+OK\r\na\r\n..b\r\n.\r\n").unwrap(),
+///     Unknown {
+///         status_indicator: StatusIndicator::OK,
+///         body: Some(b"a\r\n..b"),
+///         information: b""
+///     }
+/// );
+/// ```
+// ################################################################################
+pub fn unknown(s: &[u8]) -> Option<Unknown<'_>> {
+    match unknown_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn unknown_parser(s: &[u8]) -> IResult<&[u8], Unknown<'_>> {
+    let (rest, response) = unknown_one_line_parser(s)?;
+    if response.status_indicator == StatusIndicator::OK && !rest.is_empty() && !is_status_line(rest)
+    {
+        if let Ok(x) = unknown_multi_line_parser(s) {
+            return Ok(x);
+        }
+    }
+    Ok((rest, response))
+}
+
+/// Parses the response to any other command as a single-line response.
+pub(crate) fn unknown_one_line_parser(s: &[u8]) -> IResult<&[u8], Unknown<'_>> {
+    map(one_line_response_two_parts_parser::<OneLineTwoParts>, |x| {
+        Unknown {
+            status_indicator: x.left,
+            body: None,
+            information: x.right,
+        }
+    })(s)
+}
+
+/// Parses the response to any other command as a multi-line response,
+/// falling back to a single-line response for -ERR.
+pub(crate) fn unknown_multi_line_parser(s: &[u8]) -> IResult<&[u8], Unknown<'_>> {
+    alt((
+        map(
+            tuple((
+                map(tag_no_case(b"+OK"), |_| StatusIndicator::OK),
+                alt((
+                    preceded(tag(b" "), take_until_crlf_consume_crlf),
+                    map(tag(b"\r\n"), |_| &b""[..]),
+                )),
+                alt((
+                    map(tag(b".\r\n"), |_| &b""[..]),
+                    terminated(take_until("\r\n.\r\n"), tag(b"\r\n.\r\n")),
+                )),
+            )),
+            |(si, information, body)| Unknown {
+                status_indicator: si,
+                body: Some(body),
+                information,
+            },
+        ),
+        verify(unknown_one_line_parser, |x| {
+            x.status_indicator == StatusIndicator::ERR
+        }),
+    ))(s)
+}
+
+fn is_status_line(s: &[u8]) -> bool {
+    let starts_with =
+        |prefix: &[u8]| s.len() >= prefix.len() && s[..prefix.len()].eq_ignore_ascii_case(prefix);
+    starts_with(b"+OK") || starts_with(b"-ERR") || starts_with(b"+ ") || starts_with(b"+\r\n")
+}
+
+fn is_number(s: &[u8]) -> bool {
+    !s.is_empty() && s.iter().all(u8::is_ascii_digit)
+}
+
+fn is_expire(s: &[u8]) -> bool {
+    is_number(s) || s.eq_ignore_ascii_case(b"NEVER")
+}
+
+fn expire_policy(s: &[u8]) -> Expire {
+    if s.eq_ignore_ascii_case(b"NEVER") {
+        Expire::Never
+    } else {
+        Expire::Days(parse_u8_slice_to_usize_or_0(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capa() {
+        assert_eq!(
+            capa(
+                b"+OK\r\nTOP\r\nUSER\r\nSASL PLAIN SCRAM-SHA-256\r\nRESP-CODES\r\n\
+                  LOGIN-DELAY 900\r\nLOGIN-DELAY 0 USER\r\nPIPELINING\r\nEXPIRE 60\r\n\
+                  EXPIRE NEVER USER\r\nUIDL\r\nIMPLEMENTATION Shlemazle Plotz v302\r\n\
+                  STLS\r\nUTF8 USER\r\nLANG\r\nX-ID\r\nEXPIRE soon\r\n.\r\n"
+            )
+            .unwrap(),
+            Capa {
+                status_indicator: StatusIndicator::OK,
+                capabilities: vec![
+                    Capability::Top,
+                    Capability::User,
+                    Capability::Sasl(vec![b"PLAIN", b"SCRAM-SHA-256"]),
+                    Capability::RespCodes,
+                    Capability::LoginDelay {
+                        delay: 900,
+                        per_user: false
+                    },
+                    Capability::LoginDelay {
+                        delay: 0,
+                        per_user: true
+                    },
+                    Capability::Pipelining,
+                    Capability::Expire {
+                        expire: Expire::Days(60),
+                        per_user: false
+                    },
+                    Capability::Expire {
+                        expire: Expire::Never,
+                        per_user: true
+                    },
+                    Capability::Uidl,
+                    Capability::Implementation(b"Shlemazle Plotz v302"),
+                    Capability::Stls,
+                    Capability::Utf8 { user: true },
+                    Capability::Lang,
+                    Capability::Unknown {
+                        name: b"X-ID",
+                        parameters: vec![]
+                    },
+                    Capability::Unknown {
+                        name: b"EXPIRE",
+                        parameters: vec![b"soon"]
+                    },
+                ],
+                information: b""
+            }
+        );
+        assert_eq!(
+            capability(b" IMPLEMENTATION  Shlemazle Plotz "),
+            Capability::Implementation(b"Shlemazle Plotz")
+        );
+    }
+
+    #[test]
+    fn test_lang() {
+        assert_eq!(
+            lang_parser(b"+OK\r\nen English\r\ni-default\r\n.\r\n+OK\r\n").unwrap(),
+            (
+                &b"+OK\r\n"[..],
+                Lang {
+                    status_indicator: StatusIndicator::OK,
+                    languages: vec![
+                        Language {
+                            tag: b"en",
+                            description: b"English"
+                        },
+                        Language {
+                            tag: b"i-default",
+                            description: b""
+                        },
+                    ],
+                    information: b""
+                }
+            )
+        );
+        assert_eq!(
+            lang(b"-ERR unknown command\r\n").unwrap().information,
+            b"unknown command"
+        );
+        assert_eq!(
+            lang_selected_parser(b"+OK Les r\xc3\xa9ponses\r\n.\r\n").unwrap(),
+            (
+                &b".\r\n"[..],
+                Lang {
+                    status_indicator: StatusIndicator::OK,
+                    languages: vec![],
+                    information: b"Les r\xc3\xa9ponses"
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_capa_parser() {
+        assert_eq!(
+            capa_parser(b"+OK\r\n.\r\n+OK\r\n").unwrap(),
+            (
+                &b"+OK\r\n"[..],
+                Capa {
+                    status_indicator: StatusIndicator::OK,
+                    capabilities: vec![],
+                    information: b""
+                }
+            )
+        );
+        assert_eq!(
+            capa_parser(b"-ERR unknown command\r\n").unwrap().1,
+            Capa {
+                status_indicator: StatusIndicator::ERR,
+                capabilities: vec![],
+                information: b"unknown command"
+            }
+        );
+    }
+
+    #[test]
+    fn test_unknown() {
+        assert_eq!(
+            unknown(b"-ERR no such message\r\nuseless\r\n.\r\n").unwrap(),
+            Unknown {
+                status_indicator: StatusIndicator::ERR,
+                body: None,
+                information: b"no such message"
+            }
+        );
+        assert_eq!(
+            unknown(b"+OK\r\n.\r\n").unwrap(),
+            Unknown {
+                status_indicator: StatusIndicator::OK,
+                body: Some(b""),
+                information: b""
+            }
+        );
+        assert_eq!(
+            unknown_parser(b"+OK 1\r\n+OK 2\r\n").unwrap(),
+            (
+                &b"+OK 2\r\n"[..],
+                Unknown {
+                    status_indicator: StatusIndicator::OK,
+                    body: None,
+                    information: b"1"
+                }
+            )
+        );
+        // No termination line, not a multi-line response
+        assert_eq!(
+            unknown_parser(b"+OK\r\nXSENDER 1\r\n").unwrap().0,
+            b"XSENDER 1\r\n"
+        );
+        assert_eq!(
+            unknown_multi_line_parser(b"+OK\r\n+OK\r\n.\r\n").unwrap(),
+            (
+                &b""[..],
+                Unknown {
+                    status_indicator: StatusIndicator::OK,
+                    body: Some(b"+OK"),
+                    information: b""
+                }
+            )
+        );
+        assert_eq!(unknown(b"* OK\r\n"), None);
+    }
+}
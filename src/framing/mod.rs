@@ -0,0 +1,170 @@
+//! Finding where a response ends in a stream of bytes, without parsing it.
+//!
+//! Whether a response is multi-line depends on the command it answers
+//! and on its status indicator.  The parsers accept a one line response
+//! in place of a multi-line one, so a response read from a connection
+//! is only handed to them once it is complete.
+use crate::extension::response::unknown_parser;
+use crate::types::command::Command;
+use crate::types::response::Shape;
+use std::borrow::Cow;
+
+/// The shape of a positive response to `command`
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::framing::shape;
+/// use rfc1939::types::command::{Command, List, Retr};
+/// use rfc1939::types::response::Shape;
+/// assert_eq!(shape(&Command::Retr(Retr { msg: 1 })), Shape::MultiLine);
+/// assert_eq!(shape(&Command::List(List { msg: Some(1) })), Shape::OneLine);
+/// ```
+pub fn shape(command: &Command) -> Shape {
+    match command {
+        Command::List(x) if x.msg.is_none() => Shape::MultiLine,
+        Command::Uidl(x) if x.msg.is_none() => Shape::MultiLine,
+        Command::Lang(x) if x.tag.is_none() => Shape::MultiLine,
+        Command::Retr(_) | Command::Top(_) | Command::Capa(_) => Shape::MultiLine,
+        Command::Custom(x) => x.shape,
+        #[cfg(feature = "legacy")]
+        Command::Xtnd(crate::types::command::Xtnd::Xlst { .. }) => Shape::MultiLine,
+        _ => Shape::OneLine,
+    }
+}
+
+/// The length of the response to `command` at the start of `s`,
+/// including its termination line, or None if it is not complete yet.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::framing::response_length;
+/// use rfc1939::types::command::{Command, Retr};
+/// let retr = Command::Retr(Retr { msg: 1 });
+/// assert_eq!(response_length(&retr, b"+OK\r\nSubject: a\r\n"), None);
+/// assert_eq!(response_length(&retr, b"+OK\r\nSubject: a\r\n.\r\n+OK\r\n"), Some(20));
+/// assert_eq!(response_length(&retr, b"-ERR no such message\r\n"), Some(22));
+/// ```
+pub fn response_length(command: &Command, s: &[u8]) -> Option<usize> {
+    frame_length(shape(command), s)
+}
+
+/// The length of the response to `command` at the start of `s`, as
+/// [`response_length`] finds it, except that whether the response to an
+/// unknown command is multi-line is guessed from the bytes following
+/// its status line.  For bytes recorded in full, e.g. of a session.
+pub(crate) fn guessed_length(command: &Command, s: &[u8]) -> Option<usize> {
+    match command {
+        Command::Unknown(_) => unknown_parser(s).ok().map(|(rest, _)| s.len() - rest.len()),
+        _ => response_length(command, s),
+    }
+}
+
+/// The length of a response of the given shape at the start of `s`,
+/// including its termination line, or None if it is not complete yet.
+pub fn frame_length(shape: Shape, s: &[u8]) -> Option<usize> {
+    let status_line = line_length(s)?;
+    let positive = s.len() >= 3 && s[..3].eq_ignore_ascii_case(b"+OK");
+    if !positive || shape == Shape::OneLine {
+        return Some(status_line);
+    }
+    body_length(&s[status_line..]).map(|x| status_line + x)
+}
+
+/// Removes the byte-stuffing of the body of a multi-line response, that
+/// is the "." added before each line which starts with ".".
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::framing::unstuff;
+/// assert_eq!(unstuff(b"..a\r\nb\r\n...\r\n"), &b".a\r\nb\r\n..\r\n"[..]);
+/// assert_eq!(unstuff(b"a.\r\nb"), &b"a.\r\nb"[..]);
+/// ```
+pub fn unstuff(s: &[u8]) -> Cow<'_, [u8]> {
+    let stuffed = s.starts_with(b".") || find(s, b"\r\n.").is_some();
+    if !stuffed {
+        return Cow::Borrowed(s);
+    }
+    let mut unstuffed = Vec::with_capacity(s.len());
+    let mut rest = s;
+    loop {
+        if let [b'.', line @ ..] = rest {
+            rest = line;
+        }
+        match line_length(rest) {
+            Some(length) => {
+                unstuffed.extend_from_slice(&rest[..length]);
+                rest = &rest[length..];
+            }
+            None => {
+                unstuffed.extend_from_slice(rest);
+                return Cow::Owned(unstuffed);
+            }
+        }
+    }
+}
+
+/// The length of the line at the start of `s`, including the CRLF pair
+pub(crate) fn line_length(s: &[u8]) -> Option<usize> {
+    find(s, b"\r\n").map(|x| x + 2)
+}
+
+/// The length of the lines of a multi-line response at the start of
+/// `s`, including the termination line
+pub(crate) fn body_length(s: &[u8]) -> Option<usize> {
+    if s.starts_with(b".\r\n") {
+        return Some(3);
+    }
+    find(s, b"\r\n.\r\n").map(|x| x + 5)
+}
+
+pub(crate) fn find(s: &[u8], needle: &[u8]) -> Option<usize> {
+    s.windows(needle.len()).position(|x| x == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::command::{Capa, Custom, Lang, Noop, Uidl};
+
+    #[test]
+    fn test_response_length() {
+        let uidl = Command::Uidl(Uidl { msg: None });
+        assert_eq!(response_length(&uidl, b""), None);
+        assert_eq!(response_length(&uidl, b"+OK"), None);
+        assert_eq!(response_length(&uidl, b"+OK\r\n"), None);
+        assert_eq!(response_length(&uidl, b"+OK\r\n.\r\n"), Some(8));
+        assert_eq!(response_length(&uidl, b"+OK\r\n1 a\r\n.\r"), None);
+        assert_eq!(response_length(&uidl, b"+OK\r\n1 a\r\n.\r\n"), Some(13));
+        assert_eq!(response_length(&uidl, b"+ok\r\n..\r\n.\r\n"), Some(12));
+        assert_eq!(response_length(&uidl, b"-ERR\r\n.\r\n"), Some(6));
+        assert_eq!(
+            response_length(&Command::Uidl(Uidl { msg: Some(1) }), b"+OK 1 a\r\n.\r\n"),
+            Some(9)
+        );
+        assert_eq!(
+            response_length(&Command::Noop(Noop), b"+OK\r\n.\r\n"),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn test_shape() {
+        assert_eq!(shape(&Command::Capa(Capa)), Shape::MultiLine);
+        assert_eq!(shape(&Command::Lang(Lang { tag: None })), Shape::MultiLine);
+        assert_eq!(
+            shape(&Command::Lang(Lang { tag: Some(b"fr") })),
+            Shape::OneLine
+        );
+        assert_eq!(
+            shape(&Command::Custom(Custom {
+                keyword: b"XLIST",
+                args: vec![],
+                shape: Shape::MultiLine
+            })),
+            Shape::MultiLine
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::common::take_until_crlf_consume_crlf;
+use crate::types::command::{Command, Last, Rpop, XmitMessage, Xtnd};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case, take_until, take_while1},
+    character::complete::digit1,
+    combinator::{map, opt},
+    sequence::{preceded, terminated, tuple},
+    IResult,
+};
+
+// ################################################################################
+/// LAST
+///
+/// **Arguments** none
+///
+/// **Restrictions**
+///
+/// may only be given in the TRANSACTION state
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: LAST
+/// use rfc1939::legacy::command::last;
+/// use rfc1939::types::command::Last;
+/// assert_eq!(last(b"LAST\r\n").unwrap(), Last)
+/// ```
+// ################################################################################
+pub fn last(s: &[u8]) -> Option<Last> {
+    match last_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn last_parser(s: &[u8]) -> IResult<&[u8], Last> {
+    map(terminated(tag_no_case(b"LAST"), tag(b"\r\n")), |_| Last)(s)
+}
+
+// ################################################################################
+/// RPOP *user*
+///
+/// **Arguments**
+///
+/// a user id on the client host (required)
+///
+/// **Restrictions**
+///
+/// may only be given in the AUTHORIZATION state immediately
+/// after a successful USER command
+///
+/// **Discussion**
+///
+/// Instead of a password, the server trusts the client host to
+/// have authenticated the user, provided the connection comes
+/// from a privileged port.
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: RPOP mrose
+/// use rfc1939::legacy::command::rpop;
+/// use rfc1939::types::command::Rpop;
+/// assert_eq!(rpop(b"RPOP mrose\r\n").unwrap(), Rpop { user: b"mrose" })
+/// ```
+// ################################################################################
+pub fn rpop(s: &[u8]) -> Option<Rpop<'_>> {
+    match rpop_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn rpop_parser(s: &[u8]) -> IResult<&[u8], Rpop<'_>> {
+    map(
+        preceded(tag_no_case(b"RPOP "), take_until_crlf_consume_crlf),
+        |user| Rpop { user },
+    )(s)
+}
+
+// ################################################################################
+/// XTND XMIT | XTND XLST *header* \[*msg*\]
+///
+/// **Arguments**
+///
+/// XMIT: none, the message to send follows a positive response
+///
+/// XLST: a header field name (required) and a message-number
+/// (optional)
+///
+/// **Restrictions**
+///
+/// may only be given in the TRANSACTION state
+///
+/// **Examples**
+///
+/// ```rust
+/// // C: XTND XLST Subject 1
+/// use rfc1939::legacy::command::xtnd;
+/// use rfc1939::types::command::Xtnd;
+/// assert_eq!(xtnd(b"XTND XMIT\r\n").unwrap(), Xtnd::Xmit);
+/// assert_eq!(
+///     xtnd(b"XTND XLST Subject 1\r\n").unwrap(),
+///     Xtnd::Xlst { header: b"Subject", msg: Some(1) }
+/// );
+/// ```
+// ################################################################################
+pub fn xtnd(s: &[u8]) -> Option<Xtnd<'_>> {
+    match xtnd_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn xtnd_parser(s: &[u8]) -> IResult<&[u8], Xtnd<'_>> {
+    preceded(
+        tag_no_case(b"XTND "),
+        alt((
+            map(terminated(tag_no_case(b"XMIT"), tag(b"\r\n")), |_| {
+                Xtnd::Xmit
+            }),
+            map(
+                tuple((
+                    tag_no_case(b"XLST "),
+                    take_while1(|x: u8| x.is_ascii_graphic() && x != b':'),
+                    opt(preceded(tag(b" "), digit1)),
+                    tag(b"\r\n"),
+                )),
+                |(_, header, msg, _): (_, &[u8], Option<&[u8]>, _)| Xtnd::Xlst {
+                    header,
+                    msg: msg.map(crate::common::parse_u8_slice_to_usize_or_0),
+                },
+            ),
+        )),
+    )(s)
+}
+
+// ################################################################################
+/// The message sent after a positive response to XTND XMIT
+///
+/// **Discussion**
+///
+/// The message is byte-stuffed and terminated by a line
+/// containing a termination octet (".") and a CRLF pair, as a
+/// multi-line response is.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::legacy::command::xmit_message;
+/// use rfc1939::types::command::XmitMessage;
+/// assert_eq!(
+///     xmit_message(b"Subject: hi\r\n\r\nhello\r\n.\r\n").unwrap(),
+///     XmitMessage { message: b"Subject: hi\r\n\r\nhello" }
+/// );
+/// ```
+// ################################################################################
+pub fn xmit_message(s: &[u8]) -> Option<XmitMessage<'_>> {
+    match xmit_message_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn xmit_message_parser(s: &[u8]) -> IResult<&[u8], XmitMessage<'_>> {
+    map(
+        alt((
+            map(tag(b".\r\n"), |_| &b""[..]),
+            terminated(take_until("\r\n.\r\n"), tag(b"\r\n.\r\n")),
+        )),
+        |message| XmitMessage { message },
+    )(s)
+}
+
+/// Parses any legacy command, for the dispatch of
+/// [`session::command`](crate::session::command).
+pub(crate) fn command_parser(s: &[u8]) -> IResult<&[u8], Command<'_>> {
+    alt((
+        map(last_parser, Command::Last),
+        map(rpop_parser, Command::Rpop),
+        map(xtnd_parser, Command::Xtnd),
+    ))(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last() {
+        assert_eq!(last(b"last\r\n").unwrap(), Last);
+        assert_eq!(last(b"LAST 1\r\n"), None);
+    }
+
+    #[test]
+    fn test_rpop() {
+        assert_eq!(rpop(b"RPOP mrose\r\n").unwrap(), Rpop { user: b"mrose" });
+        assert_eq!(rpop(b"RPOP\r\n"), None);
+    }
+
+    #[test]
+    fn test_xtnd() {
+        assert_eq!(xtnd(b"xtnd xmit\r\n").unwrap(), Xtnd::Xmit);
+        assert_eq!(
+            xtnd(b"XTND XLST From\r\n").unwrap(),
+            Xtnd::Xlst {
+                header: b"From",
+                msg: None
+            }
+        );
+        assert_eq!(xtnd(b"XTND XLST\r\n"), None);
+        assert_eq!(xtnd(b"XTND XMIT 1\r\n"), None);
+        assert_eq!(xtnd(b"XTND XSTATS\r\n"), None);
+    }
+
+    #[test]
+    fn test_xmit_message() {
+        assert_eq!(
+            xmit_message(b".\r\n").unwrap(),
+            XmitMessage { message: b"" }
+        );
+        assert_eq!(
+            xmit_message_parser(b"..dot\r\n.\r\nSTAT\r\n").unwrap(),
+            (&b"STAT\r\n"[..], XmitMessage { message: b"..dot" })
+        );
+        assert_eq!(xmit_message(b"unterminated\r\n"), None);
+    }
+}
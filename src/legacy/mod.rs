@@ -0,0 +1,9 @@
+//! Commands from obsolete POP3 specifications and vendor extensions,
+//! still seen in traffic of very old clients and servers: LAST (RFC 1460)
+//! and RPOP (RFC 1081), and the XTND XMIT and XTND XLST extensions of the
+//! Qualcomm popper.
+//!
+//! Only available with the `legacy` feature.
+
+pub mod command;
+pub mod response;
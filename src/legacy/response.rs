@@ -0,0 +1,277 @@
+use crate::common::{
+    one_line_response_two_parts_parser, parse_u8_slice_to_usize_or_0, take_until_crlf_consume_crlf,
+    StatusIndicator,
+};
+use crate::types::response::{Last, OneLineTwoParts, Rpop, Xtnd};
+use nom::{
+    branch::alt,
+    bytes::complete::{tag, tag_no_case},
+    character::complete::digit1,
+    combinator::{map, verify},
+    multi::many0,
+    sequence::{preceded, tuple},
+    IResult,
+};
+
+// ################################################################################
+/// LAST
+///
+/// **Restrictions**
+///
+/// may only be given in the TRANSACTION state
+///
+/// **Discussion**
+///
+/// The POP3 server issues a positive response with a line
+/// containing the highest message number which has been accessed
+/// (by RETR or TOP) in the maildrop, or 0 if none was.
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK nn
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::common::StatusIndicator;
+/// use rfc1939::legacy::response::last;
+/// use rfc1939::types::response::Last;
+/// // C: LAST
+/// // S: +OK 1
+/// assert_eq!(
+///     last(b"+OK 1\r\n").unwrap(),
+///     Last { status_indicator: StatusIndicator::OK, msg: 1, information: b"" }
+/// );
+/// ```
+// ################################################################################
+pub fn last(s: &[u8]) -> Option<Last<'_>> {
+    match last_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn last_parser(s: &[u8]) -> IResult<&[u8], Last<'_>> {
+    alt((
+        map(
+            tuple((
+                map(tag_no_case(b"+OK"), |_| StatusIndicator::OK),
+                tag(b" "),
+                digit1,
+                alt((
+                    preceded(tag(b" "), take_until_crlf_consume_crlf),
+                    map(tag(b"\r\n"), |_| &b""[..]),
+                )),
+            )),
+            |(si, _, msg, information): (StatusIndicator, _, &[u8], &[u8])| Last {
+                status_indicator: si,
+                msg: parse_u8_slice_to_usize_or_0(msg),
+                information,
+            },
+        ),
+        map(one_line_response_two_parts_parser::<OneLineTwoParts>, |x| {
+            Last {
+                status_indicator: x.left,
+                msg: 0,
+                information: x.right,
+            }
+        }),
+    ))(s)
+}
+
+// ################################################################################
+/// RPOP *user*
+///
+/// **Restrictions**
+///
+/// may only be given in the AUTHORIZATION state immediately
+/// after a successful USER command
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK maildrop locked and ready
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;-ERR permission denied
+// ################################################################################
+pub fn rpop(s: &[u8]) -> Option<Rpop<'_>> {
+    match rpop_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn rpop_parser(s: &[u8]) -> IResult<&[u8], Rpop<'_>> {
+    one_line_response_two_parts_parser::<Rpop>(s)
+}
+
+// ################################################################################
+/// XTND XMIT
+///
+/// **Discussion**
+///
+/// After a positive response the client sends the message to
+/// deliver; the server then answers again, with a one line
+/// response, once it accepted or refused the message.
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK Start sending message
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;-ERR
+// ################################################################################
+pub fn xmit(s: &[u8]) -> Option<Xtnd<'_>> {
+    match xmit_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn xmit_parser(s: &[u8]) -> IResult<&[u8], Xtnd<'_>> {
+    map(one_line_response_two_parts_parser::<OneLineTwoParts>, |x| {
+        Xtnd {
+            status_indicator: x.left,
+            headers: vec![],
+            information: x.right,
+        }
+    })(s)
+}
+
+// ################################################################################
+/// XTND XLST *header* \[*msg*\]
+///
+/// **Discussion**
+///
+/// An +OK response is followed by one line per message: the
+/// message-number, a space and the header line of the message
+/// with the field requested.  The list is terminated by a line
+/// containing a termination octet (".") and a CRLF pair.
+///
+/// **Possible Responses**
+///
+/// &nbsp;&nbsp;&nbsp;&nbsp;+OK -ERR
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::common::StatusIndicator;
+/// use rfc1939::legacy::response::xlst;
+/// use rfc1939::types::response::Xtnd;
+/// // C: XTND XLST Subject
+/// // S: +OK header list follows
+/// // S: 1 Subject: lunch
+/// // S: 2 Subject: re: lunch
+/// // S: .
+/// assert_eq!(
+///     xlst(b"+OK header list follows\r\n1 Subject: lunch\r\n2 Subject: re: lunch\r\n.\r\n")
+///         .unwrap(),
+///     Xtnd {
+///         status_indicator: StatusIndicator::OK,
+///         headers: vec![(1, b"Subject: lunch"), (2, b"Subject: re: lunch")],
+///         information: b"header list follows"
+///     }
+/// );
+/// ```
+// ################################################################################
+pub fn xlst(s: &[u8]) -> Option<Xtnd<'_>> {
+    match xlst_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn xlst_parser(s: &[u8]) -> IResult<&[u8], Xtnd<'_>> {
+    alt((
+        map(
+            tuple((
+                map(tag_no_case(b"+OK"), |_| StatusIndicator::OK),
+                alt((
+                    preceded(tag(b" "), take_until_crlf_consume_crlf),
+                    map(tag(b"\r\n"), |_| &b""[..]),
+                )),
+                many0(map(
+                    verify(take_until_crlf_consume_crlf, |x: &[u8]| x != b"."),
+                    header,
+                )),
+                tag(b".\r\n"),
+            )),
+            |(si, information, headers, _)| Xtnd {
+                status_indicator: si,
+                headers,
+                information,
+            },
+        ),
+        xmit_parser,
+    ))(s)
+}
+
+/// Splits a listing line into the message-number and the header line,
+/// message-number 0 if the line has none.
+fn header(line: &[u8]) -> (usize, &[u8]) {
+    match line.iter().position(|x| *x == b' ') {
+        Some(i) if i > 0 && line[..i].iter().all(u8::is_ascii_digit) => {
+            (parse_u8_slice_to_usize_or_0(&line[..i]), &line[i + 1..])
+        }
+        _ => (0, line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last() {
+        assert_eq!(
+            last(b"+OK 3 last message read\r\n").unwrap(),
+            Last {
+                status_indicator: StatusIndicator::OK,
+                msg: 3,
+                information: b"last message read"
+            }
+        );
+        assert_eq!(
+            last(b"-ERR not in TRANSACTION state\r\n").unwrap(),
+            Last {
+                status_indicator: StatusIndicator::ERR,
+                msg: 0,
+                information: b"not in TRANSACTION state"
+            }
+        );
+    }
+
+    #[test]
+    fn test_rpop() {
+        assert_eq!(
+            rpop(b"-ERR permission denied\r\n").unwrap(),
+            Rpop {
+                status_indicator: StatusIndicator::ERR,
+                information: b"permission denied"
+            }
+        );
+    }
+
+    #[test]
+    fn test_xlst() {
+        assert_eq!(
+            xlst_parser(b"+OK\r\n1 From: a\r\nbogus\r\n.\r\n+OK\r\n").unwrap(),
+            (
+                &b"+OK\r\n"[..],
+                Xtnd {
+                    status_indicator: StatusIndicator::OK,
+                    headers: vec![(1, b"From: a"), (0, b"bogus")],
+                    information: b""
+                }
+            )
+        );
+        assert_eq!(
+            xlst(b"-ERR no such header\r\n").unwrap().information,
+            b"no such header"
+        );
+        assert_eq!(
+            xmit_parser(b"+OK Start sending message\r\n.\r\n")
+                .unwrap()
+                .0,
+            b".\r\n"
+        );
+    }
+}
@@ -73,5 +73,22 @@ pub mod authorization;
 pub mod transaction;
 pub mod update;
 
+pub mod extension;
+#[cfg(feature = "legacy")]
+pub mod legacy;
+
 pub mod common;
 pub mod types;
+
+pub mod analysis;
+pub mod client;
+#[cfg(feature = "codec")]
+pub mod codec;
+pub mod framing;
+pub mod maildir;
+#[cfg(unix)]
+pub mod mbox;
+pub mod pipeline;
+pub mod sasl;
+pub mod session;
+pub mod sync;
@@ -0,0 +1,262 @@
+//! Delivering messages into a Maildir.
+//!
+//! Each message is written to a file of `tmp/` with a unique name,
+//! synced to disk, then renamed into `new/`, so that readers never see a
+//! partial message.  The name records the size of the file and the
+//! unique-id of the message on the POP3 server, as Dovecot does, e.g.
+//! "1700000000.M123456P4242Q1.host,S=24,U=whqtswO00WBw418f9t5JxYwZ".
+//!
+//! Messages are stored with LF line endings.  Used as the delivery of
+//! [`sync`](crate::sync::sync), which hands over unstuffed messages, a
+//! message is only deleted from the server once it is durably in the
+//! Maildir.
+//!
+//! **Examples**
+//!
+//! ```rust,no_run
+//! use rfc1939::client::Client;
+//! use rfc1939::maildir::Maildir;
+//! use rfc1939::sync::{sync, Policy, SeenSet};
+//! use std::net::TcpStream;
+//! use std::time::{Duration, SystemTime};
+//!
+//! let mut maildir = Maildir::create("Mail/inbox")?;
+//! let mut client = Client::new(TcpStream::connect("pop.example.com:110")?)?;
+//! client.login(b"mrose", b"tanstaaf")?;
+//! let mut seen = SeenSet::load("Mail/seen")?;
+//! // Delete each message once delivered
+//! let policy = Policy {
+//!     delete_after: Some(Duration::ZERO),
+//! };
+//! let report = sync(&mut client, &mut seen, &policy, SystemTime::now(), |uid, message| {
+//!     maildir.deliver(message, Some(uid)).map(|_| ())
+//! });
+//! seen.save("Mail/seen")?;
+//! report?;
+//! client.quit()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A Maildir, with its `tmp`, `new` and `cur` directories
+#[derive(Debug, Clone)]
+pub struct Maildir {
+    path: PathBuf,
+    hostname: String,
+    count: u64,
+}
+
+impl Maildir {
+    /// Opens the Maildir at `path`, creating its directories if needed.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Maildir> {
+        let path = path.as_ref().to_path_buf();
+        for dir in ["tmp", "new", "cur"] {
+            fs::create_dir_all(path.join(dir))?;
+        }
+        Ok(Maildir {
+            path,
+            hostname: hostname(),
+            count: 0,
+        })
+    }
+
+    /// Sets the hostname written in the names of the files, the name of
+    /// the system by default.
+    pub fn with_hostname(mut self, hostname: &str) -> Maildir {
+        self.hostname = hostname.to_string();
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes `message` to `new/`, with the unique-id `uid` if any, and
+    /// returns the path of its file once it is synced to disk.
+    pub fn deliver(&mut self, message: &[u8], uid: Option<&[u8]>) -> io::Result<PathBuf> {
+        let content = to_lf(message);
+        self.count += 1;
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut name = format!(
+            "{}.M{}P{}Q{}.{},S={}",
+            time.as_secs(),
+            time.subsec_micros(),
+            process::id(),
+            self.count,
+            escape_hostname(&self.hostname),
+            content.len()
+        );
+        if let Some(uid) = uid {
+            name.push_str(",U=");
+            name.push_str(&escape_uid(uid));
+        }
+
+        let tmp = self.path.join("tmp").join(&name);
+        let new = self.path.join("new").join(&name);
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&tmp)?;
+        let written = file.write_all(&content).and_then(|_| file.sync_all());
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp);
+            return Err(e);
+        }
+        fs::rename(&tmp, &new)?;
+        File::open(self.path.join("new"))?.sync_all()?;
+        Ok(new)
+    }
+}
+
+/// The name of the system, or "localhost" if unknown
+fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|x| x.trim().to_string())
+        .filter(|x| !x.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// Escapes "/" and ":", which cannot be in the name of a file.
+fn escape_hostname(hostname: &str) -> String {
+    hostname.replace('/', "\\057").replace(':', "\\072")
+}
+
+/// Escapes the bytes of `uid` other than letters, digits, ".", "_" and
+/// "-" as "%" and two hexadecimal digits.
+fn escape_uid(uid: &[u8]) -> String {
+    let mut escaped = String::with_capacity(uid.len());
+    for &x in uid {
+        if x.is_ascii_alphanumeric() || b"._-".contains(&x) {
+            escaped.push(x as char);
+        } else {
+            escaped.push_str(&format!("%{:02X}", x));
+        }
+    }
+    escaped
+}
+
+/// Replaces CRLF pairs with LF, and ends the last line.
+pub(crate) fn to_lf(message: &[u8]) -> Vec<u8> {
+    let mut content = Vec::with_capacity(message.len() + 1);
+    let mut rest = message;
+    while let Some(i) = rest.windows(2).position(|x| x == b"\r\n") {
+        content.extend_from_slice(&rest[..i]);
+        content.push(b'\n');
+        rest = &rest[i + 2..];
+    }
+    content.extend_from_slice(rest);
+    if !content.is_empty() && !content.ends_with(b"\n") {
+        content.push(b'\n');
+    }
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::Mock;
+    use crate::client::{Client, Error};
+    use crate::sync::{sync, Policy, SeenSet};
+    use std::time::Duration;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rfc1939-maildir-{}-{}", process::id(), name))
+    }
+
+    fn files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|x| x.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape_uid(b"a.b_c-1"), "a.b_c-1");
+        assert_eq!(escape_uid(b"a/b,c:%"), "a%2Fb%2Cc%3A%25");
+        assert_eq!(escape_hostname("a/b:c"), "a\\057b\\072c");
+        assert_eq!(to_lf(b"a\r\n\r\nb"), b"a\n\nb\n");
+        assert_eq!(to_lf(b"a\r\n"), b"a\n");
+        assert_eq!(to_lf(b""), b"");
+    }
+
+    #[test]
+    fn test_deliver() {
+        let path = temp_dir("deliver");
+        let mut maildir = Maildir::create(&path).unwrap().with_hostname("host:1");
+        let first = maildir
+            .deliver(b"Subject: a\r\n\r\n.b", Some(b"whqtswO00WBw418f9t5JxYwZ"))
+            .unwrap();
+        let second = maildir.deliver(b"Subject: a\r\n\r\n.b", None).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read(&first).unwrap(), b"Subject: a\n\n.b\n");
+        let name = first.file_name().unwrap().to_str().unwrap();
+        assert!(name.ends_with(".host\\0721,S=15,U=whqtswO00WBw418f9t5JxYwZ"));
+        assert!(second.to_str().unwrap().ends_with(",S=15"));
+        assert_eq!(files(&path.join("new")), {
+            let mut x = vec![first, second];
+            x.sort();
+            x
+        });
+        assert!(files(&path.join("tmp")).is_empty());
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sync_into_maildir() {
+        let path = temp_dir("sync");
+        let mut maildir = Maildir::create(&path).unwrap();
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n+OK\r\n1 a\r\n.\r\n+OK\r\nSubject: a\r\n\r\n..b\r\n.\r\n+OK\r\n",
+        ))
+        .unwrap();
+        let mut seen = SeenSet::new();
+        let policy = Policy {
+            delete_after: Some(Duration::ZERO),
+        };
+        sync(
+            &mut client,
+            &mut seen,
+            &policy,
+            SystemTime::now(),
+            |uid, message| maildir.deliver(message, Some(uid)).map(|_| ()),
+        )
+        .unwrap();
+        let new = files(&path.join("new"));
+        assert_eq!(new.len(), 1);
+        assert_eq!(fs::read(&new[0]).unwrap(), b"Subject: a\n\n.b\n");
+        assert_eq!(client.get_ref().output, b"UIDL\r\nRETR 1\r\nDELE 1\r\n");
+        fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sync_into_missing_maildir() {
+        let path = temp_dir("missing");
+        let mut maildir = Maildir::create(&path).unwrap();
+        fs::remove_dir_all(&path).unwrap();
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n+OK\r\n1 a\r\n.\r\n+OK\r\nSubject: a\r\n.\r\n",
+        ))
+        .unwrap();
+        let mut seen = SeenSet::new();
+        let policy = Policy {
+            delete_after: Some(Duration::ZERO),
+        };
+        let result = sync(
+            &mut client,
+            &mut seen,
+            &policy,
+            SystemTime::now(),
+            |uid, message| maildir.deliver(message, Some(uid)).map(|_| ()),
+        );
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert!(seen.is_empty());
+        assert_eq!(client.get_ref().output, b"UIDL\r\nRETR 1\r\n");
+    }
+}
@@ -0,0 +1,553 @@
+//! Appending messages to an mbox, and reading them back.
+//!
+//! The mbox is in the mboxrd format: each message follows a line
+//! "From sender date", and the lines of its body which start with
+//! "From ", after any number of ">", are quoted with one more ">".  The
+//! sender and date are taken from the Return-Path, From and Date headers.
+//! Messages are stored with LF line endings, and are followed by an
+//! empty line.
+//!
+//! An [`Mbox`] holds both a dotlock, the file "mbox.lock", and an fcntl
+//! lock on the mbox while it is open, as mail delivery agents and mail
+//! readers do.  Each message is synced to disk once appended, so that it
+//! can be deleted from the server.
+//!
+//! **Examples**
+//!
+//! ```rust,no_run
+//! use rfc1939::client::Client;
+//! use rfc1939::mbox::Mbox;
+//! use rfc1939::sync::{sync, Policy, SeenSet};
+//! use std::net::TcpStream;
+//! use std::time::SystemTime;
+//!
+//! let mut client = Client::new(TcpStream::connect("pop.example.com:110")?)?;
+//! client.login(b"mrose", b"tanstaaf")?;
+//! let mut seen = SeenSet::load("Mail/seen")?;
+//! let mut mbox = Mbox::open("Mail/inbox")?;
+//! let report = sync(
+//!     &mut client,
+//!     &mut seen,
+//!     &Policy::default(),
+//!     SystemTime::now(),
+//!     |_, message| mbox.append(message),
+//! );
+//! seen.save("Mail/seen")?;
+//! report?;
+//! client.quit()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use crate::maildir::to_lf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long [`Mbox::open`] waits for the locks
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How old a dotlock is taken as left by a crashed program, and removed
+const STALE_LOCK: Duration = Duration::from_secs(5 * 60);
+
+const RETRY: Duration = Duration::from_millis(100);
+
+/// An mbox, locked until dropped
+#[derive(Debug)]
+pub struct Mbox {
+    path: PathBuf,
+    lock: PathBuf,
+    file: File,
+}
+
+/// A message of an mbox
+///
+/// Vec<u8>, the "From " line without "From " and the line ending, e.g.
+/// "mrose@dbc.mtview.ca.us Mon Nov 20 19:12:08 1995"
+///
+/// Vec<u8>, the message, unquoted, with LF line endings
+#[derive(Debug, PartialEq, Clone)]
+pub struct Message {
+    pub from: Vec<u8>,
+    pub content: Vec<u8>,
+}
+
+impl Mbox {
+    /// Opens the mbox at `path`, creating it if needed, and locks it.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Mbox> {
+        Mbox::with_timeout(path, DEFAULT_LOCK_TIMEOUT)
+    }
+
+    /// Opens the mbox at `path`, creating it if needed, and locks it.
+    /// Fails with [`io::ErrorKind::TimedOut`] if a lock is still held
+    /// by another program after `timeout`.
+    pub fn with_timeout<P: AsRef<Path>>(path: P, timeout: Duration) -> io::Result<Mbox> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock = path.as_os_str().to_owned();
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+        let deadline = SystemTime::now() + timeout;
+        dotlock(&lock, deadline)?;
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path);
+        let locked = file.and_then(|file| fcntl_lock(&file, deadline).map(|_| file));
+        match locked {
+            Ok(file) => Ok(Mbox { path, lock, file }),
+            Err(e) => {
+                let _ = fs::remove_file(&lock);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `message`, unstuffed as [`sync`](crate::sync::sync)
+    /// hands it over, and syncs the mbox to disk.  The mbox is left as it was if the message cannot be
+    /// written.
+    pub fn append(&mut self, message: &[u8]) -> io::Result<()> {
+        let len = self.file.metadata()?.len();
+        let mut buf = vec![];
+        // Messages are separated by an empty line
+        let mut end = [0; 2];
+        if len >= 2 {
+            self.file.read_exact_at(&mut end, len - 2)?;
+        } else if len == 1 {
+            self.file.read_exact_at(&mut end[1..], 0)?;
+        }
+        match (len, end) {
+            (0, _) | (_, [b'\n', b'\n']) => {}
+            (_, [_, b'\n']) => buf.push(b'\n'),
+            _ => buf.extend_from_slice(b"\n\n"),
+        }
+        buf.extend_from_slice(b"From ");
+        buf.extend_from_slice(&from_line(message, SystemTime::now()));
+        buf.push(b'\n');
+        buf.extend_from_slice(&quote(&to_lf(message)));
+        buf.push(b'\n');
+
+        let written = self.file.write_all(&buf).and_then(|_| self.file.sync_all());
+        if let Err(e) = written {
+            let _ = self.file.set_len(len);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Reads all the messages of the mbox.
+    pub fn read(&self) -> io::Result<Vec<Message>> {
+        let len = self.file.metadata()?.len();
+        let mut buf = vec![0; len as usize];
+        self.file.read_exact_at(&mut buf, 0)?;
+        Ok(parse(&buf))
+    }
+}
+
+impl Drop for Mbox {
+    /// Removes the dotlock, the fcntl lock is released as the file is
+    /// closed.
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.lock);
+    }
+}
+
+/// Creates `lock`, waiting for it to be removed if it exists.
+fn dotlock(lock: &Path, deadline: SystemTime) -> io::Result<()> {
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(lock) {
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        let stale = fs::metadata(lock)
+            .and_then(|x| x.modified())
+            .ok()
+            .and_then(|x| x.elapsed().ok())
+            .is_some_and(|x| x >= STALE_LOCK);
+        if stale {
+            let _ = fs::remove_file(lock);
+            continue;
+        }
+        wait(deadline, "the mbox is dotlocked")?;
+    }
+}
+
+/// Takes an fcntl write lock on the whole of `file`.
+fn fcntl_lock(file: &File, deadline: SystemTime) -> io::Result<()> {
+    loop {
+        // SAFETY: flock is a plain C struct, for which zeroes are valid
+        let mut flock: libc::flock = unsafe { std::mem::zeroed() };
+        flock.l_type = libc::F_WRLCK as _;
+        flock.l_whence = libc::SEEK_SET as _;
+        // SAFETY: the descriptor is open for as long as `file`, and
+        // flock outlives the call
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &flock) } == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        match e.raw_os_error() {
+            Some(libc::EACCES) | Some(libc::EAGAIN) => wait(deadline, "the mbox is locked")?,
+            Some(libc::EINTR) => {}
+            _ => return Err(e),
+        }
+    }
+}
+
+/// Sleeps before retrying to lock, unless `deadline` is reached.
+fn wait(deadline: SystemTime, reason: &str) -> io::Result<()> {
+    if SystemTime::now() >= deadline {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, reason));
+    }
+    thread::sleep(RETRY);
+    Ok(())
+}
+
+/// Splits an mbox into its messages, and unquotes them.  Bytes before
+/// the first "From " line are ignored.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::mbox::{parse, Message};
+/// assert_eq!(
+///     parse(b"From a Thu Jan  1 00:00:00 1970\n>From b\n\n"),
+///     vec![Message {
+///         from: b"a Thu Jan  1 00:00:00 1970".to_vec(),
+///         content: b"From b\n".to_vec()
+///     }]
+/// );
+/// ```
+pub fn parse(mbox: &[u8]) -> Vec<Message> {
+    let mut messages = vec![];
+    let mut current: Option<Message> = None;
+    for line in mbox.split_inclusive(|x| *x == b'\n') {
+        if let Some(from) = line.strip_prefix(b"From ") {
+            messages.extend(current.take().map(end));
+            current = Some(Message {
+                from: from.strip_suffix(b"\n").unwrap_or(from).to_vec(),
+                content: vec![],
+            });
+        } else if let Some(message) = &mut current {
+            let quoted = line.starts_with(b">") && is_from(line);
+            message
+                .content
+                .extend_from_slice(if quoted { &line[1..] } else { line });
+        }
+    }
+    messages.extend(current.map(end));
+    messages
+}
+
+/// Removes the empty line which separates `message` from the next one.
+fn end(mut message: Message) -> Message {
+    if message.content.ends_with(b"\n") {
+        message.content.pop();
+    }
+    message
+}
+
+/// Whether `line` is "From " after any number of ">"
+fn is_from(line: &[u8]) -> bool {
+    let start = line.iter().position(|x| *x != b'>').unwrap_or(line.len());
+    line[start..].starts_with(b"From ")
+}
+
+/// Quotes the lines of `message` which are "From " after any number of
+/// ">".
+fn quote(message: &[u8]) -> Vec<u8> {
+    let mut quoted = Vec::with_capacity(message.len());
+    for line in message.split_inclusive(|x| *x == b'\n') {
+        if is_from(line) {
+            quoted.push(b'>');
+        }
+        quoted.extend_from_slice(line);
+    }
+    quoted
+}
+
+/// The "From " line of `message`, without "From " and the line ending:
+/// the address of its Return-Path or From header, or "MAILER-DAEMON",
+/// and the time of its Date header in UTC, or `received`.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::mbox::from_line;
+/// use std::time::UNIX_EPOCH;
+/// let message = b"From: Marshall Rose <mrose@dbc.mtview.ca.us>\r\n\
+///                 Date: Mon, 20 Nov 1995 19:12:08 -0500\r\n\r\nHello";
+/// assert_eq!(
+///     from_line(message, UNIX_EPOCH),
+///     b"mrose@dbc.mtview.ca.us Tue Nov 21 00:12:08 1995"
+/// );
+/// assert_eq!(from_line(b"", UNIX_EPOCH), b"MAILER-DAEMON Thu Jan  1 00:00:00 1970");
+/// ```
+pub fn from_line(message: &[u8], received: SystemTime) -> Vec<u8> {
+    let headers = headers(message);
+    let header = |name: &[u8]| {
+        headers
+            .iter()
+            .find(|(x, _)| x.eq_ignore_ascii_case(name))
+            .map(|(_, value)| &value[..])
+    };
+    let sender = header(b"Return-Path")
+        .and_then(address)
+        .or_else(|| header(b"From").and_then(address))
+        .unwrap_or(b"MAILER-DAEMON");
+    let time = header(b"Date").and_then(date).unwrap_or_else(|| {
+        match received.duration_since(UNIX_EPOCH) {
+            Ok(x) => x.as_secs() as i64,
+            Err(e) => -(e.duration().as_secs() as i64),
+        }
+    });
+    let mut line = sender.to_vec();
+    line.push(b' ');
+    line.extend_from_slice(asctime(time).as_bytes());
+    line
+}
+
+/// The headers of `message`, unfolded
+fn headers(message: &[u8]) -> Vec<(&[u8], Vec<u8>)> {
+    let mut headers: Vec<(&[u8], Vec<u8>)> = vec![];
+    for line in message.split(|x| *x == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            break;
+        }
+        if line[0] == b' ' || line[0] == b'\t' {
+            if let Some((_, value)) = headers.last_mut() {
+                value.extend_from_slice(line);
+            }
+        } else if let Some(colon) = line.iter().position(|x| *x == b':') {
+            headers.push((&line[..colon], line[colon + 1..].trim_ascii().to_vec()));
+        }
+    }
+    headers
+}
+
+/// The address in angle brackets of `value`, else its first word with
+/// an "@"
+fn address(value: &[u8]) -> Option<&[u8]> {
+    let address = match value.iter().position(|x| *x == b'<') {
+        Some(start) => {
+            let rest = &value[start + 1..];
+            &rest[..rest.iter().position(|x| *x == b'>')?]
+        }
+        None => value
+            .split(|x| x.is_ascii_whitespace())
+            .find(|x| x.contains(&b'@'))?,
+    };
+    let valid = !address.is_empty() && address.iter().all(|x| x.is_ascii_graphic());
+    valid.then_some(address)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+/// The seconds since the Unix epoch of an RFC 5322 date, e.g.
+/// "Mon, 20 Nov 1995 19:12:08 -0500"
+fn date(value: &[u8]) -> Option<i64> {
+    let value = std::str::from_utf8(value).ok()?;
+    let mut words = value
+        .split(|x: char| x.is_ascii_whitespace() || x == ',')
+        .filter(|x| !x.is_empty())
+        .peekable();
+    // The day of the week is optional
+    if words.peek()?.starts_with(|x: char| x.is_ascii_alphabetic()) {
+        words.next();
+    }
+    let day: i64 = words.next()?.parse().ok()?;
+    let month = words.next()?;
+    let month = MONTHS.iter().position(|x| x.eq_ignore_ascii_case(month))? as i64 + 1;
+    let year: i64 = match words.next()? {
+        x if x.len() == 2 => x
+            .parse::<i64>()
+            .ok()
+            .map(|x| x + if x < 50 { 2000 } else { 1900 })?,
+        x => x.parse().ok()?,
+    };
+    let mut time = words.next()?.split(':');
+    let hour: i64 = time.next()?.parse().ok()?;
+    let minute: i64 = time.next()?.parse().ok()?;
+    let second: i64 = time.next().map_or(Some(0), |x| x.parse().ok())?;
+    let offset = match words.next() {
+        Some(zone) if zone.len() == 5 && (zone.starts_with('+') || zone.starts_with('-')) => {
+            let hhmm: i64 = zone[1..].parse().ok()?;
+            let offset = (hhmm / 100 * 60 + hhmm % 100) * 60;
+            if zone.starts_with('-') {
+                -offset
+            } else {
+                offset
+            }
+        }
+        // GMT, UT, and the obsolete zones, taken as UTC
+        _ => 0,
+    };
+    let valid = (1..=9999).contains(&year)
+        && (1..=31).contains(&day)
+        && (0..24).contains(&hour)
+        && (0..60).contains(&minute)
+        && (0..=60).contains(&second);
+    valid.then(|| {
+        days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset
+    })
+}
+
+/// The date and time of `time`, in seconds since the Unix epoch, as
+/// formatted by asctime, e.g. "Thu Jan  1 00:00:00 1970"
+fn asctime(time: i64) -> String {
+    let days = time.div_euclid(86400);
+    let seconds = time.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{} {} {:2} {:02}:{:02}:{:02} {}",
+        DAYS[(days + 4).rem_euclid(7) as usize],
+        MONTHS[month as usize - 1],
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        year
+    )
+}
+
+/// The days since the Unix epoch of a date of the Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of the Gregorian calendar of the days since the Unix epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = (shifted_month + 2) % 12 + 1;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rfc1939-mbox-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(
+            quote(b"From a\n>From b\n>>From c\n> From d\nFrom\nx From e\n"),
+            b">From a\n>>From b\n>>>From c\n> From d\nFrom\nx From e\n"
+        );
+    }
+
+    #[test]
+    fn test_date() {
+        assert_eq!(date(b"Thu, 01 Jan 1970 00:00:00 +0000"), Some(0));
+        assert_eq!(date(b"1 Jan 70 01:00 +0100"), Some(0));
+        assert_eq!(date(b"Wed, 31 Dec 1969 23:59:59 GMT"), Some(-1));
+        assert_eq!(date(b"29 Feb 2024 12:00:00 -0130"), Some(1709213400));
+        assert_eq!(date(b"Mon, 20 Nov"), None);
+        assert_eq!(date(b"32 Jan 1970 00:00:00"), None);
+        assert_eq!(date(b"1 Jan 99999999999999999 00:00:00"), None);
+        assert_eq!(date(b"1 Jan 0 00:00:00"), None);
+        assert_eq!(date(b"1 Jan 1970 -9999999999999999:00:00"), None);
+        assert_eq!(date(b"31 Dec 9999 23:59:59 +0000"), Some(253402300799));
+        assert_eq!(asctime(1709213400), "Thu Feb 29 13:30:00 2024");
+        assert_eq!(asctime(-1), "Wed Dec 31 23:59:59 1969");
+        for days in [-719468, -1, 0, 11016, 19782, 2932896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn test_from_line() {
+        let message = b"Return-Path: <>\r\nFrom: mrose@dbc.mtview.ca.us\r\n (Marshall Rose)\r\n\
+                        Date: garbage\r\n\r\nFrom: body@example.com\r\n";
+        assert_eq!(
+            from_line(message, UNIX_EPOCH + Duration::from_secs(86400)),
+            b"mrose@dbc.mtview.ca.us Fri Jan  2 00:00:00 1970"
+        );
+        let message = b"Return-Path: <bounce@example.com>\nfrom: <a@example.com>\n\n";
+        assert_eq!(
+            from_line(message, UNIX_EPOCH),
+            b"bounce@example.com Thu Jan  1 00:00:00 1970"
+        );
+        let message = b"Date: 1 Jan 99999999999999999 00:00:00\r\n\r\n";
+        assert_eq!(
+            from_line(message, UNIX_EPOCH),
+            b"MAILER-DAEMON Thu Jan  1 00:00:00 1970"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let path = temp_path("round-trip");
+        let messages: [&[u8]; 3] = [
+            b"From: <a@example.com>\r\nDate: 1 Jan 1970 00:00:00 +0000\r\n\r\nFrom me\r\n>From you\r\n\r\n",
+            b"From: <b@example.com>\r\nDate: 2 Jan 1970 00:00:00 +0000\r\n\r\nno line ending",
+            b"",
+        ];
+        let mut mbox = Mbox::open(&path).unwrap();
+        for message in messages {
+            mbox.append(message).unwrap();
+        }
+        let read = mbox.read().unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[0].from, b"a@example.com Thu Jan  1 00:00:00 1970");
+        assert_eq!(read[1].from, b"b@example.com Fri Jan  2 00:00:00 1970");
+        assert!(read[2].from.starts_with(b"MAILER-DAEMON "));
+        for (message, read) in messages.iter().zip(&read) {
+            assert_eq!(read.content, to_lf(message));
+        }
+        drop(mbox);
+
+        let content = fs::read(&path).unwrap();
+        assert!(content.starts_with(
+            b"From a@example.com Thu Jan  1 00:00:00 1970\nFrom: <a@example.com>\n\
+              Date: 1 Jan 1970 00:00:00 +0000\n\n>From me\n>>From you\n\n\nFrom b"
+        ));
+        // Appended after a message missing the empty line
+        fs::write(&path, b"From x Thu Jan  1 00:00:00 1970\nx\n").unwrap();
+        Mbox::open(&path).unwrap().append(b"y").unwrap();
+        let read = parse(&fs::read(&path).unwrap());
+        assert_eq!(read[0].content, b"x\n");
+        assert_eq!(read[1].content, b"y\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dotlock() {
+        let path = temp_path("dotlock");
+        let mbox = Mbox::open(&path).unwrap();
+        assert!(temp_path("dotlock.lock").exists());
+        let locked = Mbox::with_timeout(&path, Duration::from_millis(200));
+        assert_eq!(locked.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        drop(mbox);
+        assert!(!temp_path("dotlock.lock").exists());
+        drop(Mbox::with_timeout(&path, Duration::ZERO).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+}
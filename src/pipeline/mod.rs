@@ -0,0 +1,312 @@
+//! Sending several commands at once, without waiting for each response
+//! (RFC 2449, PIPELINING).
+//!
+//! The commands of a pipeline are encoded into a single buffer, written
+//! at once, and their responses are parsed in the order the commands
+//! were sent.  A command whose response changes the state of the
+//! session (USER, PASS, APOP, AUTH, STLS and QUIT) ends a pipeline: the
+//! client has to know its result before sending anything else.
+//!
+//! A server which does not announce PIPELINING in its CAPA response is
+//! sent one command at a time.
+//!
+//! **Examples**
+//!
+//! ```rust
+//! use rfc1939::pipeline::{Pipeline, Responses};
+//! use rfc1939::types::command::{Command, Dele, Retr};
+//! use rfc1939::types::response::Response;
+//!
+//! let commands = vec![
+//!     Command::Retr(Retr { msg: 1 }),
+//!     Command::Dele(Dele { msg: 1 }),
+//! ];
+//! let pipelines = Pipeline::split(commands, true);
+//! assert_eq!(pipelines.len(), 1);
+//! assert_eq!(pipelines[0].to_bytes().unwrap(), b"RETR 1\r\nDELE 1\r\n");
+//!
+//! let Responses::Done(responses, rest) = pipelines[0]
+//!     .responses(b"+OK\r\nSubject: a\r\n.\r\n+OK message 1 deleted\r\n")
+//! else {
+//!     panic!()
+//! };
+//! assert!(matches!(responses[0], Response::Retr(_)));
+//! assert!(matches!(responses[1], Response::Dele(_)));
+//! assert_eq!(rest, b"");
+//! ```
+use crate::framing::guessed_length;
+use crate::session::response_parser;
+use crate::types::command::{self, Command, Encode};
+use crate::types::response::{Capa, Capability, Response};
+
+/// The responses to the commands of a pipeline, as far as they have
+/// been received
+#[derive(Debug, PartialEq)]
+pub enum Responses<'b> {
+    /// A response has not been received in full yet
+    Incomplete,
+    /// The response to the command at the given index was received in
+    /// full but does not parse
+    Malformed(usize),
+    /// Every response, in order, along with the bytes following them
+    Done(Vec<Response<'b>>, &'b [u8]),
+}
+
+/// Commands to be sent at once
+#[derive(Debug, PartialEq)]
+pub struct Pipeline<'a> {
+    commands: Vec<Command<'a>>,
+    pipelining: bool,
+}
+
+impl<'a> Pipeline<'a> {
+    /// An empty pipeline; it holds a single command unless the server
+    /// supports `pipelining`.
+    pub fn new(pipelining: bool) -> Pipeline<'a> {
+        Pipeline {
+            commands: vec![],
+            pipelining,
+        }
+    }
+
+    /// An empty pipeline for the server which sent `capa`.
+    pub fn for_capa(capa: &Capa) -> Pipeline<'a> {
+        Pipeline::new(capa.capabilities.contains(&Capability::Pipelining))
+    }
+
+    /// Splits `commands` into the pipelines which send them in order.
+    pub fn split(commands: Vec<Command<'a>>, pipelining: bool) -> Vec<Pipeline<'a>> {
+        let mut pipelines = vec![];
+        let mut pipeline = Pipeline::new(pipelining);
+        for command in commands {
+            if let Err(command) = pipeline.push(command) {
+                pipelines.push(pipeline);
+                pipeline = Pipeline::new(pipelining);
+                // An empty pipeline accepts any command
+                let _ = pipeline.push(command);
+            }
+        }
+        if !pipeline.commands.is_empty() {
+            pipelines.push(pipeline);
+        }
+        pipelines
+    }
+
+    /// Appends `command`, or gives it back if it has to wait for the
+    /// responses to the commands already in the pipeline.
+    pub fn push(&mut self, command: Command<'a>) -> Result<(), Command<'a>> {
+        if self.is_full() {
+            return Err(command);
+        }
+        self.commands.push(command);
+        Ok(())
+    }
+
+    /// Whether no other command may be appended
+    pub fn is_full(&self) -> bool {
+        match self.commands.last() {
+            Some(last) => !self.pipelining || ends_pipeline(last),
+            None => false,
+        }
+    }
+
+    pub fn commands(&self) -> &[Command<'a>] {
+        &self.commands
+    }
+
+    /// Appends the command lines to `buf`, to be written at once.
+    /// Nothing is appended if a command cannot be encoded.
+    pub fn encode(&self, buf: &mut Vec<u8>) -> Result<(), command::Error> {
+        buf.append(&mut self.to_bytes()?);
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, command::Error> {
+        let mut buf = vec![];
+        for command in &self.commands {
+            command.encode(&mut buf)?;
+        }
+        Ok(buf)
+    }
+
+    /// Parses the responses to the commands, in order, along with the
+    /// bytes following them.  Returns [`Responses::Incomplete`] until
+    /// every response has been received in full.  The response to an
+    /// unknown command is framed as [`Session`](crate::session::Session)
+    /// frames it, multi-line if the bytes following its status line look
+    /// like the lines of a multi-line response.
+    pub fn responses<'b>(&self, s: &'b [u8]) -> Responses<'b> {
+        let mut responses = vec![];
+        let mut rest = s;
+        for (i, command) in self.commands.iter().enumerate() {
+            let Some(length) = guessed_length(command, rest) else {
+                return Responses::Incomplete;
+            };
+            let (response, next) = rest.split_at(length);
+            match response_parser(command, response) {
+                Ok((_, x)) => responses.push(x),
+                Err(_) => return Responses::Malformed(i),
+            }
+            rest = next;
+        }
+        Responses::Done(responses, rest)
+    }
+}
+
+/// Whether the client has to wait for the response to `command` before
+/// sending another one
+pub fn ends_pipeline(command: &Command) -> bool {
+    match command {
+        Command::User(_)
+        | Command::Pass(_)
+        | Command::Apop(_)
+        | Command::Auth(_)
+        | Command::AuthResponse(_)
+        | Command::Stls(_)
+        | Command::Quit(_) => true,
+        #[cfg(feature = "legacy")]
+        Command::Rpop(_) | Command::Xtnd(crate::types::command::Xtnd::Xmit) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::StatusIndicator;
+    use crate::extension::response::capa;
+    use crate::types::command::{
+        Dele, List, Noop, Pass, Quit, Retr, Stat, Stls, Top, Unknown, User,
+    };
+
+    #[test]
+    fn test_split() {
+        let commands = || {
+            vec![
+                Command::User(User { name: b"mrose" }),
+                Command::Pass(Pass {
+                    string: b"tanstaaf",
+                }),
+                Command::Stat(Stat),
+                Command::List(List { msg: None }),
+                Command::Retr(Retr { msg: 1 }),
+                Command::Dele(Dele { msg: 1 }),
+                Command::Quit(Quit),
+                Command::Noop(Noop),
+            ]
+        };
+        let pipelines = Pipeline::split(commands(), true);
+        assert_eq!(
+            pipelines
+                .iter()
+                .map(|x| x.to_bytes().unwrap())
+                .collect::<Vec<_>>(),
+            vec![
+                b"USER mrose\r\n".to_vec(),
+                b"PASS tanstaaf\r\n".to_vec(),
+                b"STAT\r\nLIST\r\nRETR 1\r\nDELE 1\r\nQUIT\r\n".to_vec(),
+                b"NOOP\r\n".to_vec(),
+            ]
+        );
+        assert_eq!(Pipeline::split(commands(), false).len(), 8);
+        assert!(Pipeline::split(vec![], true).is_empty());
+    }
+
+    #[test]
+    fn test_push() {
+        let mut pipeline = Pipeline::new(true);
+        assert_eq!(pipeline.push(Command::Noop(Noop)), Ok(()));
+        assert_eq!(pipeline.push(Command::Stls(Stls)), Ok(()));
+        assert!(pipeline.is_full());
+        assert_eq!(pipeline.push(Command::Noop(Noop)), Err(Command::Noop(Noop)));
+
+        let without = capa(b"+OK\r\nTOP\r\nUIDL\r\n.\r\n").unwrap();
+        let mut pipeline = Pipeline::for_capa(&without);
+        assert_eq!(pipeline.push(Command::Noop(Noop)), Ok(()));
+        assert!(pipeline.is_full());
+        let with = capa(b"+OK\r\nPIPELINING\r\n.\r\n").unwrap();
+        assert!(Pipeline::for_capa(&with).pipelining);
+    }
+
+    #[test]
+    fn test_responses() {
+        let pipeline = &Pipeline::split(
+            vec![
+                Command::Stat(Stat),
+                Command::List(List { msg: None }),
+                Command::Top(Top { msg: 9, n: 0 }),
+                Command::Retr(Retr { msg: 1 }),
+                Command::Dele(Dele { msg: 1 }),
+            ],
+            true,
+        )[0];
+        let s = b"+OK 1 120\r\n+OK\r\n1 120\r\n.\r\n-ERR no such message\r\n\
+                  +OK\r\nSubject: a\r\n\r\n..\r\n.\r\n+OK\r\n+OK";
+        for i in 0..s.len() - 3 {
+            assert_eq!(pipeline.responses(&s[..i]), Responses::Incomplete);
+        }
+        let Responses::Done(responses, rest) = pipeline.responses(s) else {
+            panic!()
+        };
+        assert_eq!(rest, b"+OK");
+        assert!(matches!(responses[0], Response::Stat(_)));
+        let Response::List(list) = &responses[1] else {
+            panic!()
+        };
+        assert_eq!(list.informations, vec![(1, 120)]);
+        assert_eq!(responses[2].status_indicator(), Some(&StatusIndicator::ERR));
+        let Response::Retr(retr) = &responses[3] else {
+            panic!()
+        };
+        assert_eq!(retr.message, Some(&b"Subject: a\r\n\r\n.."[..]));
+        assert!(responses[4].is_ok());
+    }
+
+    #[test]
+    fn test_responses_malformed() {
+        let pipeline = &Pipeline::split(
+            vec![
+                Command::Noop(Noop),
+                Command::Stat(Stat),
+                Command::Dele(Dele { msg: 1 }),
+            ],
+            true,
+        )[0];
+        // The response to STAT is a whole line, without a status indicator
+        let s = b"+OK\r\n* 1 120\r\n+OK message 1 deleted\r\n";
+        assert_eq!(pipeline.responses(s), Responses::Malformed(1));
+        assert_eq!(pipeline.responses(&s[..10]), Responses::Incomplete);
+    }
+
+    #[test]
+    fn test_responses_unknown() {
+        let pipeline = &Pipeline::split(
+            vec![
+                Command::Unknown(Unknown {
+                    keyword: b"XLST",
+                    args: vec![],
+                }),
+                Command::Unknown(Unknown {
+                    keyword: b"XNOP",
+                    args: vec![],
+                }),
+                Command::Noop(Noop),
+            ],
+            true,
+        )[0];
+        let s = b"+OK\r\na\r\n.\r\n+OK\r\n+OK\r\n";
+        let Responses::Done(responses, rest) = pipeline.responses(s) else {
+            panic!()
+        };
+        assert_eq!(rest, b"");
+        let Response::Unknown(xlst) = &responses[0] else {
+            panic!()
+        };
+        assert_eq!(xlst.body, Some(&b"a"[..]));
+        let Response::Unknown(xnop) = &responses[1] else {
+            panic!()
+        };
+        assert_eq!(xnop.body, None);
+        assert!(matches!(responses[2], Response::Noop(_)));
+    }
+}
@@ -0,0 +1,235 @@
+//! The CRAM-MD5 mechanism (RFC 2195).
+//!
+//! The server sends a unique challenge, the client answers with its
+//! username and the keyed MD5 digest (RFC 2104) of the challenge, using
+//! the shared secret as the key:
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;response = user SP hex(HMAC-MD5(secret, challenge))
+//!
+//! The username and the secret are prepared with SASLprep.
+use super::saslprep::saslprep;
+use super::{constant_time_eq, random_nonce, Error, Mechanism};
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Computes the lowercase hexadecimal HMAC-MD5 digest of `challenge`.
+pub fn digest(secret: &[u8], challenge: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Md5>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(challenge);
+    hex(&mac.finalize().into_bytes())
+}
+
+fn hex(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|x| format!("{:02x}", x).into_bytes())
+        .collect()
+}
+
+/// The client side of CRAM-MD5
+#[derive(Debug)]
+pub struct CramMd5<'a> {
+    pub username: &'a [u8],
+    pub secret: &'a [u8],
+}
+
+impl<'a> CramMd5<'a> {
+    pub fn new(username: &'a [u8], secret: &'a [u8]) -> CramMd5<'a> {
+        CramMd5 { username, secret }
+    }
+}
+
+impl Mechanism for CramMd5<'_> {
+    fn name(&self) -> &'static [u8] {
+        b"CRAM-MD5"
+    }
+
+    fn initial_response(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(None)
+    }
+
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>, Error> {
+        if challenge.is_empty() {
+            return Err(Error::Malformed);
+        }
+        let mut response = saslprep(self.username)?.as_bytes().to_vec();
+        response.push(b' ');
+        response.extend_from_slice(&digest(saslprep(self.secret)?.as_bytes(), challenge));
+        Ok(response)
+    }
+}
+
+/// The server side of CRAM-MD5
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::sasl::cram_md5::Server;
+/// // S: + PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+
+/// // C: dGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw
+/// let server = Server::with_challenge(b"<1896.697170952@postoffice.reston.mci.net>".to_vec());
+/// let username = server
+///     .verify(b"tim b913a602c7eda7a495b4e6e7334d3890", |_| Some(b"tanstaaftanstaaf".to_vec()))
+///     .unwrap();
+/// assert_eq!(username, b"tim");
+/// ```
+#[derive(Debug)]
+pub struct Server {
+    challenge: Vec<u8>,
+}
+
+impl Server {
+    /// Generates a challenge in the form RFC 2195 suggests,
+    /// `<unique.timestamp@hostname>`, the unique part being a random
+    /// nonce.
+    pub fn new(hostname: &str) -> Server {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut challenge = b"<".to_vec();
+        challenge.extend_from_slice(&random_nonce());
+        challenge.extend_from_slice(format!(".{}@{}>", timestamp.as_secs(), hostname).as_bytes());
+        Server::with_challenge(challenge)
+    }
+
+    pub fn with_challenge(challenge: Vec<u8>) -> Server {
+        Server { challenge }
+    }
+
+    pub fn challenge(&self) -> &[u8] {
+        &self.challenge
+    }
+
+    /// Verifies the client's response, looking up the shared secret of
+    /// the user with `secret`; the username and the secret are prepared
+    /// with SASLprep.  Returns the authenticated username.
+    pub fn verify<F>(&self, response: &[u8], secret: F) -> Result<Vec<u8>, Error>
+    where
+        F: FnOnce(&[u8]) -> Option<Vec<u8>>,
+    {
+        let i = response
+            .iter()
+            .rposition(|x| *x == b' ')
+            .ok_or(Error::Malformed)?;
+        let (username, response_digest) = (&response[..i], &response[i + 1..]);
+        if username.is_empty() || response_digest.len() != 32 {
+            return Err(Error::Malformed);
+        }
+        let username = saslprep(username)?.as_bytes().to_vec();
+        let secret = secret(&username).ok_or(Error::InvalidCredentials)?;
+        if constant_time_eq(
+            &digest(saslprep(&secret)?.as_bytes(), &self.challenge),
+            &response_digest.to_ascii_lowercase(),
+        ) {
+            Ok(username)
+        } else {
+            Err(Error::InvalidCredentials)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sasl::saslprep;
+    use crate::types::command::Encode;
+    use crate::types::response::AuthChallenge;
+
+    // RFC 2195, section 2
+    const CHALLENGE: &[u8] = b"<1896.697170952@postoffice.reston.mci.net>";
+    const RESPONSE: &[u8] = b"tim b913a602c7eda7a495b4e6e7334d3890";
+
+    #[test]
+    fn test_digest() {
+        assert_eq!(
+            digest(b"tanstaaftanstaaf", CHALLENGE),
+            b"b913a602c7eda7a495b4e6e7334d3890"
+        );
+    }
+
+    #[test]
+    fn test_cram_md5() {
+        let mut cram_md5 = CramMd5::new(b"tim", b"tanstaaftanstaaf");
+        assert_eq!(
+            cram_md5.auth().unwrap().to_bytes().unwrap(),
+            b"AUTH CRAM-MD5\r\n"
+        );
+        let challenge = AuthChallenge {
+            challenge: CHALLENGE.to_vec(),
+        };
+        assert_eq!(
+            cram_md5
+                .auth_response(&challenge)
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+            b"dGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\n"
+        );
+        assert_eq!(cram_md5.respond(b""), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_server() {
+        let server = Server::with_challenge(CHALLENGE.to_vec());
+        let secret = |x: &[u8]| (x == b"tim").then(|| b"tanstaaftanstaaf".to_vec());
+        assert_eq!(server.verify(RESPONSE, secret), Ok(b"tim".to_vec()));
+        assert_eq!(
+            server.verify(b"tim b913a602c7eda7a495b4e6e7334d3891", secret),
+            Err(Error::InvalidCredentials)
+        );
+        assert_eq!(
+            server.verify(b"tom b913a602c7eda7a495b4e6e7334d3890", secret),
+            Err(Error::InvalidCredentials)
+        );
+        assert_eq!(server.verify(b"tim", secret), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_saslprep() {
+        let mut cram_md5 =
+            CramMd5::new("t\u{ad}im".as_bytes(), "tanstaaf\u{a0}tanstaaf".as_bytes());
+        let response = cram_md5.respond(CHALLENGE).unwrap();
+        assert!(response.starts_with(b"tim "));
+        let server = Server::with_challenge(CHALLENGE.to_vec());
+        assert_eq!(
+            server.verify(&response, |_| Some(b"tanstaaf tanstaaf".to_vec())),
+            Ok(b"tim".to_vec())
+        );
+        let response = [
+            "t\u{ad}im ".as_bytes(),
+            &digest(b"tanstaaftanstaaf", CHALLENGE),
+        ]
+        .concat();
+        let secret = |x: &[u8]| (x == b"tim").then(|| b"tanstaaftanstaaf".to_vec());
+        assert_eq!(server.verify(&response, secret), Ok(b"tim".to_vec()));
+
+        let mut cram_md5 = CramMd5::new(b"tim", b"tanstaaf\x07");
+        assert_eq!(
+            cram_md5.respond(CHALLENGE),
+            Err(Error::SaslPrep(saslprep::Error::Prohibited {
+                character: '\u{7}',
+                position: 8,
+                table: saslprep::Table::AsciiControl
+            }))
+        );
+    }
+
+    #[test]
+    fn test_server_challenge() {
+        let a = Server::new("postoffice.example.net");
+        let b = Server::new("postoffice.example.net");
+        assert!(a.challenge().starts_with(b"<"));
+        // 18 random octets, in base64
+        assert_eq!(a.challenge()[25], b'.');
+        assert!(a.challenge().ends_with(b"@postoffice.example.net>"));
+        assert_ne!(a.challenge(), b.challenge());
+
+        let mut cram_md5 = CramMd5::new(b"tim", b"secret");
+        let response = cram_md5.respond(a.challenge()).unwrap();
+        assert_eq!(
+            a.verify(&response, |_| Some(b"secret".to_vec())),
+            Ok(b"tim".to_vec())
+        );
+    }
+}
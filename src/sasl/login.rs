@@ -0,0 +1,183 @@
+//! The LOGIN mechanism (draft-murchison-sasl-login).
+//!
+//! A de-facto standard: the server challenges with "Username:" and then
+//! "Password:", the client answers each challenge in turn.
+//!
+//! The username and the password are prepared with SASLprep.
+use super::saslprep::saslprep;
+use super::{constant_time_eq, Error, Mechanism};
+
+/// The challenges a server sends, in order
+pub const CHALLENGES: [&[u8]; 2] = [b"Username:", b"Password:"];
+
+/// The client side of LOGIN
+#[derive(Debug)]
+pub struct Login<'a> {
+    pub username: &'a [u8],
+    pub password: &'a [u8],
+    step: usize,
+}
+
+impl<'a> Login<'a> {
+    pub fn new(username: &'a [u8], password: &'a [u8]) -> Login<'a> {
+        Login {
+            username,
+            password,
+            step: 0,
+        }
+    }
+}
+
+impl Mechanism for Login<'_> {
+    fn name(&self) -> &'static [u8] {
+        b"LOGIN"
+    }
+
+    fn initial_response(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(None)
+    }
+
+    /// Answers the first challenge with the username and the second with
+    /// the password, whatever their text.
+    fn respond(&mut self, _challenge: &[u8]) -> Result<Vec<u8>, Error> {
+        self.step += 1;
+        match self.step {
+            1 => Ok(saslprep(self.username)?.as_bytes().to_vec()),
+            2 => Ok(saslprep(self.password)?.as_bytes().to_vec()),
+            _ => Err(Error::UnexpectedChallenge),
+        }
+    }
+}
+
+/// The server side of LOGIN
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::sasl::login::Server;
+/// let mut server = Server::default();
+/// assert_eq!(server.challenge(), Some(&b"Username:"[..]));
+/// server.respond(b"tim").unwrap();
+/// assert_eq!(server.challenge(), Some(&b"Password:"[..]));
+/// server.respond(b"tanstaaf").unwrap();
+/// assert_eq!(server.challenge(), None);
+/// assert_eq!(server.username(), Some(&b"tim"[..]));
+/// assert!(server.verify(b"tanstaaf"));
+/// ```
+#[derive(Debug, Default)]
+pub struct Server {
+    username: Option<Vec<u8>>,
+    password: Option<Vec<u8>>,
+}
+
+impl Server {
+    /// The next challenge to send, None once both responses were received
+    pub fn challenge(&self) -> Option<&'static [u8]> {
+        match (&self.username, &self.password) {
+            (None, _) => Some(CHALLENGES[0]),
+            (Some(_), None) => Some(CHALLENGES[1]),
+            _ => None,
+        }
+    }
+
+    /// Accepts the client's response to the last challenge. The
+    /// username is kept prepared with SASLprep.
+    pub fn respond(&mut self, response: &[u8]) -> Result<(), Error> {
+        if response.is_empty() || response.contains(&0) {
+            return Err(Error::Malformed);
+        }
+        match (&self.username, &self.password) {
+            (None, _) => self.username = Some(saslprep(response)?.as_bytes().to_vec()),
+            (Some(_), None) => self.password = Some(response.to_vec()),
+            _ => return Err(Error::Malformed),
+        }
+        Ok(())
+    }
+
+    pub fn username(&self) -> Option<&[u8]> {
+        self.username.as_deref()
+    }
+
+    /// Whether the password received is `password` once both are
+    /// prepared with SASLprep, compared in constant time.
+    pub fn verify(&self, password: &[u8]) -> bool {
+        let Some(received) = &self.password else {
+            return false;
+        };
+        match (saslprep(received), saslprep(password)) {
+            (Ok(a), Ok(b)) => constant_time_eq(a.as_bytes(), b.as_bytes()),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sasl::saslprep;
+    use crate::types::command::{Auth, Encode};
+    use crate::types::response::AuthChallenge;
+
+    #[test]
+    fn test_login() {
+        let mut login = Login::new(b"tim", b"tanstaaf");
+        assert_eq!(
+            login.auth().unwrap(),
+            Auth {
+                mechanism: b"LOGIN",
+                initial_response: None
+            }
+        );
+        let challenge = AuthChallenge {
+            challenge: b"Username:".to_vec(),
+        };
+        assert_eq!(
+            login.auth_response(&challenge).unwrap().to_bytes().unwrap(),
+            b"dGlt\r\n"
+        );
+        assert_eq!(login.respond(b"Password:"), Ok(b"tanstaaf".to_vec()));
+        assert_eq!(
+            login.auth_response(&challenge),
+            Err(Error::UnexpectedChallenge)
+        );
+    }
+
+    #[test]
+    fn test_server() {
+        let mut server = Server::default();
+        assert_eq!(server.respond(b""), Err(Error::Malformed));
+        server.respond(b"tim").unwrap();
+        assert!(!server.verify(b"tanstaaf"));
+        server.respond(b"tanstaaf").unwrap();
+        assert!(server.verify(b"tanstaaf"));
+        assert!(!server.verify(b"tanstaaftanstaaf"));
+        assert_eq!(server.respond(b"more"), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_saslprep() {
+        let mut login = Login::new("t\u{ad}im".as_bytes(), "I\u{ad}X\u{a0}\u{2168}".as_bytes());
+        assert_eq!(login.respond(b"Username:"), Ok(b"tim".to_vec()));
+        assert_eq!(login.respond(b"Password:"), Ok(b"IX IX".to_vec()));
+
+        let mut login = Login::new(b"tim\x07", b"tanstaaf");
+        assert_eq!(
+            login.respond(b"Username:"),
+            Err(Error::SaslPrep(saslprep::Error::Prohibited {
+                character: '\u{7}',
+                position: 3,
+                table: saslprep::Table::AsciiControl
+            }))
+        );
+
+        let mut server = Server::default();
+        server.respond("t\u{ad}im".as_bytes()).unwrap();
+        server.respond(b"IX IX").unwrap();
+        assert_eq!(server.username(), Some(&b"tim"[..]));
+        assert!(server.verify("I\u{ad}X\u{a0}\u{2168}".as_bytes()));
+        assert!(matches!(
+            Server::default().respond(b"tim\x07"),
+            Err(Error::SaslPrep(_))
+        ));
+    }
+}
@@ -0,0 +1,92 @@
+//! SASL mechanisms (RFC 4422) for the AUTH command.
+//!
+//! Client sides implement [`Mechanism`], which produces the AUTH command
+//! and the responses to the server's challenges. Server sides decode and
+//! verify what the client sent.
+use crate::types::command::{Auth, AuthResponse};
+use crate::types::response::AuthChallenge;
+use std::fmt;
+
+pub mod cram_md5;
+pub mod login;
+pub mod oauth;
+pub mod plain;
+pub mod saslprep;
+pub mod scram;
+
+/// Errors of SASL mechanisms
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    /// A message does not follow the syntax of the mechanism
+    Malformed,
+    /// A challenge was received where the mechanism expected none
+    UnexpectedChallenge,
+    /// The client's credentials did not verify
+    InvalidCredentials,
+    /// The server failed to prove it knows the client's credentials
+    InvalidServerSignature,
+    /// The server reported an error in the exchange
+    Server(String),
+    /// A username or password was rejected by SASLprep
+    SaslPrep(saslprep::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Malformed => write!(f, "malformed SASL message"),
+            Error::UnexpectedChallenge => write!(f, "unexpected SASL challenge"),
+            Error::InvalidCredentials => write!(f, "invalid credentials"),
+            Error::InvalidServerSignature => write!(f, "invalid server signature"),
+            Error::Server(x) => write!(f, "server error: {}", x),
+            Error::SaslPrep(x) => write!(f, "SASLprep: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<saslprep::Error> for Error {
+    fn from(error: saslprep::Error) -> Self {
+        Error::SaslPrep(error)
+    }
+}
+
+/// The client side of a SASL mechanism
+pub trait Mechanism {
+    /// The name of the mechanism, as listed by the SASL capability
+    fn name(&self) -> &'static [u8];
+
+    /// The initial response, sent along with the AUTH command, if the
+    /// mechanism has one
+    fn initial_response(&mut self) -> Result<Option<Vec<u8>>, Error>;
+
+    /// The response to a challenge of the server
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>, Error>;
+
+    /// The AUTH command which starts the exchange
+    fn auth(&mut self) -> Result<Auth<'static>, Error> {
+        Ok(Auth {
+            mechanism: self.name(),
+            initial_response: self.initial_response()?,
+        })
+    }
+
+    /// The line answering a continuation line of the server
+    fn auth_response(&mut self, challenge: &AuthChallenge) -> Result<AuthResponse, Error> {
+        self.respond(&challenge.challenge).map(AuthResponse::Data)
+    }
+}
+
+/// Compares secrets in constant time.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Generates a printable nonce, without ",", from 18 random octets.
+pub(crate) fn random_nonce() -> Vec<u8> {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    let mut nonce = [0; 18];
+    getrandom::getrandom(&mut nonce).expect("the system's random number generator");
+    STANDARD.encode(nonce).into_bytes()
+}
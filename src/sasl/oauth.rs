@@ -0,0 +1,505 @@
+//! The XOAUTH2 and OAUTHBEARER (RFC 7628) mechanisms.
+//!
+//! The client sends an OAuth 2.0 bearer token in its initial response:
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;XOAUTH2 = "user=" user ^A "auth=Bearer " token ^A ^A
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;OAUTHBEARER = "n,a=" user "," ^A \["host=" host ^A\] \["port=" port ^A\] "auth=Bearer " token ^A ^A
+//!
+//! On failure the server sends a JSON error as a challenge, to which the
+//! client replies before the server ends the exchange with -ERR: with a
+//! single ^A for OAUTHBEARER (RFC 7628, section 3.2.3), with an empty
+//! response for XOAUTH2.
+use super::scram::saslname;
+use super::{Error, Mechanism};
+use nom::{
+    branch::alt,
+    bytes::complete::{is_not, tag, take_while1, take_while_m_n},
+    character::complete::multispace0,
+    combinator::{all_consuming, map_opt, map_res, value},
+    multi::{fold_many0, separated_list0},
+    sequence::{delimited, preceded, separated_pair, tuple},
+    IResult,
+};
+use std::str::from_utf8;
+
+/// The reply of OAUTHBEARER to an error challenge
+pub const ERROR_REPLY: &[u8] = b"\x01";
+
+/// ErrorChallenge
+///
+/// The JSON error a server sends when it rejects the token
+///
+/// String, the error code, e.g. "invalid_token", or an HTTP status for
+/// XOAUTH2
+///
+/// Option<String>, the authentication schemes the server supports
+///
+/// Option<String>, the scope the token needs
+///
+/// Option<String>, the URL of the OpenID Provider Configuration
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ErrorChallenge {
+    pub status: String,
+    pub schemes: Option<String>,
+    pub scope: Option<String>,
+    pub openid_configuration: Option<String>,
+}
+
+impl ErrorChallenge {
+    /// Decodes the challenge of a server. Members other than those of
+    /// ErrorChallenge are ignored.
+    ///
+    /// **Examples**
+    ///
+    /// ```rust
+    /// use rfc1939::sasl::oauth::ErrorChallenge;
+    /// let error = ErrorChallenge::decode(br#"{"status":"401","schemes":"Bearer"}"#).unwrap();
+    /// assert_eq!(error.status, "401");
+    /// assert_eq!(error.schemes.as_deref(), Some("Bearer"));
+    /// ```
+    pub fn decode(challenge: &[u8]) -> Result<ErrorChallenge, Error> {
+        let (_, members) =
+            all_consuming(json_object_parser)(challenge).map_err(|_| Error::Malformed)?;
+        let mut error = ErrorChallenge::default();
+        let mut status = None;
+        for (name, x) in members {
+            match name.as_str() {
+                "status" => status = Some(x),
+                "schemes" => error.schemes = Some(x),
+                "scope" => error.scope = Some(x),
+                "openid-configuration" => error.openid_configuration = Some(x),
+                _ => {}
+            }
+        }
+        error.status = status.ok_or(Error::Malformed)?;
+        Ok(error)
+    }
+}
+
+/// A JSON object whose values are strings, numbers, booleans or null.
+/// Values other than strings are kept as they were written.
+fn json_object_parser(s: &[u8]) -> IResult<&[u8], Vec<(String, String)>> {
+    delimited(
+        tuple((multispace0, tag("{"), multispace0)),
+        separated_list0(
+            tuple((multispace0, tag(","), multispace0)),
+            separated_pair(
+                json_string_parser,
+                tuple((multispace0, tag(":"), multispace0)),
+                alt((
+                    json_string_parser,
+                    map_res(
+                        take_while1(|x: u8| x.is_ascii_alphanumeric() || b"+-.".contains(&x)),
+                        |x| from_utf8(x).map(String::from),
+                    ),
+                )),
+            ),
+        ),
+        tuple((multispace0, tag("}"), multispace0)),
+    )(s)
+}
+
+fn json_string_parser(s: &[u8]) -> IResult<&[u8], String> {
+    delimited(
+        tag("\""),
+        fold_many0(
+            alt((
+                map_res(is_not("\"\\"), |x| from_utf8(x).map(String::from)),
+                preceded(
+                    tag("\\"),
+                    alt((
+                        value("\"".to_string(), tag("\"")),
+                        value("\\".to_string(), tag("\\")),
+                        value("/".to_string(), tag("/")),
+                        value("\u{8}".to_string(), tag("b")),
+                        value("\u{c}".to_string(), tag("f")),
+                        value("\n".to_string(), tag("n")),
+                        value("\r".to_string(), tag("r")),
+                        value("\t".to_string(), tag("t")),
+                        map_opt(
+                            preceded(
+                                tag("u"),
+                                take_while_m_n(4, 4, |x: u8| x.is_ascii_hexdigit()),
+                            ),
+                            |x| {
+                                let x = u32::from_str_radix(from_utf8(x).ok()?, 16).ok()?;
+                                char::from_u32(x).map(String::from)
+                            },
+                        ),
+                    )),
+                ),
+            )),
+            String::new,
+            |mut acc, x| {
+                acc.push_str(&x);
+                acc
+            },
+        ),
+        tag("\""),
+    )(s)
+}
+
+/// Answers an error challenge with `reply`, keeping the error.
+fn error_reply(
+    challenge: &[u8],
+    error: &mut Option<ErrorChallenge>,
+    reply: &[u8],
+) -> Result<Vec<u8>, Error> {
+    if error.is_some() {
+        return Err(Error::UnexpectedChallenge);
+    }
+    *error = Some(ErrorChallenge::decode(challenge)?);
+    Ok(reply.to_vec())
+}
+
+/// The fields of a client message, separated by ^A and ended by ^A ^A
+fn kvpairs(message: &[u8]) -> Result<impl Iterator<Item = &[u8]>, Error> {
+    let fields = message.strip_suffix(b"\x01\x01").ok_or(Error::Malformed)?;
+    Ok(fields.split(|x| *x == 1))
+}
+
+/// The token of the "auth" field among `fields`
+fn bearer_token<'a>(mut fields: impl Iterator<Item = &'a [u8]>) -> Result<&'a [u8], Error> {
+    fields
+        .find_map(|x| x.strip_prefix(b"auth=Bearer "))
+        .ok_or(Error::Malformed)
+}
+
+/// The client side of XOAUTH2
+#[derive(Debug)]
+pub struct XOauth2<'a> {
+    pub user: &'a [u8],
+    pub token: &'a [u8],
+    error: Option<ErrorChallenge>,
+}
+
+impl<'a> XOauth2<'a> {
+    pub fn new(user: &'a [u8], token: &'a [u8]) -> XOauth2<'a> {
+        XOauth2 {
+            user,
+            token,
+            error: None,
+        }
+    }
+
+    /// Encodes the message the client sends.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut message = b"user=".to_vec();
+        message.extend_from_slice(self.user);
+        message.extend_from_slice(b"\x01auth=Bearer ");
+        message.extend_from_slice(self.token);
+        message.extend_from_slice(b"\x01\x01");
+        message
+    }
+
+    /// Decodes the message the client sent.
+    ///
+    /// **Examples**
+    ///
+    /// ```rust
+    /// use rfc1939::sasl::oauth::XOauth2;
+    /// let xoauth2 = XOauth2::decode(b"user=mrose\x01auth=Bearer vF9dft4qmT\x01\x01").unwrap();
+    /// assert_eq!(xoauth2.user, b"mrose");
+    /// assert_eq!(xoauth2.token, b"vF9dft4qmT");
+    /// ```
+    pub fn decode(message: &'a [u8]) -> Result<XOauth2<'a>, Error> {
+        let mut fields = kvpairs(message)?;
+        let user = fields
+            .next()
+            .and_then(|x| x.strip_prefix(b"user="))
+            .ok_or(Error::Malformed)?;
+        let token = bearer_token(fields)?;
+        Ok(XOauth2::new(user, token))
+    }
+
+    /// The error the server sent, if it rejected the token
+    pub fn error(&self) -> Option<&ErrorChallenge> {
+        self.error.as_ref()
+    }
+}
+
+impl Mechanism for XOauth2<'_> {
+    fn name(&self) -> &'static [u8] {
+        b"XOAUTH2"
+    }
+
+    fn initial_response(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(Some(self.encode()))
+    }
+
+    /// Servers which do not support initial responses send an empty
+    /// challenge first, anything else is an error challenge, answered
+    /// with an empty response.
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>, Error> {
+        if challenge.is_empty() {
+            Ok(self.encode())
+        } else {
+            error_reply(challenge, &mut self.error, b"")
+        }
+    }
+}
+
+/// The client side of OAUTHBEARER
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::sasl::oauth::OAuthBearer;
+/// let bearer = OAuthBearer::new(b"user@example.com", b"vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==")
+///     .with_host(b"server.example.com", 110);
+/// assert!(bearer.encode().starts_with(b"n,a=user@example.com,\x01host=server.example.com\x01port=110\x01"));
+/// ```
+#[derive(Debug)]
+pub struct OAuthBearer<'a> {
+    /// The authorization identity, may be empty
+    pub user: &'a [u8],
+    pub token: &'a [u8],
+    pub host: Option<&'a [u8]>,
+    pub port: Option<u16>,
+    error: Option<ErrorChallenge>,
+}
+
+impl<'a> OAuthBearer<'a> {
+    pub fn new(user: &'a [u8], token: &'a [u8]) -> OAuthBearer<'a> {
+        OAuthBearer {
+            user,
+            token,
+            host: None,
+            port: None,
+            error: None,
+        }
+    }
+
+    /// Sets the server the client connects to, which RFC 7628 recommends
+    /// sending.
+    pub fn with_host(mut self, host: &'a [u8], port: u16) -> OAuthBearer<'a> {
+        self.host = Some(host);
+        self.port = Some(port);
+        self
+    }
+
+    /// Encodes the message the client sends.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut message = b"n,".to_vec();
+        if !self.user.is_empty() {
+            message.extend_from_slice(b"a=");
+            message.extend_from_slice(&saslname(self.user));
+        }
+        message.extend_from_slice(b",\x01");
+        if let Some(host) = self.host {
+            message.extend_from_slice(b"host=");
+            message.extend_from_slice(host);
+            message.push(1);
+        }
+        if let Some(port) = self.port {
+            message.extend_from_slice(format!("port={}\x01", port).as_bytes());
+        }
+        message.extend_from_slice(b"auth=Bearer ");
+        message.extend_from_slice(self.token);
+        message.extend_from_slice(b"\x01\x01");
+        message
+    }
+
+    /// Decodes the message the client sent.  The user is left as it was
+    /// sent, with "=" and "," escaped.
+    ///
+    /// **Examples**
+    ///
+    /// ```rust
+    /// use rfc1939::sasl::oauth::OAuthBearer;
+    /// let bearer = OAuthBearer::decode(b"n,a=mrose,\x01port=110\x01auth=Bearer vF9dft4qmT\x01\x01").unwrap();
+    /// assert_eq!(bearer.user, b"mrose");
+    /// assert_eq!(bearer.port, Some(110));
+    /// assert_eq!(bearer.token, b"vF9dft4qmT");
+    /// ```
+    pub fn decode(message: &'a [u8]) -> Result<OAuthBearer<'a>, Error> {
+        let mut fields = kvpairs(message)?;
+        let mut gs2_header = fields
+            .next()
+            .ok_or(Error::Malformed)?
+            .splitn(3, |x| *x == b',');
+        let user = match (gs2_header.next(), gs2_header.next(), gs2_header.next()) {
+            (Some(b"n" | b"y"), Some(b""), Some(b"")) => &b""[..],
+            (Some(b"n" | b"y"), Some(x), Some(b"")) => {
+                x.strip_prefix(b"a=").ok_or(Error::Malformed)?
+            }
+            _ => return Err(Error::Malformed),
+        };
+        let mut bearer = OAuthBearer::new(user, b"");
+        let mut token = None;
+        for field in fields {
+            if let Some(x) = field.strip_prefix(b"host=") {
+                bearer.host = Some(x);
+            } else if let Some(x) = field.strip_prefix(b"port=") {
+                let port = from_utf8(x).ok().and_then(|x| x.parse().ok());
+                bearer.port = Some(port.ok_or(Error::Malformed)?);
+            } else if let Some(x) = field.strip_prefix(b"auth=Bearer ") {
+                token = Some(x);
+            }
+        }
+        bearer.token = token.ok_or(Error::Malformed)?;
+        Ok(bearer)
+    }
+
+    /// The error the server sent, if it rejected the token
+    pub fn error(&self) -> Option<&ErrorChallenge> {
+        self.error.as_ref()
+    }
+}
+
+impl Mechanism for OAuthBearer<'_> {
+    fn name(&self) -> &'static [u8] {
+        b"OAUTHBEARER"
+    }
+
+    fn initial_response(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        Ok(Some(self.encode()))
+    }
+
+    /// Servers which do not support initial responses send an empty
+    /// challenge first, anything else is an error challenge.
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>, Error> {
+        if challenge.is_empty() {
+            Ok(self.encode())
+        } else {
+            error_reply(challenge, &mut self.error, ERROR_REPLY)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::Session;
+    use crate::types::command::Encode;
+    use crate::types::response::AuthChallenge;
+
+    #[test]
+    fn test_xoauth2() {
+        let mut xoauth2 = XOauth2::new(
+            b"someuser@example.com",
+            b"ya29.vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg",
+        );
+        assert_eq!(
+            xoauth2.auth().unwrap().to_bytes().unwrap(),
+            b"AUTH XOAUTH2 dXNlcj1zb21ldXNlckBleGFtcGxlLmNvbQFhdXRoPUJlYXJlciB5YTI5LnZGOWRmdDRxbVRjMk52YjNSbGNrQmhkSFJoZG1semRHRXVZMjl0Q2cBAQ==\r\n"
+        );
+        let challenge = AuthChallenge {
+            challenge: br#"{"status":"401","schemes":"Bearer","scope":"https://mail.google.com/"}"#
+                .to_vec(),
+        };
+        assert_eq!(
+            xoauth2
+                .auth_response(&challenge)
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+            b"\r\n"
+        );
+        assert_eq!(
+            xoauth2.error(),
+            Some(&ErrorChallenge {
+                status: "401".to_string(),
+                schemes: Some("Bearer".to_string()),
+                scope: Some("https://mail.google.com/".to_string()),
+                openid_configuration: None,
+            })
+        );
+        assert_eq!(
+            xoauth2.respond(&challenge.challenge),
+            Err(Error::UnexpectedChallenge)
+        );
+    }
+
+    #[test]
+    fn test_oauthbearer() {
+        // RFC 7628, section 4.1
+        let mut bearer = OAuthBearer::new(
+            b"user@example.com",
+            b"vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==",
+        )
+        .with_host(b"server.example.com", 143);
+        assert_eq!(
+            bearer.initial_response().unwrap().unwrap(),
+            b"n,a=user@example.com,\x01host=server.example.com\x01port=143\x01\
+              auth=Bearer vF9dft4qmTc2Nvb3RlckBhbHRhdmlzdGEuY29tCg==\x01\x01"
+        );
+        assert_eq!(
+            OAuthBearer::new(b"", b"token").encode(),
+            b"n,,\x01auth=Bearer token\x01\x01"
+        );
+        assert_eq!(
+            OAuthBearer::new(b"a,b=c", b"token").encode(),
+            b"n,a=a=2Cb=3Dc,\x01auth=Bearer token\x01\x01"
+        );
+
+        // RFC 7628, section 4.3
+        assert_eq!(
+            bearer.respond(
+                br#"{"status":"invalid_token","scope":"example_scope","openid-configuration":"https://example.com/.well-known/openid-configuration"}"#
+            ),
+            Ok(b"\x01".to_vec())
+        );
+        assert_eq!(
+            bearer.error().unwrap().openid_configuration.as_deref(),
+            Some("https://example.com/.well-known/openid-configuration")
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let xoauth2 = XOauth2::new(b"someuser@example.com", b"ya29.vF9dft4qmT");
+        let message = xoauth2.encode();
+        let decoded = XOauth2::decode(&message).unwrap();
+        assert_eq!((decoded.user, decoded.token), (xoauth2.user, xoauth2.token));
+        assert!(XOauth2::decode(b"user=a\x01auth=Bearer b\x01").is_err());
+        assert!(XOauth2::decode(b"user=a\x01\x01").is_err());
+
+        let bearer = OAuthBearer::new(b"user@example.com", b"vF9dft4qmT")
+            .with_host(b"server.example.com", 110);
+        let message = bearer.encode();
+        let decoded = OAuthBearer::decode(&message).unwrap();
+        assert_eq!(decoded.user, b"user@example.com");
+        assert_eq!(decoded.host, Some(&b"server.example.com"[..]));
+        assert_eq!(decoded.port, Some(110));
+        assert_eq!(decoded.token, b"vF9dft4qmT");
+        let decoded = OAuthBearer::decode(b"n,,\x01auth=Bearer token\x01\x01").unwrap();
+        assert_eq!((decoded.user, decoded.token), (&b""[..], &b"token"[..]));
+        assert!(OAuthBearer::decode(b"n,user,\x01auth=Bearer token\x01\x01").is_err());
+        assert!(OAuthBearer::decode(b"n,,\x01port=x\x01auth=Bearer token\x01\x01").is_err());
+    }
+
+    #[test]
+    fn test_error_challenge() {
+        assert_eq!(
+            ErrorChallenge::decode(
+                b" { \"status\" : \"in\\u0076alid\\\"\" , \"expires\": 3600, \"x\": null } "
+            ),
+            Ok(ErrorChallenge {
+                status: "invalid\"".to_string(),
+                ..Default::default()
+            })
+        );
+        assert_eq!(ErrorChallenge::decode(b"{}"), Err(Error::Malformed));
+        assert_eq!(ErrorChallenge::decode(b"invalid"), Err(Error::Malformed));
+        assert_eq!(
+            ErrorChallenge::decode(br#"{"status":"invalid_token""#),
+            Err(Error::Malformed)
+        );
+        assert_eq!(
+            OAuthBearer::new(b"", b"token").respond(b"{"),
+            Err(Error::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_session() {
+        let session = Session::parse(
+            b"AUTH OAUTHBEARER bixhPXVzZXJAZXhhbXBsZS5jb20sAWF1dGg9QmVhcmVyIHRva2VuAQE=\r\nAQ==\r\n",
+            b"+OK POP3 server ready\r\n+ eyJzdGF0dXMiOiJpbnZhbGlkX3Rva2VuIn0=\r\n-ERR [AUTH] invalid token\r\n",
+        );
+        assert_eq!(session.exchanges.len(), 2);
+        assert!(session.exchanges[1].response.as_ref().unwrap().is_err());
+    }
+}
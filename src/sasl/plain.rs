@@ -0,0 +1,197 @@
+//! The PLAIN mechanism (RFC 4616).
+//!
+//! The client sends its authorization identity, authentication identity
+//! and password in a single message:
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;message = \[authzid\] NUL authcid NUL passwd
+//!
+//! The authentication identity and the password are prepared with
+//! SASLprep.
+use super::saslprep::saslprep;
+use super::{constant_time_eq, Error, Mechanism};
+
+/// Credentials
+///
+/// &[u8], the authorization identity to act as, empty to act as authcid
+///
+/// &[u8], the authentication identity
+///
+/// &[u8], the password of the authentication identity
+#[derive(Debug, PartialEq)]
+pub struct Plain<'a> {
+    pub authzid: &'a [u8],
+    pub authcid: &'a [u8],
+    pub passwd: &'a [u8],
+}
+
+impl<'a> Plain<'a> {
+    pub fn new(authcid: &'a [u8], passwd: &'a [u8]) -> Plain<'a> {
+        Plain {
+            authzid: &[],
+            authcid,
+            passwd,
+        }
+    }
+
+    /// Encodes the message the client sends, with the fields as they
+    /// are.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut message = self.authzid.to_vec();
+        message.push(0);
+        message.extend_from_slice(self.authcid);
+        message.push(0);
+        message.extend_from_slice(self.passwd);
+        message
+    }
+
+    /// Encodes the message the client sends, with the authentication
+    /// identity and the password prepared with SASLprep.
+    pub fn prepare(&self) -> Result<Vec<u8>, Error> {
+        let authcid = saslprep(self.authcid)?;
+        let passwd = saslprep(self.passwd)?;
+        Ok(Plain {
+            authzid: self.authzid,
+            authcid: authcid.as_bytes(),
+            passwd: passwd.as_bytes(),
+        }
+        .encode())
+    }
+
+    /// Decodes the message the client sent.
+    ///
+    /// **Examples**
+    ///
+    /// ```rust
+    /// use rfc1939::sasl::plain::Plain;
+    /// let plain = Plain::decode(b"Ursel\0Kurt\0xipj3plmq").unwrap();
+    /// assert_eq!(plain.authzid, b"Ursel");
+    /// assert_eq!(plain.authcid, b"Kurt");
+    /// assert!(plain.verify(b"xipj3plmq"));
+    /// ```
+    pub fn decode(message: &'a [u8]) -> Result<Plain<'a>, Error> {
+        let mut fields = message.split(|x| *x == 0);
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(authzid), Some(authcid), Some(passwd), None)
+                if (1..=255).contains(&authcid.len()) && (1..=255).contains(&passwd.len()) =>
+            {
+                Ok(Plain {
+                    authzid,
+                    authcid,
+                    passwd,
+                })
+            }
+            _ => Err(Error::Malformed),
+        }
+    }
+
+    /// Whether the password is `passwd` once both are prepared with
+    /// SASLprep, compared in constant time.
+    pub fn verify(&self, passwd: &[u8]) -> bool {
+        match (saslprep(self.passwd), saslprep(passwd)) {
+            (Ok(a), Ok(b)) => constant_time_eq(a.as_bytes(), b.as_bytes()),
+            _ => false,
+        }
+    }
+}
+
+impl Mechanism for Plain<'_> {
+    fn name(&self) -> &'static [u8] {
+        b"PLAIN"
+    }
+
+    fn initial_response(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.prepare().map(Some)
+    }
+
+    /// Servers which do not support initial responses send an empty
+    /// challenge first.
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>, Error> {
+        if challenge.is_empty() {
+            self.prepare()
+        } else {
+            Err(Error::UnexpectedChallenge)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sasl::saslprep;
+    use crate::types::command::{Auth, Encode};
+
+    #[test]
+    fn test_encode() {
+        // RFC 4616, section 4
+        assert_eq!(
+            Plain::new(b"tim", b"tanstaaftanstaaf").encode(),
+            b"\0tim\0tanstaaftanstaaf"
+        );
+        assert_eq!(
+            Plain {
+                authzid: b"Ursel",
+                authcid: b"Kurt",
+                passwd: b"xipj3plmq"
+            }
+            .encode(),
+            b"Ursel\0Kurt\0xipj3plmq"
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(
+            Plain::decode(b"\0tim\0tanstaaftanstaaf").unwrap(),
+            Plain::new(b"tim", b"tanstaaftanstaaf")
+        );
+        assert_eq!(Plain::decode(b"tim\0tanstaaf"), Err(Error::Malformed));
+        assert_eq!(Plain::decode(b"\0\0tanstaaf"), Err(Error::Malformed));
+        assert_eq!(Plain::decode(b"\0tim\0"), Err(Error::Malformed));
+        assert_eq!(Plain::decode(b"\0tim\0a\0b"), Err(Error::Malformed));
+    }
+
+    #[test]
+    fn test_verify() {
+        let plain = Plain::decode(b"\0tim\0tanstaaftanstaaf").unwrap();
+        assert!(plain.verify(b"tanstaaftanstaaf"));
+        assert!(!plain.verify(b"tanstaaf"));
+    }
+
+    #[test]
+    fn test_mechanism() {
+        let mut plain = Plain::new(b"tim", b"tanstaaftanstaaf");
+        assert_eq!(
+            plain.auth().unwrap(),
+            Auth {
+                mechanism: b"PLAIN",
+                initial_response: Some(b"\0tim\0tanstaaftanstaaf".to_vec())
+            }
+        );
+        assert_eq!(
+            plain.auth().unwrap().to_bytes().unwrap(),
+            b"AUTH PLAIN AHRpbQB0YW5zdGFhZnRhbnN0YWFm\r\n"
+        );
+        assert_eq!(plain.respond(b""), Ok(b"\0tim\0tanstaaftanstaaf".to_vec()));
+        assert_eq!(plain.respond(b"?"), Err(Error::UnexpectedChallenge));
+    }
+
+    #[test]
+    fn test_saslprep() {
+        let mut plain = Plain::new("tim".as_bytes(), "I\u{ad}X\u{a0}\u{2168}".as_bytes());
+        assert_eq!(plain.prepare(), Ok(b"\0tim\0IX IX".to_vec()));
+        assert_eq!(plain.respond(b""), Ok(b"\0tim\0IX IX".to_vec()));
+        assert!(Plain::decode(b"\0tim\0IX IX")
+            .unwrap()
+            .verify("I\u{ad}X\u{a0}\u{2168}".as_bytes()));
+
+        let mut plain = Plain::new(b"tim\x07", b"tanstaaf");
+        assert_eq!(
+            plain.auth(),
+            Err(Error::SaslPrep(saslprep::Error::Prohibited {
+                character: '\u{7}',
+                position: 3,
+                table: saslprep::Table::AsciiControl
+            }))
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! The SASLprep profile (RFC 4013) of stringprep (RFC 3454).
+//!
+//! Prepares usernames and passwords so that equivalent strings compare
+//! equal, whatever the way they were typed:
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;1. Map: non-ASCII spaces to SPACE, characters in table B.1 to nothing
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;2. Normalize: Unicode normalization form KC
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;3. Prohibit: the characters of tables C.1.2 and C.2.1 to C.9
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;4. Check bidi: no mix of right-to-left and left-to-right characters
+//!
+//! Queries, such as the credentials a client sends, may contain code
+//! points unassigned in Unicode 3.2; stored strings may not.
+use std::borrow::Cow;
+use std::fmt;
+use stringprep::tables;
+use unicode_normalization::UnicodeNormalization;
+
+type InTable = fn(char) -> bool;
+
+/// The RFC 3454 table a prohibited character belongs to
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Table {
+    /// C.1.2
+    NonAsciiSpace,
+    /// C.2.1
+    AsciiControl,
+    /// C.2.2
+    NonAsciiControl,
+    /// C.3
+    PrivateUse,
+    /// C.4
+    NonCharacter,
+    /// C.5
+    Surrogate,
+    /// C.6
+    InappropriateForPlainText,
+    /// C.7
+    InappropriateForCanonicalRepresentation,
+    /// C.8
+    ChangeDisplayProperties,
+    /// C.9
+    Tagging,
+    /// A.1, prohibited in stored strings only
+    Unassigned,
+}
+
+impl Table {
+    /// The section of RFC 3454 defining the table
+    pub fn section(&self) -> &'static str {
+        match self {
+            Table::NonAsciiSpace => "C.1.2",
+            Table::AsciiControl => "C.2.1",
+            Table::NonAsciiControl => "C.2.2",
+            Table::PrivateUse => "C.3",
+            Table::NonCharacter => "C.4",
+            Table::Surrogate => "C.5",
+            Table::InappropriateForPlainText => "C.6",
+            Table::InappropriateForCanonicalRepresentation => "C.7",
+            Table::ChangeDisplayProperties => "C.8",
+            Table::Tagging => "C.9",
+            Table::Unassigned => "A.1",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Table::NonAsciiSpace => "non-ASCII space character",
+            Table::AsciiControl => "ASCII control character",
+            Table::NonAsciiControl => "non-ASCII control character",
+            Table::PrivateUse => "private use character",
+            Table::NonCharacter => "non-character code point",
+            Table::Surrogate => "surrogate code",
+            Table::InappropriateForPlainText => "character inappropriate for plain text",
+            Table::InappropriateForCanonicalRepresentation => {
+                "character inappropriate for canonical representation"
+            }
+            Table::ChangeDisplayProperties => {
+                "character which changes display properties or is deprecated"
+            }
+            Table::Tagging => "tagging character",
+            Table::Unassigned => "unassigned code point",
+        }
+    }
+
+    fn of(c: char, stored: bool) -> Option<Table> {
+        let tables: [(InTable, Table); 10] = [
+            (tables::non_ascii_space_character, Table::NonAsciiSpace),
+            (tables::ascii_control_character, Table::AsciiControl),
+            (tables::non_ascii_control_character, Table::NonAsciiControl),
+            (tables::private_use, Table::PrivateUse),
+            (tables::non_character_code_point, Table::NonCharacter),
+            (tables::surrogate_code, Table::Surrogate),
+            (
+                tables::inappropriate_for_plain_text,
+                Table::InappropriateForPlainText,
+            ),
+            (
+                tables::inappropriate_for_canonical_representation,
+                Table::InappropriateForCanonicalRepresentation,
+            ),
+            (
+                tables::change_display_properties_or_deprecated,
+                Table::ChangeDisplayProperties,
+            ),
+            (tables::tagging_character, Table::Tagging),
+        ];
+        match tables.iter().find(|(f, _)| f(c)) {
+            Some((_, table)) => Some(*table),
+            None if stored && tables::unassigned_code_point(c) => Some(Table::Unassigned),
+            None => None,
+        }
+    }
+}
+
+/// Errors of SASLprep
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    /// The string is not UTF-8, `valid_up_to` octets are
+    InvalidUtf8 { valid_up_to: usize },
+    /// The prepared string contains a prohibited character, at the
+    /// character index `position`
+    Prohibited {
+        character: char,
+        position: usize,
+        table: Table,
+    },
+    /// The string mixes right-to-left and left-to-right characters
+    MixedDirection,
+    /// The string contains right-to-left characters but does not start
+    /// and end with one
+    RightToLeftBoundary,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidUtf8 { valid_up_to } => {
+                write!(f, "invalid UTF-8 after {} octets", valid_up_to)
+            }
+            Error::Prohibited {
+                character,
+                position,
+                table,
+            } => write!(
+                f,
+                "prohibited {} U+{:04X} at position {} (RFC 3454 {})",
+                table.description(),
+                *character as u32,
+                position,
+                table.section()
+            ),
+            Error::MixedDirection => {
+                write!(f, "mixed right-to-left and left-to-right characters")
+            }
+            Error::RightToLeftBoundary => write!(
+                f,
+                "right-to-left string does not start and end with a right-to-left character"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Prepares a query string, such as the credentials a client sends.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::sasl::saslprep::{saslprep, Error, Table};
+/// // RFC 4013, section 3
+/// assert_eq!(saslprep("I\u{ad}X".as_bytes()).unwrap(), "IX");
+/// assert_eq!(saslprep("\u{2168}".as_bytes()).unwrap(), "IX");
+/// assert_eq!(
+///     saslprep(b"\x07"),
+///     Err(Error::Prohibited { character: '\u{7}', position: 0, table: Table::AsciiControl })
+/// );
+/// ```
+pub fn saslprep(s: &[u8]) -> Result<Cow<'_, str>, Error> {
+    prepare(s, false)
+}
+
+/// Prepares a stored string, such as the credentials a server keeps.
+/// Unlike [`saslprep`], code points unassigned in Unicode 3.2 are
+/// prohibited.
+pub fn saslprep_stored(s: &[u8]) -> Result<Cow<'_, str>, Error> {
+    prepare(s, true)
+}
+
+fn prepare(s: &[u8], stored: bool) -> Result<Cow<'_, str>, Error> {
+    let s = std::str::from_utf8(s).map_err(|x| Error::InvalidUtf8 {
+        valid_up_to: x.valid_up_to(),
+    })?;
+    // Printable ASCII is left unchanged by every step
+    if s.bytes().all(|x| (0x20..0x7f).contains(&x)) {
+        return Ok(Cow::Borrowed(s));
+    }
+
+    let prepared: String = s
+        .chars()
+        .filter(|c| !tables::commonly_mapped_to_nothing(*c))
+        .map(|c| {
+            if tables::non_ascii_space_character(c) {
+                ' '
+            } else {
+                c
+            }
+        })
+        .nfkc()
+        .collect();
+
+    for (position, character) in prepared.chars().enumerate() {
+        if let Some(table) = Table::of(character, stored) {
+            return Err(Error::Prohibited {
+                character,
+                position,
+                table,
+            });
+        }
+    }
+
+    if prepared.chars().any(tables::bidi_r_or_al) {
+        if prepared.chars().any(tables::bidi_l) {
+            return Err(Error::MixedDirection);
+        }
+        let first = prepared.chars().next().is_some_and(tables::bidi_r_or_al);
+        let last = prepared.chars().last().is_some_and(tables::bidi_r_or_al);
+        if !first || !last {
+            return Err(Error::RightToLeftBoundary);
+        }
+    }
+    Ok(Cow::Owned(prepared))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_saslprep() {
+        // RFC 4013, section 3
+        assert_eq!(saslprep("I\u{ad}X".as_bytes()).unwrap(), "IX");
+        assert_eq!(saslprep(b"user").unwrap(), "user");
+        assert_eq!(saslprep(b"USER").unwrap(), "USER");
+        assert_eq!(saslprep("\u{aa}".as_bytes()).unwrap(), "a");
+        assert_eq!(saslprep("\u{2168}".as_bytes()).unwrap(), "IX");
+        assert_eq!(
+            saslprep(b"\x07"),
+            Err(Error::Prohibited {
+                character: '\u{7}',
+                position: 0,
+                table: Table::AsciiControl
+            })
+        );
+        assert_eq!(
+            saslprep("\u{627}1".as_bytes()),
+            Err(Error::RightToLeftBoundary)
+        );
+
+        assert!(matches!(saslprep(b"pencil").unwrap(), Cow::Borrowed(_)));
+        assert_eq!(saslprep("a\u{a0}b".as_bytes()).unwrap(), "a b");
+        assert_eq!(
+            saslprep("\u{627}1\u{628}".as_bytes()).unwrap(),
+            "\u{627}1\u{628}"
+        );
+        assert_eq!(
+            saslprep("\u{627}a\u{628}".as_bytes()),
+            Err(Error::MixedDirection)
+        );
+        assert_eq!(
+            saslprep(b"ab\xff"),
+            Err(Error::InvalidUtf8 { valid_up_to: 2 })
+        );
+    }
+
+    #[test]
+    fn test_prohibited() {
+        for (s, table) in [
+            ("\u{85}", Table::NonAsciiControl),
+            ("\u{e000}", Table::PrivateUse),
+            ("\u{fdd0}", Table::NonCharacter),
+            ("\u{fffd}", Table::InappropriateForPlainText),
+            ("\u{2ff0}", Table::InappropriateForCanonicalRepresentation),
+            ("\u{200e}", Table::ChangeDisplayProperties),
+            ("\u{e0001}", Table::Tagging),
+        ] {
+            match saslprep(format!("ab{}", s).as_bytes()) {
+                Err(Error::Prohibited {
+                    position: 2,
+                    table: x,
+                    ..
+                }) => assert_eq!(x, table),
+                x => panic!("{:?}: {:?}", s, x),
+            }
+        }
+    }
+
+    #[test]
+    fn test_saslprep_stored() {
+        assert_eq!(saslprep("\u{221}".as_bytes()).unwrap(), "\u{221}");
+        assert_eq!(
+            saslprep_stored("\u{221}".as_bytes()),
+            Err(Error::Prohibited {
+                character: '\u{221}',
+                position: 0,
+                table: Table::Unassigned
+            })
+        );
+        assert_eq!(
+            saslprep_stored("\u{221}".as_bytes())
+                .unwrap_err()
+                .to_string(),
+            "prohibited unassigned code point U+0221 at position 0 (RFC 3454 A.1)"
+        );
+    }
+}
@@ -0,0 +1,751 @@
+//! The SCRAM-SHA-1 (RFC 5802) and SCRAM-SHA-256 (RFC 7677) mechanisms.
+//!
+//! Neither the password nor anything which can be replayed is sent; the
+//! client proves it knows the salted password, and the server proves it
+//! knows the stored key, in the following exchange:
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;C: n,,n=user,r=client-nonce
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;S: r=client-nonce server-nonce,s=salt,i=iteration-count
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;C: c=biws,r=client-nonce server-nonce,p=client-proof
+//!
+//! &nbsp;&nbsp;&nbsp;&nbsp;S: v=server-signature
+//!
+//! Usernames and passwords are prepared with SASLprep. Channel binding is
+//! not supported.
+use super::saslprep::{saslprep, saslprep_stored};
+use super::{constant_time_eq, random_nonce, Error, Mechanism};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// The GS2 header of a client which does not support channel binding
+const GS2_HEADER: &[u8] = b"n,,";
+
+/// The largest iteration count a client accepts, as each iteration
+/// costs it an HMAC
+pub const MAX_ITERATIONS: u32 = 1_000_000;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+}
+
+impl Algorithm {
+    /// The name of the mechanism, as listed by the SASL capability
+    pub fn name(&self) -> &'static [u8] {
+        match self {
+            Algorithm::Sha1 => b"SCRAM-SHA-1",
+            Algorithm::Sha256 => b"SCRAM-SHA-256",
+        }
+    }
+
+    fn hash(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Sha1 => Sha1::digest(data).to_vec(),
+            Algorithm::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    fn hmac(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Sha1 => {
+                let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("any key length");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+            Algorithm::Sha256 => {
+                let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("any key length");
+                mac.update(data);
+                mac.finalize().into_bytes().to_vec()
+            }
+        }
+    }
+
+    /// Hi(), that is PBKDF2 with HMAC as the pseudorandom function
+    fn salted_password(&self, password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+        match self {
+            Algorithm::Sha1 => {
+                let mut out = [0; 20];
+                pbkdf2::pbkdf2_hmac::<Sha1>(password, salt, iterations, &mut out);
+                out.to_vec()
+            }
+            Algorithm::Sha256 => {
+                let mut out = [0; 32];
+                pbkdf2::pbkdf2_hmac::<Sha256>(password, salt, iterations, &mut out);
+                out.to_vec()
+            }
+        }
+    }
+}
+
+/// Credentials
+///
+/// What a server stores for a user instead of the password
+///
+/// Vec<u8>, the salt
+///
+/// u32, the iteration count
+///
+/// Vec<u8>, StoredKey, H(HMAC(SaltedPassword, "Client Key"))
+///
+/// Vec<u8>, ServerKey, HMAC(SaltedPassword, "Server Key")
+#[derive(Debug, PartialEq, Clone)]
+pub struct Credentials {
+    pub salt: Vec<u8>,
+    pub iterations: u32,
+    pub stored_key: Vec<u8>,
+    pub server_key: Vec<u8>,
+}
+
+impl Credentials {
+    /// Derives the credentials from the password, prepared with SASLprep
+    /// for stored strings.
+    pub fn new(
+        algorithm: Algorithm,
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+    ) -> Result<Credentials, Error> {
+        let password = saslprep_stored(password)?;
+        let salted_password = algorithm.salted_password(password.as_bytes(), salt, iterations);
+        Ok(Credentials {
+            salt: salt.to_vec(),
+            iterations,
+            stored_key: algorithm.hash(&algorithm.hmac(&salted_password, b"Client Key")),
+            server_key: algorithm.hmac(&salted_password, b"Server Key"),
+        })
+    }
+}
+
+/// The iteration count offered to users without credentials
+const DUMMY_ITERATIONS: u32 = 4096;
+
+/// Made-up credentials for a user without any, so that unknown users
+/// are only rejected with the client-final-message (RFC 5802, section
+/// 5.1).  The salt of a username is the same for the life of the
+/// process, and no proof matches the random stored key.
+fn dummy_credentials(algorithm: Algorithm, username: &[u8]) -> Credentials {
+    static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+    let salt = algorithm.hmac(KEY.get_or_init(random_nonce), username);
+    Credentials {
+        salt: salt[..16].to_vec(),
+        iterations: DUMMY_ITERATIONS,
+        stored_key: algorithm.hash(&random_nonce()),
+        server_key: vec![],
+    }
+}
+
+/// Splits a SCRAM message into its attributes, e.g. `r=abc,i=4096` into
+/// `[(b'r', b"abc"), (b'i', b"4096")]`.
+fn attributes(message: &[u8]) -> Result<Vec<(u8, &[u8])>, Error> {
+    message
+        .split(|x| *x == b',')
+        .map(|x| match x {
+            [name, b'=', value @ ..] if name.is_ascii_alphabetic() => Ok((*name, value)),
+            _ => Err(Error::Malformed),
+        })
+        .collect()
+}
+
+fn attribute<'a>(attributes: &[(u8, &'a [u8])], name: u8) -> Result<&'a [u8], Error> {
+    attributes
+        .iter()
+        .find(|(x, _)| *x == name)
+        .map(|(_, value)| *value)
+        .ok_or(Error::Malformed)
+}
+
+/// Escapes "=" and "," in a username, as the saslname production requires.
+pub(crate) fn saslname(username: &[u8]) -> Vec<u8> {
+    let mut name = vec![];
+    for x in username {
+        match x {
+            b'=' => name.extend_from_slice(b"=3D"),
+            b',' => name.extend_from_slice(b"=2C"),
+            _ => name.push(*x),
+        }
+    }
+    name
+}
+
+/// Reverses [`saslname`], rejecting any other use of "=".
+fn unescape_saslname(name: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut username = vec![];
+    let mut rest = name;
+    while let Some((x, tail)) = rest.split_first() {
+        rest = match (x, tail) {
+            (b'=', [b'2', b'C', tail @ ..]) => {
+                username.push(b',');
+                tail
+            }
+            (b'=', [b'3', b'D', tail @ ..]) => {
+                username.push(b'=');
+                tail
+            }
+            (b'=', _) => return Err(Error::Malformed),
+            _ => {
+                username.push(*x);
+                tail
+            }
+        };
+    }
+    Ok(username)
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+fn decode_base64(s: &[u8]) -> Result<Vec<u8>, Error> {
+    STANDARD.decode(s).map_err(|_| Error::Malformed)
+}
+
+#[derive(Debug)]
+enum ClientState {
+    Initial,
+    First { client_first_bare: Vec<u8> },
+    Final { server_signature: Vec<u8> },
+    Done,
+    Failed,
+}
+
+/// The client side of SCRAM
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::sasl::scram::{Algorithm, Scram};
+/// use rfc1939::sasl::Mechanism;
+/// use rfc1939::types::command::Encode;
+///
+/// let mut scram = Scram::new(Algorithm::Sha256, b"user", b"pencil");
+/// let auth = scram.auth().unwrap();
+/// assert_eq!(auth.mechanism, b"SCRAM-SHA-256");
+/// assert!(auth.initial_response.unwrap().starts_with(b"n,,n=user,r="));
+/// ```
+#[derive(Debug)]
+pub struct Scram<'a> {
+    pub algorithm: Algorithm,
+    pub username: &'a [u8],
+    pub password: &'a [u8],
+    nonce: Vec<u8>,
+    state: ClientState,
+}
+
+impl<'a> Scram<'a> {
+    /// A client with a random nonce
+    pub fn new(algorithm: Algorithm, username: &'a [u8], password: &'a [u8]) -> Scram<'a> {
+        Scram::with_nonce(algorithm, username, password, random_nonce())
+    }
+
+    pub fn with_nonce(
+        algorithm: Algorithm,
+        username: &'a [u8],
+        password: &'a [u8],
+        nonce: Vec<u8>,
+    ) -> Scram<'a> {
+        Scram {
+            algorithm,
+            username,
+            password,
+            nonce,
+            state: ClientState::Initial,
+        }
+    }
+
+    /// Whether the server proved it knows the user's credentials
+    pub fn is_verified(&self) -> bool {
+        matches!(self.state, ClientState::Done)
+    }
+
+    fn client_first(&mut self) -> Result<Vec<u8>, Error> {
+        let mut client_first_bare = b"n=".to_vec();
+        client_first_bare.extend_from_slice(&saslname(saslprep(self.username)?.as_bytes()));
+        client_first_bare.extend_from_slice(b",r=");
+        client_first_bare.extend_from_slice(&self.nonce);
+        let message = [GS2_HEADER, &client_first_bare].concat();
+        self.state = ClientState::First { client_first_bare };
+        Ok(message)
+    }
+
+    fn client_final(
+        &self,
+        client_first_bare: &[u8],
+        server_first: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), Error> {
+        let attributes = attributes(server_first)?;
+        let nonce = attribute(&attributes, b'r')?;
+        if !nonce.starts_with(&self.nonce) || nonce.len() == self.nonce.len() {
+            return Err(Error::Malformed);
+        }
+        let salt = decode_base64(attribute(&attributes, b's')?)?;
+        let iterations = std::str::from_utf8(attribute(&attributes, b'i')?)
+            .ok()
+            .and_then(|x| x.parse::<u32>().ok())
+            .filter(|x| (1..=MAX_ITERATIONS).contains(x))
+            .ok_or(Error::Malformed)?;
+
+        let mut without_proof = b"c=".to_vec();
+        without_proof.extend_from_slice(STANDARD.encode(GS2_HEADER).as_bytes());
+        without_proof.extend_from_slice(b",r=");
+        without_proof.extend_from_slice(nonce);
+        let auth_message = [client_first_bare, b",", server_first, b",", &without_proof].concat();
+
+        let algorithm = self.algorithm;
+        let password = saslprep(self.password)?;
+        let salted_password = algorithm.salted_password(password.as_bytes(), &salt, iterations);
+        let client_key = algorithm.hmac(&salted_password, b"Client Key");
+        let stored_key = algorithm.hash(&client_key);
+        let client_signature = algorithm.hmac(&stored_key, &auth_message);
+        let server_key = algorithm.hmac(&salted_password, b"Server Key");
+        let server_signature = algorithm.hmac(&server_key, &auth_message);
+
+        let mut client_final = without_proof;
+        client_final.extend_from_slice(b",p=");
+        client_final.extend_from_slice(
+            STANDARD
+                .encode(xor(&client_key, &client_signature))
+                .as_bytes(),
+        );
+        Ok((client_final, server_signature))
+    }
+}
+
+impl Mechanism for Scram<'_> {
+    fn name(&self) -> &'static [u8] {
+        self.algorithm.name()
+    }
+
+    fn initial_response(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        self.client_first().map(Some)
+    }
+
+    fn respond(&mut self, challenge: &[u8]) -> Result<Vec<u8>, Error> {
+        match std::mem::replace(&mut self.state, ClientState::Failed) {
+            ClientState::Initial if challenge.is_empty() => self.client_first(),
+            ClientState::First { client_first_bare } => {
+                let (client_final, server_signature) =
+                    self.client_final(&client_first_bare, challenge)?;
+                self.state = ClientState::Final { server_signature };
+                Ok(client_final)
+            }
+            ClientState::Final { server_signature } => {
+                let attributes = attributes(challenge)?;
+                if let Ok(error) = attribute(&attributes, b'e') {
+                    return Err(Error::Server(String::from_utf8_lossy(error).into_owned()));
+                }
+                let verifier = decode_base64(attribute(&attributes, b'v')?)?;
+                if constant_time_eq(&verifier, &server_signature) {
+                    self.state = ClientState::Done;
+                    // The server ends the exchange once it gets an empty response
+                    Ok(vec![])
+                } else {
+                    Err(Error::InvalidServerSignature)
+                }
+            }
+            _ => Err(Error::UnexpectedChallenge),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ServerState {
+    Initial,
+    First {
+        username: Vec<u8>,
+        /// The GS2 header of the client-first-message, which the
+        /// client-final-message repeats
+        gs2_header: Vec<u8>,
+        credentials: Credentials,
+        auth_message: Vec<u8>,
+        nonce: Vec<u8>,
+    },
+    Done {
+        username: Vec<u8>,
+    },
+}
+
+/// The server side of SCRAM
+#[derive(Debug)]
+pub struct Server {
+    pub algorithm: Algorithm,
+    nonce: Vec<u8>,
+    state: ServerState,
+}
+
+impl Server {
+    /// A server with a random nonce
+    pub fn new(algorithm: Algorithm) -> Server {
+        Server::with_nonce(algorithm, random_nonce())
+    }
+
+    pub fn with_nonce(algorithm: Algorithm, nonce: Vec<u8>) -> Server {
+        Server {
+            algorithm,
+            nonce,
+            state: ServerState::Initial,
+        }
+    }
+
+    /// The authenticated username, once the client's proof verified
+    pub fn username(&self) -> Option<&[u8]> {
+        match &self.state {
+            ServerState::Done { username } => Some(username),
+            _ => None,
+        }
+    }
+
+    /// Handles the client-first-message, looking up the stored
+    /// credentials of the user with `credentials`. Returns the
+    /// server-first-message to send as a challenge; a user without
+    /// credentials gets one too, and is rejected by [`Server::last`].
+    pub fn first<F>(&mut self, client_first: &[u8], credentials: F) -> Result<Vec<u8>, Error>
+    where
+        F: FnOnce(&[u8]) -> Option<Credentials>,
+    {
+        let client_first_bare = match client_first {
+            [b'n' | b'y', b',', rest @ ..] => match rest.iter().position(|x| *x == b',') {
+                Some(i) => &rest[i + 1..],
+                None => return Err(Error::Malformed),
+            },
+            _ => return Err(Error::Malformed),
+        };
+        let attributes = attributes(client_first_bare)?;
+        let username = attribute(&attributes, b'n')?;
+        let username = unescape_saslname(username)?;
+        let client_nonce = attribute(&attributes, b'r')?;
+        let credentials =
+            credentials(&username).unwrap_or_else(|| dummy_credentials(self.algorithm, &username));
+
+        let nonce = [client_nonce, &self.nonce].concat();
+        let mut server_first = b"r=".to_vec();
+        server_first.extend_from_slice(&nonce);
+        server_first.extend_from_slice(b",s=");
+        server_first.extend_from_slice(STANDARD.encode(&credentials.salt).as_bytes());
+        server_first.extend_from_slice(format!(",i={}", credentials.iterations).as_bytes());
+        self.state = ServerState::First {
+            username,
+            gs2_header: client_first[..client_first.len() - client_first_bare.len()].to_vec(),
+            credentials,
+            auth_message: [client_first_bare, b",", &server_first].concat(),
+            nonce,
+        };
+        Ok(server_first)
+    }
+
+    /// Verifies the client-final-message. Returns the
+    /// server-final-message to send as a challenge.
+    pub fn last(&mut self, client_final: &[u8]) -> Result<Vec<u8>, Error> {
+        let ServerState::First {
+            username,
+            gs2_header,
+            credentials,
+            auth_message,
+            nonce,
+        } = std::mem::replace(&mut self.state, ServerState::Initial)
+        else {
+            return Err(Error::UnexpectedChallenge);
+        };
+        let i = client_final
+            .windows(3)
+            .rposition(|x| x == b",p=")
+            .ok_or(Error::Malformed)?;
+        let without_proof = &client_final[..i];
+        let attributes = attributes(client_final)?;
+        if attribute(&attributes, b'r')? != nonce.as_slice()
+            || decode_base64(attribute(&attributes, b'c')?)? != gs2_header
+        {
+            return Err(Error::Malformed);
+        }
+        let proof = decode_base64(attribute(&attributes, b'p')?)?;
+
+        let algorithm = self.algorithm;
+        let auth_message = [&auth_message[..], b",", without_proof].concat();
+        let client_signature = algorithm.hmac(&credentials.stored_key, &auth_message);
+        let client_key = xor(&proof, &client_signature);
+        if proof.len() != client_signature.len()
+            || !constant_time_eq(&algorithm.hash(&client_key), &credentials.stored_key)
+        {
+            return Err(Error::InvalidCredentials);
+        }
+        let server_signature = algorithm.hmac(&credentials.server_key, &auth_message);
+        self.state = ServerState::Done { username };
+        Ok([b"v=", STANDARD.encode(server_signature).as_bytes()].concat())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Conversation {
+        algorithm: Algorithm,
+        client_nonce: &'static [u8],
+        server_nonce: &'static [u8],
+        salt: &'static [u8],
+        client_first: &'static [u8],
+        server_first: &'static [u8],
+        client_final: &'static [u8],
+        server_final: &'static [u8],
+    }
+
+    // RFC 5802, section 5
+    const SHA1: Conversation = Conversation {
+        algorithm: Algorithm::Sha1,
+        client_nonce: b"fyko+d2lbbFgONRv9qkxdawL",
+        server_nonce: b"3rfcNHYJY1ZVvWVs7j",
+        salt: b"QSXCR+Q6sek8bf92",
+        client_first: b"n,,n=user,r=fyko+d2lbbFgONRv9qkxdawL",
+        server_first: b"r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096",
+        client_final: b"c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,\
+                        p=v0X8v3Bz2T0CJGbJQyF0X+HI4Ts=",
+        server_final: b"v=rmF9pqV8S7suAoZWja4dJRkFsKQ=",
+    };
+
+    // RFC 7677, section 3
+    const SHA256: Conversation = Conversation {
+        algorithm: Algorithm::Sha256,
+        client_nonce: b"rOprNGfwEbeRWgbNEkqO",
+        server_nonce: b"%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0",
+        salt: b"W22ZaJ0SNY7soEsUEjb6gQ==",
+        client_first: b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO",
+        server_first: b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                        s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096",
+        client_final: b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+                        p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=",
+        server_final: b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=",
+    };
+
+    fn client(conversation: &Conversation) {
+        let mut scram = Scram::with_nonce(
+            conversation.algorithm,
+            b"user",
+            b"pencil",
+            conversation.client_nonce.to_vec(),
+        );
+        assert_eq!(
+            scram.initial_response().unwrap().unwrap(),
+            conversation.client_first
+        );
+        assert_eq!(
+            scram.respond(conversation.server_first).unwrap(),
+            conversation.client_final
+        );
+        assert!(!scram.is_verified());
+        assert_eq!(scram.respond(conversation.server_final).unwrap(), b"");
+        assert!(scram.is_verified());
+    }
+
+    fn server(conversation: &Conversation) {
+        let credentials = Credentials::new(
+            conversation.algorithm,
+            b"pencil",
+            &STANDARD.decode(conversation.salt).unwrap(),
+            4096,
+        )
+        .unwrap();
+        let mut server =
+            Server::with_nonce(conversation.algorithm, conversation.server_nonce.to_vec());
+        assert_eq!(
+            server
+                .first(conversation.client_first, |x| {
+                    (x == b"user").then(|| credentials.clone())
+                })
+                .unwrap(),
+            conversation.server_first
+        );
+        assert_eq!(server.username(), None);
+        assert_eq!(
+            server.last(conversation.client_final).unwrap(),
+            conversation.server_final
+        );
+        assert_eq!(server.username(), Some(&b"user"[..]));
+    }
+
+    #[test]
+    fn test_scram_sha_1() {
+        client(&SHA1);
+        server(&SHA1);
+    }
+
+    #[test]
+    fn test_scram_sha_256() {
+        client(&SHA256);
+        server(&SHA256);
+    }
+
+    #[test]
+    fn test_client_rejects_server() {
+        let mut scram = Scram::with_nonce(
+            Algorithm::Sha1,
+            b"user",
+            b"pencil",
+            SHA1.client_nonce.to_vec(),
+        );
+        scram.initial_response().unwrap();
+        // The server nonce must extend the client nonce
+        assert_eq!(
+            scram.respond(b"r=abc,s=QSXCR+Q6sek8bf92,i=4096"),
+            Err(Error::Malformed)
+        );
+
+        let mut scram = Scram::with_nonce(
+            Algorithm::Sha1,
+            b"user",
+            b"pencil",
+            SHA1.client_nonce.to_vec(),
+        );
+        scram.initial_response().unwrap();
+        assert_eq!(
+            scram.respond(
+                b"r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4294967295"
+            ),
+            Err(Error::Malformed)
+        );
+
+        let mut scram = Scram::with_nonce(
+            Algorithm::Sha1,
+            b"user",
+            b"pencil",
+            SHA1.client_nonce.to_vec(),
+        );
+        scram.initial_response().unwrap();
+        scram.respond(SHA1.server_first).unwrap();
+        assert_eq!(
+            scram.respond(b"v=AAAAAAAAAAAAAAAAAAAAAAAAAAA="),
+            Err(Error::InvalidServerSignature)
+        );
+        assert!(!scram.is_verified());
+
+        let mut scram = Scram::with_nonce(
+            Algorithm::Sha1,
+            b"user",
+            b"pencil",
+            SHA1.client_nonce.to_vec(),
+        );
+        scram.initial_response().unwrap();
+        scram.respond(SHA1.server_first).unwrap();
+        assert_eq!(
+            scram.respond(b"e=invalid-proof"),
+            Err(Error::Server("invalid-proof".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_server_rejects_client() {
+        let credentials = Credentials::new(Algorithm::Sha1, b"pencil", b"salt", 4096).unwrap();
+        let mut server = Server::with_nonce(Algorithm::Sha1, b"3rfcNHYJY1ZVvWVs7j".to_vec());
+        let server_first = server
+            .first(SHA1.client_first, |_| Some(credentials.clone()))
+            .unwrap();
+
+        let mut scram = Scram::with_nonce(
+            Algorithm::Sha1,
+            b"user",
+            b"eraser",
+            SHA1.client_nonce.to_vec(),
+        );
+        scram.initial_response().unwrap();
+        let client_final = scram.respond(&server_first).unwrap();
+        assert_eq!(server.last(&client_final), Err(Error::InvalidCredentials));
+        assert_eq!(server.username(), None);
+
+        // c= repeats the GS2 header of the client-first-message, "y,,"
+        let nonce = "fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j";
+        let client_first = b"y,,n=user,r=fyko+d2lbbFgONRv9qkxdawL";
+        for (c, result) in [
+            ("biws", Error::Malformed),
+            ("eSws", Error::InvalidCredentials),
+        ] {
+            server
+                .first(client_first, |_| Some(credentials.clone()))
+                .unwrap();
+            let client_final = format!("c={},r={},p=AAAA", c, nonce);
+            assert_eq!(server.last(client_final.as_bytes()), Err(result));
+        }
+    }
+
+    #[test]
+    fn test_server_unknown_user() {
+        let mut server = Server::with_nonce(Algorithm::Sha1, b"3rfcNHYJY1ZVvWVs7j".to_vec());
+        let server_first = server.first(SHA1.client_first, |_| None).unwrap();
+        let salt = attribute(&attributes(&server_first).unwrap(), b's')
+            .unwrap()
+            .to_vec();
+        assert!(server_first.ends_with(b",i=4096"));
+
+        let mut scram = Scram::with_nonce(
+            Algorithm::Sha1,
+            b"user",
+            b"pencil",
+            SHA1.client_nonce.to_vec(),
+        );
+        scram.initial_response().unwrap();
+        let client_final = scram.respond(&server_first).unwrap();
+        assert_eq!(server.last(&client_final), Err(Error::InvalidCredentials));
+        assert_eq!(server.username(), None);
+
+        // the same salt again, another for another user
+        let server_first = server.first(SHA1.client_first, |_| None).unwrap();
+        assert_eq!(
+            attribute(&attributes(&server_first).unwrap(), b's').unwrap(),
+            salt
+        );
+        let server_first = server
+            .first(b"n,,n=resu,r=fyko+d2lbbFgONRv9qkxdawL", |_| None)
+            .unwrap();
+        assert_ne!(
+            attribute(&attributes(&server_first).unwrap(), b's').unwrap(),
+            salt
+        );
+    }
+
+    #[test]
+    fn test_random_nonce() {
+        let mut client = Scram::new(Algorithm::Sha256, b"user,=", b"pencil");
+        let mut server = Server::new(Algorithm::Sha256);
+        let credentials = Credentials::new(Algorithm::Sha256, b"pencil", b"salt", 4096).unwrap();
+        let client_first = client.initial_response().unwrap().unwrap();
+        assert!(client_first.starts_with(b"n,,n=user=2C=3D,r="));
+        let server_first = server
+            .first(&client_first, |x| {
+                (x == b"user,=").then(|| credentials.clone())
+            })
+            .unwrap();
+        let client_final = client.respond(&server_first).unwrap();
+        let server_final = server.last(&client_final).unwrap();
+        assert_eq!(client.respond(&server_final).unwrap(), b"");
+        assert!(client.is_verified());
+    }
+
+    #[test]
+    fn test_saslprep() {
+        let credentials = Credentials::new(Algorithm::Sha256, b"IX", b"salt", 4096).unwrap();
+        let mut client = Scram::new(
+            Algorithm::Sha256,
+            "user\u{ad}".as_bytes(),
+            "\u{2168}".as_bytes(),
+        );
+        let mut server = Server::new(Algorithm::Sha256);
+        let client_first = client.initial_response().unwrap().unwrap();
+        let server_first = server
+            .first(&client_first, |x| {
+                (x == b"user").then(|| credentials.clone())
+            })
+            .unwrap();
+        let client_final = client.respond(&server_first).unwrap();
+        assert!(server.last(&client_final).is_ok());
+
+        assert!(matches!(
+            Credentials::new(Algorithm::Sha256, "\u{221}".as_bytes(), b"salt", 4096),
+            Err(Error::SaslPrep(_))
+        ));
+        let mut client = Scram::new(Algorithm::Sha256, b"user\x00", b"pencil");
+        assert!(matches!(client.auth(), Err(Error::SaslPrep(_))));
+    }
+}
@@ -0,0 +1,578 @@
+//! A POP3 session is both directions of one connection: the commands
+//! sent by the client and the responses sent by the server.
+//!
+//! Every command is correlated with the response that answers it, so
+//! that responses whose shape depends on the command (e.g. a multi-line
+//! LIST versus a one line STAT) are parsed with the right parser.
+pub mod registry;
+pub mod state;
+
+use crate::authorization;
+use crate::authorization::response::greeting_parser;
+use crate::extension;
+use crate::framing::guessed_length;
+use crate::transaction;
+use crate::types::command::Command;
+use crate::types::response::{Greeting, Response};
+use crate::update;
+use nom::{branch::alt, combinator::map, IResult};
+use registry::Registry;
+use state::{State, Transition};
+use std::borrow::Cow;
+use std::net::SocketAddr;
+
+// ################################################################################
+/// Parses any command a POP3 client may send.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::session::command;
+/// use rfc1939::types::command::{Command, Retr, Unknown};
+/// assert_eq!(command(b"RETR 1\r\n").unwrap(), Command::Retr(Retr { msg: 1 }));
+/// assert_eq!(
+///     command(b"XSENDER 1\r\n").unwrap(),
+///     Command::Unknown(Unknown { keyword: b"XSENDER", args: vec![b"1"] })
+/// );
+/// ```
+// ################################################################################
+pub fn command(s: &[u8]) -> Option<Command<'_>> {
+    match command_parser(s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn command_parser(s: &[u8]) -> IResult<&[u8], Command<'_>> {
+    alt((
+        standard_command_parser,
+        legacy_command_parser,
+        map(extension::command::unknown_parser, Command::Unknown),
+    ))(s)
+}
+
+fn standard_command_parser(s: &[u8]) -> IResult<&[u8], Command<'_>> {
+    alt((
+        map(authorization::command::user_parser, Command::User),
+        map(authorization::command::pass_parser, Command::Pass),
+        map(authorization::command::apop_parser, Command::Apop),
+        map(authorization::command::stls_parser, Command::Stls),
+        map(authorization::command::auth_parser, Command::Auth),
+        map(authorization::command::utf8_parser, Command::Utf8),
+        map(transaction::command::stat_parser, Command::Stat),
+        map(transaction::command::list_parser, Command::List),
+        map(transaction::command::retr_parser, Command::Retr),
+        map(transaction::command::dele_parser, Command::Dele),
+        map(transaction::command::noop_parser, Command::Noop),
+        map(transaction::command::rset_parser, Command::Rset),
+        map(update::command::quit_parser, Command::Quit),
+        map(transaction::command::top_parser, Command::Top),
+        map(transaction::command::uidl_parser, Command::Uidl),
+        map(extension::command::capa_parser, Command::Capa),
+        map(extension::command::lang_parser, Command::Lang),
+    ))(s)
+}
+
+#[cfg(feature = "legacy")]
+fn legacy_command_parser(s: &[u8]) -> IResult<&[u8], Command<'_>> {
+    crate::legacy::command::command_parser(s)
+}
+
+#[cfg(not(feature = "legacy"))]
+fn legacy_command_parser(s: &[u8]) -> IResult<&[u8], Command<'_>> {
+    nom::combinator::fail(s)
+}
+
+/// Parses the next client line, which is not a command while an AUTH or
+/// XTND XMIT exchange is in progress.
+fn next_command_parser<'a>(
+    registry: &Registry,
+    state: &State,
+    s: &'a [u8],
+) -> IResult<&'a [u8], Command<'a>> {
+    if state.authenticating {
+        return map(
+            authorization::command::auth_response_parser,
+            Command::AuthResponse,
+        )(s);
+    }
+    #[cfg(feature = "legacy")]
+    if state.transmitting {
+        return map(
+            crate::legacy::command::xmit_message_parser,
+            Command::XmitMessage,
+        )(s);
+    }
+    registry.command_parser(s)
+}
+
+// ################################################################################
+/// Parses the response the POP3 server sends to `command`.
+///
+/// A response which the parser of a known command rejects is parsed as
+/// the response to an unknown command, so that it is still framed.
+///
+/// **Examples**
+///
+/// ```rust
+/// use rfc1939::common::StatusIndicator;
+/// use rfc1939::session::response;
+/// use rfc1939::types::{command, response::{Dele, Response}};
+/// assert_eq!(
+///     response(&command::Command::Dele(command::Dele { msg: 1 }), b"+OK message 1 deleted\r\n")
+///         .unwrap(),
+///     Response::Dele(Dele {
+///         status_indicator: StatusIndicator::OK,
+///         information: b"message 1 deleted"
+///     })
+/// );
+/// ```
+// ################################################################################
+pub fn response<'a>(command: &Command, s: &'a [u8]) -> Option<Response<'a>> {
+    match response_parser(command, s) {
+        Ok((_, x)) => Some(x),
+        Err(_) => None,
+    }
+}
+
+pub(crate) fn response_parser<'a>(
+    command: &Command,
+    s: &'a [u8],
+) -> IResult<&'a [u8], Response<'a>> {
+    known_response_parser(command, s)
+        .or_else(|_| map(extension::response::unknown_parser, Response::Unknown)(s))
+}
+
+fn known_response_parser<'a>(command: &Command, s: &'a [u8]) -> IResult<&'a [u8], Response<'a>> {
+    match command {
+        Command::User(_) => map(authorization::response::user_parser, Response::User)(s),
+        Command::Pass(_) => map(authorization::response::pass_parser, Response::Pass)(s),
+        Command::Apop(_) => map(authorization::response::apop_parser, Response::Apop)(s),
+        Command::Stls(_) => map(authorization::response::stls_parser, Response::Stls)(s),
+        Command::Utf8(_) => map(authorization::response::utf8_parser, Response::Utf8)(s),
+        Command::Auth(_) | Command::AuthResponse(_) => alt((
+            map(
+                authorization::response::auth_challenge_parser,
+                Response::AuthChallenge,
+            ),
+            map(authorization::response::auth_parser, Response::Auth),
+        ))(s),
+        Command::Stat(_) => map(transaction::response::stat_parser, Response::Stat)(s),
+        Command::List(_) => map(transaction::response::list_parser, Response::List)(s),
+        Command::Retr(_) => map(transaction::response::retr_parser, Response::Retr)(s),
+        Command::Dele(_) => map(transaction::response::dele_parser, Response::Dele)(s),
+        Command::Noop(_) => map(transaction::response::noop_parser, Response::Noop)(s),
+        Command::Rset(_) => map(transaction::response::rset_parser, Response::Rset)(s),
+        Command::Quit(_) => map(update::response::quit_parser, Response::Quit)(s),
+        Command::Top(_) => map(transaction::response::top_parser, Response::Top)(s),
+        Command::Uidl(_) => map(transaction::response::uidl_parser, Response::Uidl)(s),
+        Command::Capa(_) => map(extension::response::capa_parser, Response::Capa)(s),
+        Command::Lang(x) if x.tag.is_some() => {
+            map(extension::response::lang_selected_parser, Response::Lang)(s)
+        }
+        Command::Lang(_) => map(extension::response::lang_parser, Response::Lang)(s),
+        Command::Unknown(_) => map(extension::response::unknown_parser, Response::Unknown)(s),
+        Command::Custom(x) => map(|s| registry::response_parser(x.shape, s), Response::Custom)(s),
+        #[cfg(feature = "legacy")]
+        Command::Last(_) => map(crate::legacy::response::last_parser, Response::Last)(s),
+        #[cfg(feature = "legacy")]
+        Command::Rpop(_) => map(crate::legacy::response::rpop_parser, Response::Rpop)(s),
+        #[cfg(feature = "legacy")]
+        Command::Xtnd(crate::types::command::Xtnd::Xlst { .. }) => {
+            map(crate::legacy::response::xlst_parser, Response::Xtnd)(s)
+        }
+        #[cfg(feature = "legacy")]
+        Command::Xtnd(_) | Command::XmitMessage(_) => {
+            map(crate::legacy::response::xmit_parser, Response::Xtnd)(s)
+        }
+    }
+}
+
+/// A command and the response which answers it
+#[derive(Debug, PartialEq)]
+pub struct Exchange<'a> {
+    pub command: Command<'a>,
+    /// The command line as sent by the client, including the CRLF pair
+    pub raw_command: &'a [u8],
+    /// None if the server did not answer or the answer could not be parsed
+    pub response: Option<Response<'a>>,
+    /// Whether UTF-8 mode was active when the command was sent
+    pub utf8: bool,
+}
+
+impl Exchange<'_> {
+    /// Decodes an argument of the command or text of the response, as
+    /// UTF-8 if UTF-8 mode was active, as US-ASCII otherwise.
+    ///
+    /// **Examples**
+    ///
+    /// ```rust
+    /// use rfc1939::session::Session;
+    /// use rfc1939::types::command::Command;
+    ///
+    /// let session = Session::parse(
+    ///     "UTF8\r\nUSER José\r\n".as_bytes(),
+    ///     b"+OK POP3 server ready\r\n+OK UTF8 enabled\r\n+OK\r\n",
+    /// );
+    /// let exchange = &session.exchanges[1];
+    /// let Command::User(user) = &exchange.command else { panic!() };
+    /// assert_eq!(exchange.decode(user.name), "José");
+    /// ```
+    pub fn decode<'b>(&self, s: &'b [u8]) -> Cow<'b, str> {
+        state::decode(self.utf8, s)
+    }
+}
+
+/// Session
+///
+/// Option<SocketAddr>, address of the client, if known
+///
+/// Option<SocketAddr>, address of the server, if known
+///
+/// Option<Greeting>, the one line greeting issued by the server
+///
+/// Vec<Exchange>, commands in the order sent, with their responses
+///
+/// State, the state of the session after the last exchange
+///
+/// &[u8], client bytes which could not be parsed as a command
+///
+/// &[u8], server bytes which were not consumed by any response
+#[derive(Debug, PartialEq, Default)]
+pub struct Session<'a> {
+    pub client: Option<SocketAddr>,
+    pub server: Option<SocketAddr>,
+    pub greeting: Option<Greeting<'a>>,
+    pub exchanges: Vec<Exchange<'a>>,
+    pub state: State,
+    pub client_remaining: &'a [u8],
+    pub server_remaining: &'a [u8],
+}
+
+impl<'a> Session<'a> {
+    /// Parses the bytes sent by the client and by the server over one
+    /// connection.
+    ///
+    /// The server bytes are split into responses with
+    /// [`response_length`](crate::framing::response_length) before they
+    /// are parsed, so that a parser never reads into the following
+    /// response; whether the response to an unknown command is
+    /// multi-line is guessed from the bytes following its status line.
+    /// Commands and responses this crate does not know are kept as
+    /// [`Command::Unknown`] and [`Response::Unknown`].  Parsing stops at the first client line
+    /// which is not a command line, or when the connection switches to
+    /// TLS after a successful STLS; the bytes left over in each direction
+    /// are kept in `client_remaining` and `server_remaining`.  After a
+    /// response which is not complete or not a status line, the following
+    /// commands have no response.
+    pub fn parse(client: &'a [u8], server: &'a [u8]) -> Session<'a> {
+        Session::parse_with(&Registry::default(), client, server)
+    }
+
+    /// Parses the bytes sent by the client and by the server over one
+    /// connection, as [`Session::parse`] does, knowing the commands
+    /// registered with `registry` as well.
+    pub fn parse_with(registry: &Registry, client: &'a [u8], server: &'a [u8]) -> Session<'a> {
+        let mut session = Session::default();
+        let mut server = server;
+        if let Ok((rest, greeting)) = greeting_parser(server) {
+            session.greeting = Some(greeting);
+            server = rest;
+        }
+        let mut client = client;
+        let mut lost = false;
+        while let Ok((rest, command)) = next_command_parser(registry, &session.state, client) {
+            let raw_command = &client[..client.len() - rest.len()];
+            client = rest;
+            // Once a response is not complete, or is not a response, the
+            // following responses cannot be found in the server bytes.
+            let response = match guessed_length(&command, server) {
+                Some(length) if !lost => match response_parser(&command, &server[..length]) {
+                    Ok((_, response)) => {
+                        server = &server[length..];
+                        Some(response)
+                    }
+                    Err(_) => None,
+                },
+                _ => None,
+            };
+            lost = response.is_none();
+            let utf8 = session.state.utf8;
+            let transition = match &response {
+                Some(response) => session.state.apply(&command, response),
+                None => Transition::Continue,
+            };
+            session.exchanges.push(Exchange {
+                command,
+                raw_command,
+                response,
+                utf8,
+            });
+            if transition == Transition::StartTls {
+                break;
+            }
+        }
+        session.client_remaining = client;
+        session.server_remaining = server;
+        session
+    }
+
+    /// Sets the addresses of both ends of the connection.
+    pub fn with_endpoints(mut self, client: SocketAddr, server: SocketAddr) -> Session<'a> {
+        self.client = Some(client);
+        self.server = Some(server);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::StatusIndicator;
+    use crate::types::{command, response};
+
+    #[test]
+    fn test_command() {
+        assert_eq!(
+            command(b"USER mrose\r\n").unwrap(),
+            Command::User(command::User { name: b"mrose" })
+        );
+        assert_eq!(command(b"QUIT\r\n").unwrap(), Command::Quit(command::Quit));
+        assert_eq!(
+            command(b"XSENDER 1\r\n").unwrap(),
+            Command::Unknown(command::Unknown {
+                keyword: b"XSENDER",
+                args: vec![b"1"]
+            })
+        );
+        assert_eq!(command(b"\x16\x03\x01\r\n"), None);
+    }
+
+    #[test]
+    fn test_session_parse() {
+        let session = Session::parse(
+            b"USER mrose\r\nPASS tanstaaf\r\nLIST\r\nQUIT\r\n",
+            b"+OK POP3 server ready\r\n+OK\r\n+OK maildrop has 2 messages\r\n\
+              +OK 2 messages\r\n1 120\r\n2 200\r\n.\r\n+OK bye\r\n",
+        );
+        assert_eq!(
+            session.greeting,
+            Some(Greeting {
+                status_indicator: StatusIndicator::OK,
+                information: b"POP3 server ready"
+            })
+        );
+        assert_eq!(session.exchanges.len(), 4);
+        assert_eq!(session.exchanges[1].raw_command, b"PASS tanstaaf\r\n");
+        assert_eq!(
+            session.exchanges[2].response,
+            Some(Response::List(response::List {
+                status_indicator: StatusIndicator::OK,
+                informations: vec![(1, 120), (2, 200)],
+                information: b"2 messages"
+            }))
+        );
+        assert_eq!(session.client_remaining, b"");
+        assert_eq!(session.server_remaining, b"");
+    }
+
+    #[test]
+    fn test_session_parse_stops_at_stls() {
+        let session = Session::parse(
+            b"CAPA\r\nSTLS\r\n\x16\x03\x01",
+            b"+OK ready\r\n+OK\r\nSTLS\r\n.\r\n+OK Begin TLS\r\n\x16\x03\x03",
+        );
+        assert_eq!(session.exchanges.len(), 2);
+        assert!(session.state.tls);
+        assert_eq!(session.client_remaining, b"\x16\x03\x01");
+        assert_eq!(session.server_remaining, b"\x16\x03\x03");
+    }
+
+    #[test]
+    fn test_session_parse_auth() {
+        let session = Session::parse(
+            b"AUTH CRAM-MD5\r\ndGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\nSTAT\r\n",
+            b"+OK ready\r\n+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n\
+              +OK maildrop locked and ready\r\n+OK 2 320\r\n",
+        );
+        assert_eq!(
+            session.exchanges[1].command,
+            Command::AuthResponse(command::AuthResponse::Data(
+                b"tim b913a602c7eda7a495b4e6e7334d3890".to_vec()
+            ))
+        );
+        assert_eq!(
+            session.exchanges[1].response,
+            Some(Response::Auth(response::Auth {
+                status_indicator: StatusIndicator::OK,
+                information: b"maildrop locked and ready"
+            }))
+        );
+        assert_eq!(session.exchanges.len(), 3);
+        assert_eq!(session.state.phase, state::Phase::Transaction);
+    }
+
+    #[test]
+    fn test_session_parse_utf8() {
+        let session = Session::parse(
+            "LANG\r\nLANG es\r\nUTF8\r\nUSER Jos\u{e9}\r\n".as_bytes(),
+            "+OK ready\r\n+OK Language listing follows\r\nen English\r\nes Espa\u{f1}ol\r\n.\r\n\
+             +OK Idioma cambiado\r\n+OK UTF8 enabled\r\n+OK Bienvenido Jos\u{e9}\r\n"
+                .as_bytes(),
+        );
+        assert_eq!(session.exchanges.len(), 4);
+        assert!(session.state.utf8);
+        let Some(Response::Lang(listing)) = &session.exchanges[0].response else {
+            panic!()
+        };
+        assert_eq!(listing.languages.len(), 2);
+        assert_eq!(
+            session.exchanges[1]
+                .response
+                .as_ref()
+                .unwrap()
+                .information(),
+            b"Idioma cambiado"
+        );
+        assert!(!session.exchanges[2].utf8);
+        let user = &session.exchanges[3];
+        assert!(user.utf8);
+        assert_eq!(
+            user.decode(user.response.as_ref().unwrap().information()),
+            "Bienvenido Jos\u{e9}"
+        );
+        assert_eq!(session.server_remaining, b"");
+    }
+
+    #[cfg(feature = "legacy")]
+    #[test]
+    fn test_session_parse_legacy() {
+        let session = Session::parse(
+            b"USER mrose\r\nRPOP mrose\r\nLAST\r\nXTND XLST Subject\r\nXTND XMIT\r\n\
+              Subject: hi\r\n\r\n..\r\n.\r\nQUIT\r\n",
+            b"+OK ready\r\n+OK\r\n+OK maildrop ready\r\n+OK 0\r\n\
+              +OK\r\n1 Subject: lunch\r\n.\r\n+OK Start sending message\r\n\
+              +OK Message sent\r\n+OK bye\r\n",
+        );
+        assert_eq!(session.exchanges.len(), 7);
+        assert_eq!(
+            session.exchanges[5].command,
+            Command::XmitMessage(command::XmitMessage {
+                message: b"Subject: hi\r\n\r\n.."
+            })
+        );
+        assert!(session.exchanges[5].response.as_ref().unwrap().is_ok());
+        assert_eq!(session.state.phase, state::Phase::Update);
+        assert_eq!(session.server_remaining, b"");
+    }
+
+    #[test]
+    fn test_session_parse_unknown_command() {
+        let session = Session::parse(
+            b"NOOP\r\nXSENDER 1\r\nXLIST\r\nXSENDER 2\r\nQUIT\r\n",
+            b"+OK\r\n+OK\r\n-ERR\r\n+OK\r\na\r\n..b\r\n.\r\n+OK <a@example.com>\r\n+OK\r\n",
+        );
+        assert_eq!(session.greeting.unwrap().information, b"");
+        assert_eq!(session.exchanges.len(), 5);
+        assert_eq!(session.exchanges[1].raw_command, b"XSENDER 1\r\n");
+        assert_eq!(
+            session.exchanges[1].response,
+            Some(Response::Unknown(response::Unknown {
+                status_indicator: StatusIndicator::ERR,
+                body: None,
+                information: b""
+            }))
+        );
+        assert_eq!(
+            session.exchanges[2].response,
+            Some(Response::Unknown(response::Unknown {
+                status_indicator: StatusIndicator::OK,
+                body: Some(b"a\r\n..b"),
+                information: b""
+            }))
+        );
+        assert_eq!(
+            session.exchanges[3]
+                .response
+                .as_ref()
+                .unwrap()
+                .information(),
+            b"<a@example.com>"
+        );
+        assert!(matches!(
+            session.exchanges[4].response,
+            Some(Response::Quit(_))
+        ));
+        assert_eq!(session.client_remaining, b"");
+        assert_eq!(session.server_remaining, b"");
+    }
+
+    #[test]
+    fn test_session_parse_negative_multi_line() {
+        let session = Session::parse(
+            b"RETR 9\r\nLIST\r\nQUIT\r\n",
+            b"+OK ready\r\n-ERR no such message\r\n+OK\r\n1 120\r\n.\r\n+OK bye\r\n",
+        );
+        assert_eq!(
+            session.exchanges[0].response,
+            Some(Response::Retr(response::Retr {
+                status_indicator: StatusIndicator::ERR,
+                message: None,
+                information: b"no such message"
+            }))
+        );
+        assert_eq!(
+            session.exchanges[1].response,
+            Some(Response::List(response::List {
+                status_indicator: StatusIndicator::OK,
+                informations: vec![(1, 120)],
+                information: b""
+            }))
+        );
+        assert!(matches!(
+            session.exchanges[2].response,
+            Some(Response::Quit(_))
+        ));
+        assert_eq!(session.server_remaining, b"");
+    }
+
+    #[test]
+    fn test_session_parse_empty_body() {
+        let session = Session::parse(
+            b"RETR 1\r\nNOOP\r\n",
+            b"+OK ready\r\n+OK 0 octets\r\n.\r\n+OK\r\n",
+        );
+        assert_eq!(
+            session.exchanges[0].response,
+            Some(Response::Retr(response::Retr {
+                status_indicator: StatusIndicator::OK,
+                message: Some(b""),
+                information: b"0 octets"
+            }))
+        );
+        assert!(matches!(
+            session.exchanges[1].response,
+            Some(Response::Noop(_))
+        ));
+        assert_eq!(session.server_remaining, b"");
+    }
+
+    #[test]
+    fn test_session_parse_unparsed_response() {
+        // The first +OK is the greeting. A RETR response without its
+        // termination line is never framed, and the responses after it
+        // cannot be correlated
+        let session = Session::parse(b"RETR 1\r\nNOOP\r\n", b"+OK\r\n+OK\r\n+OK\r\n");
+        assert_eq!(session.exchanges.len(), 2);
+        assert_eq!(session.exchanges[0].response, None);
+        assert_eq!(session.exchanges[1].response, None);
+        assert_eq!(session.server_remaining, b"+OK\r\n+OK\r\n");
+    }
+
+    #[test]
+    fn test_session_parse_stops_at_binary() {
+        let session = Session::parse(b"NOOP\r\n\x16\x03\x01\x02\x00", b"+OK\r\n+OK\r\n");
+        assert_eq!(session.exchanges.len(), 1);
+        assert_eq!(session.client_remaining, b"\x16\x03\x01\x02\x00");
+    }
+}
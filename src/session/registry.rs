@@ -0,0 +1,269 @@
+//! Commands a POP3 server adds privately, taught to the parser at run
+//! time rather than in this crate.
+//!
+//! Each command is registered with its keyword, a parser for its
+//! arguments, and the shape of the response it expects; the response
+//! is then correlated with the command like those of the standard
+//! commands.
+use crate::common::take_until_crlf_consume_crlf;
+use crate::extension::response::{unknown_multi_line_parser, unknown_one_line_parser};
+use crate::types::command::{Command, Custom};
+use crate::types::response::{self, Shape};
+use nom::{
+    bytes::complete::take_while1,
+    combinator::{all_consuming, map},
+    error::{Error, ErrorKind},
+    IResult,
+};
+
+/// Parses the arguments of a command: the text following the keyword
+/// and a single space, up to the CRLF pair, which must be consumed
+/// entirely
+pub type ArgumentsParser = for<'a> fn(&'a [u8]) -> IResult<&'a [u8], Vec<&'a [u8]>>;
+
+#[derive(Debug, Clone)]
+struct Registration {
+    keyword: Vec<u8>,
+    arguments: ArgumentsParser,
+    shape: Shape,
+}
+
+/// Commands registered in addition to those this crate knows
+///
+/// **Examples**
+///
+/// ```rust
+/// use nom::{bytes::complete::tag, character::complete::digit1, multi::separated_list1};
+/// use rfc1939::session::{registry::Registry, Session};
+/// use rfc1939::types::{command::Command, response::{Response, Shape}};
+///
+/// let mut registry = Registry::new();
+/// // XSENDER msg
+/// registry.register(b"XSENDER", |s| separated_list1(tag(" "), digit1)(s), Shape::OneLine);
+///
+/// let session = Session::parse_with(
+///     &registry,
+///     b"XSENDER 1\r\n",
+///     b"+OK POP3 server ready\r\n+OK <chris@example.com>\r\n",
+/// );
+/// let Command::Custom(command) = &session.exchanges[0].command else { panic!() };
+/// assert_eq!(command.args, vec![b"1"]);
+/// let Some(Response::Custom(response)) = &session.exchanges[0].response else { panic!() };
+/// assert_eq!(response.information, b"<chris@example.com>");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    registrations: Vec<Registration>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    /// Registers the command `keyword`, case-insensitive, replacing any
+    /// earlier registration of it.  Registered commands take precedence
+    /// over those this crate knows.
+    pub fn register(&mut self, keyword: &[u8], arguments: ArgumentsParser, shape: Shape) {
+        self.registrations
+            .retain(|x| !x.keyword.eq_ignore_ascii_case(keyword));
+        self.registrations.push(Registration {
+            keyword: keyword.to_vec(),
+            arguments,
+            shape,
+        });
+    }
+
+    /// Whether `keyword` is registered
+    pub fn contains(&self, keyword: &[u8]) -> bool {
+        self.registration(keyword).is_some()
+    }
+
+    fn registration(&self, keyword: &[u8]) -> Option<&Registration> {
+        self.registrations
+            .iter()
+            .find(|x| x.keyword.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Parses a registered command, or any command a POP3 client may
+    /// send.
+    pub fn command<'a>(&self, s: &'a [u8]) -> Option<Command<'a>> {
+        match self.command_parser(s) {
+            Ok((_, x)) => Some(x),
+            Err(_) => None,
+        }
+    }
+
+    pub(crate) fn command_parser<'a>(&self, s: &'a [u8]) -> IResult<&'a [u8], Command<'a>> {
+        self.custom_parser(s).or_else(|_| super::command_parser(s))
+    }
+
+    fn custom_parser<'a>(&self, s: &'a [u8]) -> IResult<&'a [u8], Command<'a>> {
+        let fail = || nom::Err::Error(Error::new(s, ErrorKind::Verify));
+        let (rest, keyword) =
+            take_while1(|x: u8| x.is_ascii_alphanumeric() || x == b'-' || x == b'_')(s)?;
+        let registration = self.registration(keyword).ok_or_else(fail)?;
+        let (rest, line) = take_until_crlf_consume_crlf(rest)?;
+        let line = match line {
+            [] => line,
+            [b' ', arguments @ ..] => arguments,
+            _ => return Err(fail()),
+        };
+        let (_, args) = all_consuming(registration.arguments)(line).map_err(|_| fail())?;
+        Ok((
+            rest,
+            Command::Custom(Custom {
+                keyword,
+                args,
+                shape: registration.shape,
+            }),
+        ))
+    }
+}
+
+/// Parses the response to a registered command, of the given shape.
+pub(crate) fn response_parser(shape: Shape, s: &[u8]) -> IResult<&[u8], response::Custom<'_>> {
+    let parser = match shape {
+        Shape::OneLine => unknown_one_line_parser,
+        Shape::MultiLine => unknown_multi_line_parser,
+    };
+    map(parser, |x| response::Custom {
+        status_indicator: x.status_indicator,
+        body: x.body,
+        information: x.information,
+    })(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::StatusIndicator;
+    use crate::session::{response, Session};
+    use crate::types::command::{Encode, Unknown};
+    use crate::types::response::Response;
+    use nom::{
+        bytes::complete::tag, character::complete::digit1, combinator::opt, multi::separated_list1,
+    };
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.register(
+            b"XSENDER",
+            |s| separated_list1(tag(" "), digit1)(s),
+            Shape::OneLine,
+        );
+        registry.register(
+            b"XLIST",
+            |s| map(opt(digit1), |x| x.into_iter().collect())(s),
+            Shape::MultiLine,
+        );
+        registry
+    }
+
+    #[test]
+    fn test_command() {
+        let registry = registry();
+        assert!(registry.contains(b"xsender"));
+        assert_eq!(
+            registry.command(b"xsender 1\r\n").unwrap(),
+            Command::Custom(Custom {
+                keyword: b"xsender",
+                args: vec![b"1"],
+                shape: Shape::OneLine
+            })
+        );
+        assert_eq!(
+            registry.command(b"XLIST\r\n").unwrap(),
+            Command::Custom(Custom {
+                keyword: b"XLIST",
+                args: vec![],
+                shape: Shape::MultiLine
+            })
+        );
+        // Arguments the parser rejects
+        assert_eq!(
+            registry.command(b"XSENDER a\r\n").unwrap(),
+            Command::Unknown(Unknown {
+                keyword: b"XSENDER",
+                args: vec![b"a"]
+            })
+        );
+        assert_eq!(registry.command(b"XSENDER 1"), None);
+        assert!(matches!(
+            registry.command(b"NOOP\r\n").unwrap(),
+            Command::Noop(_)
+        ));
+        assert_eq!(
+            registry
+                .command(b"XSENDER 1 2\r\n")
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+            b"XSENDER 1 2\r\n"
+        );
+    }
+
+    #[test]
+    fn test_register_replaces() {
+        let mut registry = registry();
+        registry.register(b"xlist", |s| Ok((&s[s.len()..], vec![s])), Shape::OneLine);
+        assert_eq!(
+            registry.command(b"XLIST a b\r\n").unwrap(),
+            Command::Custom(Custom {
+                keyword: b"XLIST",
+                args: vec![b"a b"],
+                shape: Shape::OneLine
+            })
+        );
+    }
+
+    #[test]
+    fn test_response() {
+        let registry = registry();
+        let command = registry.command(b"XLIST\r\n").unwrap();
+        assert_eq!(
+            response(&command, b"+OK\r\n+OK\r\n.\r\n").unwrap(),
+            Response::Custom(response::Custom {
+                status_indicator: StatusIndicator::OK,
+                body: Some(b"+OK"),
+                information: b""
+            })
+        );
+        assert_eq!(
+            response(&command, b"-ERR\r\n").unwrap(),
+            Response::Custom(response::Custom {
+                status_indicator: StatusIndicator::ERR,
+                body: None,
+                information: b""
+            })
+        );
+    }
+
+    #[test]
+    fn test_session_parse_with() {
+        let session = Session::parse_with(
+            &registry(),
+            b"XLIST\r\nXSENDER 1\r\nQUIT\r\n",
+            b"+OK\r\n+OK\r\n1 a\r\n.\r\n+OK\r\n.\r\n+OK\r\n",
+        );
+        assert_eq!(session.exchanges.len(), 3);
+        assert_eq!(
+            session.exchanges[0].response,
+            Some(Response::Custom(response::Custom {
+                status_indicator: StatusIndicator::OK,
+                body: Some(b"1 a"),
+                information: b""
+            }))
+        );
+        // The lines after a one-line response are not its body
+        assert_eq!(
+            session.exchanges[1].response,
+            Some(Response::Custom(response::Custom {
+                status_indicator: StatusIndicator::OK,
+                body: None,
+                information: b""
+            }))
+        );
+        assert_eq!(session.server_remaining, b".\r\n+OK\r\n");
+    }
+}
@@ -0,0 +1,206 @@
+//! Tracking of the state a POP3 session is in, as commands are answered.
+use crate::types::command::Command;
+use crate::types::response::Response;
+use std::borrow::Cow;
+
+/// The states of a POP3 session (RFC 1939, section 3)
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Phase {
+    #[default]
+    Authorization,
+    Transaction,
+    Update,
+}
+
+/// What follows an exchange on the connection
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Transition {
+    /// More POP3 commands and responses follow
+    Continue,
+    /// The bytes following the response, in both directions, belong to a
+    /// TLS handshake and then to the encrypted session
+    StartTls,
+    /// The server closes the connection
+    Close,
+}
+
+/// State
+///
+/// Phase, the state of the session as defined by RFC 1939
+///
+/// bool, whether the connection was switched to TLS with STLS
+///
+/// bool, whether an AUTH exchange is in progress, in which case the
+/// next client line is a response to a challenge rather than a command
+///
+/// bool, whether UTF-8 mode was enabled with UTF8 (RFC 6856)
+///
+/// bool, whether the server accepted XTND XMIT, in which case the next
+/// client bytes are the message to deliver rather than a command; only
+/// with the `legacy` feature
+#[derive(Debug, PartialEq, Clone, Default)]
+#[non_exhaustive]
+pub struct State {
+    pub phase: Phase,
+    pub tls: bool,
+    pub authenticating: bool,
+    pub utf8: bool,
+    #[cfg(feature = "legacy")]
+    pub transmitting: bool,
+}
+
+impl State {
+    /// Applies `command` and the server's `response` to it.
+    ///
+    /// **Examples**
+    ///
+    /// ```rust
+    /// use rfc1939::session::state::{State, Transition};
+    /// use rfc1939::session::{command, response};
+    ///
+    /// let mut state = State::default();
+    /// let stls = command(b"STLS\r\n").unwrap();
+    /// let ok = response(&stls, b"+OK Begin TLS negotiation\r\n").unwrap();
+    /// assert_eq!(state.apply(&stls, &ok), Transition::StartTls);
+    /// assert!(state.tls);
+    /// ```
+    pub fn apply(&mut self, command: &Command, response: &Response) -> Transition {
+        self.authenticating = matches!(response, Response::AuthChallenge(_))
+            && matches!(command, Command::Auth(_) | Command::AuthResponse(_));
+        #[cfg(feature = "legacy")]
+        {
+            self.transmitting = matches!(command, Command::Xtnd(crate::types::command::Xtnd::Xmit))
+                && response.is_ok();
+        }
+        if !response.is_ok() {
+            return Transition::Continue;
+        }
+        match (self.phase, command) {
+            (
+                Phase::Authorization,
+                Command::Pass(_) | Command::Apop(_) | Command::Auth(_) | Command::AuthResponse(_),
+            ) => {
+                self.phase = Phase::Transaction;
+                Transition::Continue
+            }
+            (Phase::Authorization, Command::Stls(_)) if !self.tls => {
+                self.tls = true;
+                Transition::StartTls
+            }
+            #[cfg(feature = "legacy")]
+            (Phase::Authorization, Command::Rpop(_)) => {
+                self.phase = Phase::Transaction;
+                Transition::Continue
+            }
+            (Phase::Authorization, Command::Utf8(_)) => {
+                self.utf8 = true;
+                Transition::Continue
+            }
+            (Phase::Authorization, Command::Quit(_)) => Transition::Close,
+            (Phase::Transaction, Command::Quit(_)) => {
+                self.phase = Phase::Update;
+                Transition::Close
+            }
+            _ => Transition::Continue,
+        }
+    }
+
+    /// Decodes an argument or text exchanged in the current mode, see
+    /// [`Exchange::decode`](super::Exchange::decode).
+    pub fn decode<'a>(&self, s: &'a [u8]) -> Cow<'a, str> {
+        decode(self.utf8, s)
+    }
+}
+
+/// Decodes `s` as UTF-8 in UTF-8 mode, as US-ASCII otherwise. Invalid
+/// sequences, and any octet above 127 outside UTF-8 mode, are replaced
+/// with U+FFFD.
+pub(crate) fn decode(utf8: bool, s: &[u8]) -> Cow<'_, str> {
+    if utf8 || s.is_ascii() {
+        String::from_utf8_lossy(s)
+    } else {
+        Cow::Owned(
+            s.iter()
+                .map(|x| if x.is_ascii() { *x as char } else { '\u{fffd}' })
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{command, response};
+    use crate::types::command::AuthResponse;
+
+    fn apply(state: &mut State, c: &[u8], r: &[u8]) -> Transition {
+        let c = command(c).unwrap();
+        let r = response(&c, r).unwrap();
+        state.apply(&c, &r)
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut state = State::default();
+        assert_eq!(
+            apply(&mut state, b"USER mrose\r\n", b"+OK\r\n"),
+            Transition::Continue
+        );
+        assert_eq!(state.phase, Phase::Authorization);
+        assert_eq!(
+            apply(&mut state, b"PASS x\r\n", b"-ERR\r\n"),
+            Transition::Continue
+        );
+        assert_eq!(state.phase, Phase::Authorization);
+        assert_eq!(
+            apply(&mut state, b"STLS\r\n", b"-ERR\r\n"),
+            Transition::Continue
+        );
+        assert!(!state.tls);
+        assert_eq!(
+            apply(&mut state, b"STLS\r\n", b"+OK\r\n"),
+            Transition::StartTls
+        );
+        assert!(state.tls);
+        assert_eq!(
+            apply(&mut state, b"PASS x\r\n", b"+OK\r\n"),
+            Transition::Continue
+        );
+        assert_eq!(state.phase, Phase::Transaction);
+        assert_eq!(
+            apply(&mut state, b"STLS\r\n", b"+OK\r\n"),
+            Transition::Continue
+        );
+        assert_eq!(
+            apply(&mut state, b"QUIT\r\n", b"+OK\r\n"),
+            Transition::Close
+        );
+        assert_eq!(state.phase, Phase::Update);
+    }
+
+    #[test]
+    fn test_apply_utf8() {
+        let mut state = State::default();
+        assert_eq!(state.decode("é".as_bytes()), "\u{fffd}\u{fffd}");
+        apply(&mut state, b"UTF8\r\n", b"-ERR\r\n");
+        assert!(!state.utf8);
+        apply(&mut state, b"UTF8\r\n", b"+OK\r\n");
+        assert!(state.utf8);
+        assert_eq!(state.decode("é".as_bytes()), "é");
+        assert_eq!(state.decode(b"ok"), "ok");
+    }
+
+    #[test]
+    fn test_apply_auth() {
+        let mut state = State::default();
+        let auth = command(b"AUTH LOGIN\r\n").unwrap();
+        let challenge = response(&auth, b"+ VXNlcm5hbWU6\r\n").unwrap();
+        assert_eq!(state.apply(&auth, &challenge), Transition::Continue);
+        assert!(state.authenticating);
+        let cancel = Command::AuthResponse(AuthResponse::Cancel);
+        let err = response(&cancel, b"-ERR cancelled\r\n").unwrap();
+        assert_eq!(state.apply(&cancel, &err), Transition::Continue);
+        assert!(!state.authenticating);
+        assert_eq!(state.phase, Phase::Authorization);
+    }
+}
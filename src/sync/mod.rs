@@ -0,0 +1,444 @@
+//! Fetching new messages only, leaving mail on the server.
+//!
+//! The messages of a maildrop are identified by their unique-id listing
+//! (UIDL), and compared with a [`SeenSet`] persisted between sessions:
+//! unseen messages are retrieved and delivered, then marked as seen.
+//! Messages seen more than a given time ago may be deleted.
+//!
+//! Servers without UIDL are handled by identifying each message by the
+//! SHA-256 hash of its headers, as returned by TOP with no lines of the
+//! body, and of its size, as returned by LIST.  Messages with identical
+//! headers and size, e.g. the same message delivered twice, have the
+//! same hash; the hash of each repetition in the listing is followed by
+//! its occurrence, e.g. "-1" for the second message, so that every
+//! message is delivered, and deleted, on its own.  As the occurrence
+//! depends on the position in the listing, deleting such a message from
+//! the server passes its unique-id, and the time it was first seen, on
+//! to the next identical message.
+//!
+//! **Examples**
+//!
+//! ```rust,no_run
+//! use rfc1939::client::Client;
+//! use rfc1939::sync::{sync, Policy, SeenSet};
+//! use std::net::TcpStream;
+//! use std::time::{Duration, SystemTime};
+//!
+//! let mut client = Client::new(TcpStream::connect("pop.example.com:110")?)?;
+//! client.login(b"mrose", b"tanstaaf")?;
+//! let mut seen = SeenSet::load("seen")?;
+//! let policy = Policy {
+//!     delete_after: Some(Duration::from_secs(30 * 24 * 60 * 60)),
+//! };
+//! let report = sync(&mut client, &mut seen, &policy, SystemTime::now(), |uid, message| {
+//!     // ...
+//!     Ok(())
+//! });
+//! seen.save("seen")?;
+//! report?;
+//! client.quit()?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+use crate::client::{Client, Error};
+use crate::framing::unstuff;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The unique-ids of the messages seen, with when each was first seen
+///
+/// The file format has one line per message, the unique-id and the
+/// seconds since the Unix epoch separated by a space, e.g.
+/// "whqtswO00WBw418f9t5JxYwZ 1700000000".
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct SeenSet {
+    seen: BTreeMap<Vec<u8>, u64>,
+}
+
+impl SeenSet {
+    pub fn new() -> SeenSet {
+        SeenSet::default()
+    }
+
+    /// Reads the set from `path`, empty if the file does not exist.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SeenSet> {
+        match File::open(path) {
+            Ok(file) => SeenSet::read(BufReader::new(file)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SeenSet::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the set to `path` durably, replacing the previous file at
+    /// once.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp)?;
+        self.write(&mut file)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        if let Some(parent) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+            File::open(parent)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    pub fn read<R: BufRead>(reader: R) -> io::Result<SeenSet> {
+        let mut seen = SeenSet::new();
+        for line in reader.split(b'\n') {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed seen set");
+            let space = line.iter().position(|x| *x == b' ').ok_or_else(malformed)?;
+            let seconds = std::str::from_utf8(&line[space + 1..])
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .ok_or_else(malformed)?;
+            seen.seen.insert(line[..space].to_vec(), seconds);
+        }
+        Ok(seen)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        for (uid, seconds) in &self.seen {
+            writer.write_all(uid)?;
+            writeln!(writer, " {}", seconds)?;
+        }
+        writer.flush()
+    }
+
+    pub fn contains(&self, uid: &[u8]) -> bool {
+        self.seen.contains_key(uid)
+    }
+
+    /// Marks `uid` as seen at `now`, unless it was seen before.
+    pub fn insert(&mut self, uid: &[u8], now: SystemTime) {
+        self.seen
+            .entry(uid.to_vec())
+            .or_insert_with(|| seconds(now));
+    }
+
+    /// When `uid` was first seen
+    pub fn first_seen(&self, uid: &[u8]) -> Option<SystemTime> {
+        self.seen
+            .get(uid)
+            .map(|x| UNIX_EPOCH + Duration::from_secs(*x))
+    }
+
+    /// Forgets the unique-ids for which `f` returns false.
+    pub fn retain<F: FnMut(&[u8]) -> bool>(&mut self, mut f: F) {
+        self.seen.retain(|uid, _| f(uid));
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs())
+}
+
+/// What to do besides retrieving new messages
+///
+/// Option<Duration>, delete the messages first seen longer ago than
+/// this, None to leave them on the server
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Policy {
+    pub delete_after: Option<Duration>,
+}
+
+/// What a sync did
+///
+/// Vec<Vec<u8>>, the unique-ids of the messages retrieved
+///
+/// Vec<Vec<u8>>, the unique-ids of the messages deleted
+///
+/// bool, whether the unique-ids are hashes of the headers and sizes,
+/// as the server does not support UIDL
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Report {
+    pub retrieved: Vec<Vec<u8>>,
+    pub deleted: Vec<Vec<u8>>,
+    pub hashed: bool,
+}
+
+/// Retrieves the messages not in `seen` and hands them to `deliver`,
+/// with their unique-id, then deletes the messages `policy` expires.
+///
+/// A message is marked as seen once `deliver` succeeds, and the set is
+/// updated in place, so that it can be saved even if the sync fails
+/// halfway.  Unique-ids no longer on the server are forgotten.  The
+/// messages are unstuffed.
+pub fn sync<S, F>(
+    client: &mut Client<S>,
+    seen: &mut SeenSet,
+    policy: &Policy,
+    now: SystemTime,
+    mut deliver: F,
+) -> Result<Report, Error>
+where
+    S: Read + Write,
+    F: FnMut(&[u8], &[u8]) -> io::Result<()>,
+{
+    let mut report = Report::default();
+    let messages = match client.uidl(None) {
+        Ok(uidl) => uidl
+            .informations
+            .into_iter()
+            .map(|(msg, uid)| (msg, uid.to_vec()))
+            .collect(),
+        Err(Error::Negative(_)) => {
+            report.hashed = true;
+            headers_hashes(client)?
+        }
+        Err(e) => return Err(e),
+    };
+
+    for (msg, uid) in &messages {
+        if seen.contains(uid) {
+            continue;
+        }
+        let retr = client.retr(*msg)?;
+        deliver(uid, &unstuff(retr.message.unwrap_or_default()))?;
+        seen.insert(uid, now);
+        report.retrieved.push(uid.clone());
+    }
+
+    if let Some(delete_after) = policy.delete_after {
+        for (msg, uid) in &messages {
+            let expired = seen
+                .first_seen(uid)
+                .and_then(|x| now.duration_since(x).ok())
+                .is_some_and(|x| x >= delete_after);
+            if expired {
+                client.dele(*msg)?;
+                report.deleted.push(uid.clone());
+            }
+        }
+    }
+
+    let uids: BTreeSet<&[u8]> = messages.iter().map(|(_, x)| &x[..]).collect();
+    seen.retain(|uid| uids.contains(uid));
+    Ok(report)
+}
+
+/// Identifies the messages by the hash of their headers and size,
+/// unique within the listing.
+fn headers_hashes<S: Read + Write>(client: &mut Client<S>) -> Result<Vec<(usize, Vec<u8>)>, Error> {
+    let msgs = client.list(None)?.informations;
+    let mut occurrences = BTreeMap::new();
+    let mut messages = vec![];
+    for (msg, size) in msgs {
+        let top = client.top(msg, 0)?;
+        let hash = Sha256::new()
+            .chain_update(top.message.unwrap_or_default())
+            .chain_update(format!(" {}", size))
+            .finalize();
+        let mut uid = hash
+            .iter()
+            .map(|x| format!("{:02x}", x))
+            .collect::<String>();
+        let occurrence = occurrences.entry(uid.clone()).or_insert(0);
+        if *occurrence > 0 {
+            uid.push_str(&format!("-{}", occurrence));
+        }
+        *occurrence += 1;
+        messages.push((msg, uid.into_bytes()));
+    }
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::tests::Mock;
+
+    const DAY: u64 = 24 * 60 * 60;
+
+    fn day(n: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(n * DAY)
+    }
+
+    #[test]
+    fn test_seen_set() {
+        let mut seen = SeenSet::read(&b"b 86400\na 0\n"[..]).unwrap();
+        assert!(seen.contains(b"a"));
+        assert_eq!(seen.first_seen(b"b"), Some(day(1)));
+        seen.insert(b"b", day(2));
+        seen.insert(b"c", day(2));
+        let mut buf = vec![];
+        seen.write(&mut buf).unwrap();
+        assert_eq!(buf, b"a 0\nb 86400\nc 172800\n");
+        assert!(SeenSet::read(&b"a\n"[..]).is_err());
+        assert!(SeenSet::read(&b"a b\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_seen_set_save() {
+        let path = std::env::temp_dir().join(format!("rfc1939-seen-{}", std::process::id()));
+        assert!(SeenSet::load(&path).unwrap().is_empty());
+        let mut seen = SeenSet::new();
+        seen.insert(b"whqtswO00WBw418f9t5JxYwZ", day(1));
+        seen.save(&path).unwrap();
+        assert_eq!(SeenSet::load(&path).unwrap(), seen);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sync() {
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n+OK\r\n1 a\r\n2 b\r\n3 c\r\n.\r\n+OK\r\nSubject: c\r\n\r\n..d\r\n.\r\n+OK\r\n",
+        ))
+        .unwrap();
+        let mut seen = SeenSet::read(&b"a 0\nb 172800\nz 0\n"[..]).unwrap();
+        let policy = Policy {
+            delete_after: Some(Duration::from_secs(30 * DAY)),
+        };
+        let mut delivered = vec![];
+        let report = sync(&mut client, &mut seen, &policy, day(31), |uid, message| {
+            delivered.push((uid.to_vec(), message.to_vec()));
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            delivered,
+            vec![(b"c".to_vec(), b"Subject: c\r\n\r\n.d".to_vec())]
+        );
+        assert_eq!(report.retrieved, vec![b"c".to_vec()]);
+        assert_eq!(report.deleted, vec![b"a".to_vec()]);
+        assert!(!report.hashed);
+        assert!(!seen.contains(b"z"));
+        assert_eq!(seen.first_seen(b"c"), Some(day(31)));
+        assert_eq!(client.get_ref().output, b"UIDL\r\nRETR 3\r\nDELE 1\r\n");
+    }
+
+    #[test]
+    fn test_sync_delivery_error() {
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n+OK\r\n1 a\r\n2 b\r\n.\r\n+OK\r\nSubject: a\r\n.\r\n\
+              +OK\r\nSubject: b\r\n.\r\n",
+        ))
+        .unwrap();
+        let mut seen = SeenSet::new();
+        let result = sync(
+            &mut client,
+            &mut seen,
+            &Policy::default(),
+            day(1),
+            |uid, _| match uid {
+                b"a" => Ok(()),
+                _ => Err(io::Error::new(io::ErrorKind::StorageFull, "full")),
+            },
+        );
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert!(seen.contains(b"a"));
+        assert!(!seen.contains(b"b"));
+    }
+
+    #[test]
+    fn test_sync_without_uidl() {
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n-ERR\r\n+OK\r\n1 120\r\n.\r\n+OK\r\nSubject: a\r\n.\r\n\
+              +OK\r\nSubject: a\r\n\r\nb\r\n.\r\n",
+        ))
+        .unwrap();
+        let mut seen = SeenSet::new();
+        let report = sync(
+            &mut client,
+            &mut seen,
+            &Policy::default(),
+            day(1),
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        assert!(report.hashed);
+        // SHA-256 of "Subject: a 120"
+        assert_eq!(
+            report.retrieved,
+            vec![b"1c6d8d9cc90358614d781844494f431085e4be7212f9022a2036c0ed87ab181e".to_vec()]
+        );
+        assert_eq!(
+            client.get_ref().output,
+            b"UIDL\r\nLIST\r\nTOP 1 0\r\nRETR 1\r\n"
+        );
+    }
+
+    #[test]
+    fn test_sync_without_uidl_duplicates() {
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n-ERR\r\n+OK\r\n1 120\r\n2 120\r\n.\r\n+OK\r\nSubject: a\r\n.\r\n\
+              +OK\r\nSubject: a\r\n.\r\n+OK\r\nSubject: a\r\n\r\nc\r\n.\r\n+OK\r\n",
+        ))
+        .unwrap();
+        // SHA-256 of "Subject: a 120"
+        let hash = b"1c6d8d9cc90358614d781844494f431085e4be7212f9022a2036c0ed87ab181e";
+        let mut seen = SeenSet::new();
+        seen.insert(hash, day(0));
+        let policy = Policy {
+            delete_after: Some(Duration::from_secs(30 * DAY)),
+        };
+        let mut delivered = vec![];
+        let report = sync(&mut client, &mut seen, &policy, day(31), |uid, message| {
+            delivered.push((uid.to_vec(), message.to_vec()));
+            Ok(())
+        })
+        .unwrap();
+        let second = [&hash[..], b"-1"].concat();
+        assert_eq!(
+            delivered,
+            vec![(second.clone(), b"Subject: a\r\n\r\nc".to_vec())]
+        );
+        assert_eq!(report.deleted, vec![hash.to_vec()]);
+        assert!(seen.contains(hash));
+        assert_eq!(seen.first_seen(&second), Some(day(31)));
+        assert_eq!(
+            client.get_ref().output,
+            b"UIDL\r\nLIST\r\nTOP 1 0\r\nTOP 2 0\r\nRETR 2\r\nDELE 1\r\n"
+        );
+    }
+
+    #[test]
+    fn test_sync_without_uidl_duplicate_headers() {
+        // SHA-256 of "Subject: a 120" and of "Subject: a 130"
+        let first = b"1c6d8d9cc90358614d781844494f431085e4be7212f9022a2036c0ed87ab181e";
+        let second = b"d783c49f1f42cd29c755bb7059adf2114393e7d2d999fc5f45b58c5a61785ea7";
+        let mut seen = SeenSet::new();
+        seen.insert(first, day(0));
+        let policy = Policy {
+            delete_after: Some(Duration::from_secs(30 * DAY)),
+        };
+
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n-ERR\r\n+OK\r\n1 120\r\n2 130\r\n.\r\n+OK\r\nSubject: a\r\n.\r\n\
+              +OK\r\nSubject: a\r\n.\r\n+OK\r\nSubject: a\r\n\r\nc\r\n.\r\n+OK\r\n",
+        ))
+        .unwrap();
+        let report = sync(&mut client, &mut seen, &policy, day(31), |_, _| Ok(())).unwrap();
+        assert_eq!(report.retrieved, vec![second.to_vec()]);
+        assert_eq!(report.deleted, vec![first.to_vec()]);
+
+        // the second message is now the first of the listing
+        let mut client = Client::new(Mock::new(
+            b"+OK\r\n-ERR\r\n+OK\r\n1 130\r\n.\r\n+OK\r\nSubject: a\r\n.\r\n",
+        ))
+        .unwrap();
+        let report = sync(&mut client, &mut seen, &policy, day(32), |_, _| Ok(())).unwrap();
+        assert!(report.retrieved.is_empty());
+        assert!(report.deleted.is_empty());
+        assert!(!seen.contains(first));
+        assert_eq!(seen.first_seen(second), Some(day(31)));
+        assert_eq!(client.get_ref().output, b"UIDL\r\nLIST\r\nTOP 1 0\r\n");
+    }
+}
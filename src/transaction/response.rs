@@ -28,14 +28,14 @@ use nom::{
 /// space, the number of messages in the maildrop, a single
 /// space, and the size of the maildrop in octets.
 // ################################################################################
-pub fn stat(s: &[u8]) -> Option<Stat> {
+pub fn stat(s: &[u8]) -> Option<Stat<'_>> {
     match stat_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn stat_parser(s: &[u8]) -> IResult<&[u8], Stat> {
+pub(crate) fn stat_parser(s: &[u8]) -> IResult<&[u8], Stat<'_>> {
     alt((
         map(
             tuple((
@@ -101,18 +101,18 @@ pub(crate) fn stat_parser(s: &[u8]) -> IResult<&[u8], Stat> {
 /// with a CRLF pair.  More advanced implementations may
 /// include other information, as parsed from the message.
 // ################################################################################
-pub fn list(s: &[u8]) -> Option<List> {
+pub fn list(s: &[u8]) -> Option<List<'_>> {
     match list_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn list_parser(s: &[u8]) -> IResult<&[u8], List> {
+pub(crate) fn list_parser(s: &[u8]) -> IResult<&[u8], List<'_>> {
     alt((list_multi_line_parser, list_one_line_parser))(s)
 }
 
-fn list_multi_line_parser(s: &[u8]) -> IResult<&[u8], List> {
+fn list_multi_line_parser(s: &[u8]) -> IResult<&[u8], List<'_>> {
     map(
         terminated(
             tuple((
@@ -143,7 +143,7 @@ fn list_multi_line_parser(s: &[u8]) -> IResult<&[u8], List> {
     )(s)
 }
 
-fn list_one_line_parser(s: &[u8]) -> IResult<&[u8], List> {
+fn list_one_line_parser(s: &[u8]) -> IResult<&[u8], List<'_>> {
     alt((
         map(
             terminated(
@@ -193,14 +193,14 @@ fn list_one_line_parser(s: &[u8]) -> IResult<&[u8], List> {
 /// 
 /// &nbsp;&nbsp;&nbsp;&nbsp;-ERR no such message
 // ################################################################################
-pub fn retr(s: &[u8]) -> Option<Retr> {
+pub fn retr(s: &[u8]) -> Option<Retr<'_>> {
     match retr_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn retr_parser(s: &[u8]) -> IResult<&[u8], Retr> {
+pub(crate) fn retr_parser(s: &[u8]) -> IResult<&[u8], Retr<'_>> {
     retr_message_parser::<Retr>(s)
 }
 
@@ -231,14 +231,14 @@ pub(crate) fn retr_parser(s: &[u8]) -> IResult<&[u8], Retr> {
 /// 
 /// &nbsp;&nbsp;&nbsp;&nbsp;-ERR no such message
 // ################################################################################
-pub fn top(s: &[u8]) -> Option<Top> {
+pub fn top(s: &[u8]) -> Option<Top<'_>> {
     match top_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn top_parser(s: &[u8]) -> IResult<&[u8], Top> {
+pub(crate) fn top_parser(s: &[u8]) -> IResult<&[u8], Top<'_>> {
     retr_message_parser::<Top>(s)
 }
 
@@ -269,14 +269,14 @@ pub(crate) fn top_parser(s: &[u8]) -> IResult<&[u8], Top> {
 /// 
 /// &nbsp;&nbsp;&nbsp;&nbsp;S: -ERR message 2 already deleted
 // ################################################################################
-pub fn dele(s: &[u8]) -> Option<Dele> {
+pub fn dele(s: &[u8]) -> Option<Dele<'_>> {
     match dele_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn dele_parser(s: &[u8]) -> IResult<&[u8], Dele> {
+pub(crate) fn dele_parser(s: &[u8]) -> IResult<&[u8], Dele<'_>> {
     one_line_response_two_parts_parser::<Dele>(s)
 }
 
@@ -300,14 +300,14 @@ pub(crate) fn dele_parser(s: &[u8]) -> IResult<&[u8], Dele> {
 /// 
 /// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK
 // ################################################################################
-pub fn noop(s: &[u8]) -> Option<Noop> {
+pub fn noop(s: &[u8]) -> Option<Noop<'_>> {
     match noop_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn noop_parser(s: &[u8]) -> IResult<&[u8], Noop> {
+pub(crate) fn noop_parser(s: &[u8]) -> IResult<&[u8], Noop<'_>> {
     one_line_response_two_parts_parser::<Noop>(s)
 }
 
@@ -332,14 +332,14 @@ pub(crate) fn noop_parser(s: &[u8]) -> IResult<&[u8], Noop> {
 /// 
 /// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK maildrop has 2 messages (320 octets)
 // ################################################################################
-pub fn rset(s: &[u8]) -> Option<Rset> {
+pub fn rset(s: &[u8]) -> Option<Rset<'_>> {
     match rset_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn rset_parser(s: &[u8]) -> IResult<&[u8], Rset> {
+pub(crate) fn rset_parser(s: &[u8]) -> IResult<&[u8], Rset<'_>> {
     one_line_response_two_parts_parser::<Rset>(s)
 }
 
@@ -378,7 +378,7 @@ pub(crate) fn rset_parser(s: &[u8]) -> IResult<&[u8], Rset> {
 /// using the unique-id exists.
 ///
 /// Note that messages marked as deleted are not listed.
-
+///
 /// While it is generally preferable for server implementations
 /// to store arbitrarily assigned unique-ids in the maildrop,
 /// this specification is intended to permit unique-ids to be
@@ -386,18 +386,18 @@ pub(crate) fn rset_parser(s: &[u8]) -> IResult<&[u8], Rset> {
 /// to handle a situation where two identical copies of a
 /// message in a maildrop have the same unique-id.
 // ################################################################################
-pub fn uidl(s: &[u8]) -> Option<Uidl> {
+pub fn uidl(s: &[u8]) -> Option<Uidl<'_>> {
     match uidl_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn uidl_parser(s: &[u8]) -> IResult<&[u8], Uidl> {
+pub(crate) fn uidl_parser(s: &[u8]) -> IResult<&[u8], Uidl<'_>> {
     alt((uidl_multi_line_parser, uidl_one_line_parser))(s)
 }
 
-fn uidl_multi_line_parser(s: &[u8]) -> IResult<&[u8], Uidl> {
+fn uidl_multi_line_parser(s: &[u8]) -> IResult<&[u8], Uidl<'_>> {
     map(
         terminated(
             tuple((
@@ -423,12 +423,12 @@ fn uidl_multi_line_parser(s: &[u8]) -> IResult<&[u8], Uidl> {
         |(si, information, infos)| Uidl {
             status_indicator: si,
             informations: infos,
-            information: information,
+            information,
         },
     )(s)
 }
 
-fn uidl_one_line_parser(s: &[u8]) -> IResult<&[u8], Uidl> {
+fn uidl_one_line_parser(s: &[u8]) -> IResult<&[u8], Uidl<'_>> {
     alt((
         map(
             terminated(
@@ -566,6 +566,34 @@ mod tests {
                 information: b"120 octets"
             }
         );
+        assert_eq!(
+            retr(b"+OK\r\nSubject: a\r\n.\r\n").unwrap(),
+            Retr {
+                status_indicator: StatusIndicator::OK,
+                message: Some(b"Subject: a"),
+                information: b""
+            }
+        );
+        assert_eq!(
+            retr(b"+OK 0 octets\r\n.\r\n").unwrap(),
+            Retr {
+                status_indicator: StatusIndicator::OK,
+                message: Some(b""),
+                information: b"0 octets"
+            }
+        );
+        // The body of the next response is not taken as a message
+        assert_eq!(
+            retr_parser(b"-ERR no such message\r\n+OK\r\n1 120\r\n.\r\n").unwrap(),
+            (
+                &b"+OK\r\n1 120\r\n.\r\n"[..],
+                Retr {
+                    status_indicator: StatusIndicator::ERR,
+                    message: None,
+                    information: b"no such message"
+                }
+            )
+        );
     }
 
     #[test]
@@ -619,7 +647,7 @@ mod tests {
             Uidl {
                 status_indicator: StatusIndicator::OK,
                 informations: vec![(1, b"whqtswO00WBw418f9t5JxYwZ"), (2, b"QhdPYR:00WBw1Ph7x7")],
-                information: &vec![]
+                information: &[]
             }
         );
         assert_eq!(
@@ -627,7 +655,7 @@ mod tests {
             Uidl {
                 status_indicator: StatusIndicator::OK,
                 informations: vec![(1, b"whqtswO00WBw418f9t5JxYwZ%")],
-                information: &vec![]
+                information: &[]
             }
         );
         assert_eq!(
@@ -639,4 +667,25 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_response_code() {
+        use crate::types::response::{Response, ResponseCode, SysCode};
+        let responses = [
+            Response::Stat(stat(b"-ERR [SYS/TEMP] maildrop busy\r\n").unwrap()),
+            Response::List(list(b"-ERR [SYS/TEMP] maildrop busy\r\n").unwrap()),
+            Response::Retr(retr(b"-ERR [SYS/TEMP] maildrop busy\r\n").unwrap()),
+            Response::Uidl(uidl(b"-ERR [SYS/TEMP] maildrop busy\r\n").unwrap()),
+        ];
+        for response in &responses {
+            assert_eq!(
+                response.response_code(),
+                Some(ResponseCode::Sys(SysCode::Temp))
+            );
+            assert_eq!(response.text(), b"maildrop busy");
+        }
+        let response = Response::Retr(retr(b"+OK 120 octets\r\n.\r\n").unwrap());
+        assert_eq!(response.response_code(), None);
+        assert_eq!(response.text(), b"120 octets");
+    }
 }
@@ -1,3 +1,9 @@
+use crate::sasl::saslprep::{self, saslprep};
+use crate::types::response::Shape;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use md5::{Digest, Md5};
+use std::fmt;
+
 #[derive(Debug, PartialEq)]
 pub struct Stat;
 
@@ -29,7 +35,7 @@ pub struct Rset;
 pub struct Quit;
 
 /// A message-number (required)
-/// 
+///
 /// A non-negative number of lines (required)
 #[derive(Debug, PartialEq)]
 pub struct Top {
@@ -56,10 +62,690 @@ pub struct Pass<'a> {
 }
 
 /// A string identifying a mailbox (required)
-/// 
+///
 /// A MD5 digest string (required)
 #[derive(Debug, PartialEq)]
 pub struct Apop<'a> {
     pub name: &'a [u8],
     pub digest: &'a [u8],
 }
+
+impl Apop<'_> {
+    /// Computes the digest of APOP, the lowercase hexadecimal MD5 hash
+    /// of the greeting `timestamp` followed by the shared `secret`.
+    ///
+    /// **Examples**
+    ///
+    /// ```rust
+    /// use rfc1939::types::command::Apop;
+    /// assert_eq!(
+    ///     Apop::digest(b"<1896.697170952@dbc.mtview.ca.us>", b"tanstaaf"),
+    ///     b"c4c9334bac560ecc979e58001b3e22fb"
+    /// );
+    /// ```
+    pub fn digest(timestamp: &[u8], secret: &[u8]) -> Vec<u8> {
+        Md5::new()
+            .chain_update(timestamp)
+            .chain_update(secret)
+            .finalize()
+            .iter()
+            .flat_map(|x| format!("{:02x}", x).into_bytes())
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Capa;
+
+#[derive(Debug, PartialEq)]
+pub struct Stls;
+
+#[derive(Debug, PartialEq)]
+pub struct Utf8;
+
+/// A language tag, or "*" for the client's default language (optional).
+/// None asks for the list of languages the server supports
+#[derive(Debug, PartialEq)]
+pub struct Lang<'a> {
+    pub tag: Option<&'a [u8]>,
+}
+
+/// A command this crate does not know, e.g. a vendor extension
+///
+/// A keyword (required)
+///
+/// Arguments, as separated by spaces (optional)
+#[derive(Debug, PartialEq)]
+pub struct Unknown<'a> {
+    pub keyword: &'a [u8],
+    pub args: Vec<&'a [u8]>,
+}
+
+/// A command registered with a
+/// [`Registry`](crate::session::registry::Registry)
+///
+/// A keyword (required)
+///
+/// Arguments, as returned by the parser it was registered with
+///
+/// The shape of the response the command expects
+#[derive(Debug, PartialEq)]
+pub struct Custom<'a> {
+    pub keyword: &'a [u8],
+    pub args: Vec<&'a [u8]>,
+    pub shape: Shape,
+}
+
+/// LAST (RFC 1460)
+#[cfg(feature = "legacy")]
+#[derive(Debug, PartialEq)]
+pub struct Last;
+
+/// A user id on the client host (required), RPOP (RFC 1081)
+#[cfg(feature = "legacy")]
+#[derive(Debug, PartialEq)]
+pub struct Rpop<'a> {
+    pub user: &'a [u8],
+}
+
+/// The XTND extensions of the Qualcomm popper
+#[cfg(feature = "legacy")]
+#[derive(Debug, PartialEq)]
+pub enum Xtnd<'a> {
+    /// XTND XMIT, the client sends a message for the server to deliver
+    Xmit,
+    /// XTND XLST *header* \[*msg*\], the values of a header field in one
+    /// or all messages
+    Xlst {
+        header: &'a [u8],
+        msg: Option<usize>,
+    },
+}
+
+/// The message a client sends after a positive response to XTND XMIT,
+/// byte-stuffed, without the CRLF pair and the line containing the
+/// termination octet which end it
+#[cfg(feature = "legacy")]
+#[derive(Debug, PartialEq)]
+pub struct XmitMessage<'a> {
+    pub message: &'a [u8],
+}
+
+/// A SASL mechanism name (required)
+///
+/// An initial response, decoded from base64 (optional). `Some(vec![])`
+/// stands for an empty initial response, sent as "="
+#[derive(Debug, PartialEq)]
+pub struct Auth<'a> {
+    pub mechanism: &'a [u8],
+    pub initial_response: Option<Vec<u8>>,
+}
+
+/// A line sent by the client in reply to a challenge of an AUTH exchange
+#[derive(Debug, PartialEq)]
+pub enum AuthResponse {
+    /// A response, decoded from base64
+    Data(Vec<u8>),
+    /// "*", the client cancels the exchange
+    Cancel,
+}
+
+/// Any command a POP3 client may send; the `legacy` feature adds
+/// variants
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Command<'a> {
+    User(User<'a>),
+    Pass(Pass<'a>),
+    Apop(Apop<'a>),
+    Stat(Stat),
+    List(List),
+    Retr(Retr),
+    Dele(Dele),
+    Noop(Noop),
+    Rset(Rset),
+    Quit(Quit),
+    Top(Top),
+    Uidl(Uidl),
+    Capa(Capa),
+    Stls(Stls),
+    Utf8(Utf8),
+    Lang(Lang<'a>),
+    Auth(Auth<'a>),
+    AuthResponse(AuthResponse),
+    Unknown(Unknown<'a>),
+    Custom(Custom<'a>),
+    #[cfg(feature = "legacy")]
+    Last(Last),
+    #[cfg(feature = "legacy")]
+    Rpop(Rpop<'a>),
+    #[cfg(feature = "legacy")]
+    Xtnd(Xtnd<'a>),
+    #[cfg(feature = "legacy")]
+    XmitMessage(XmitMessage<'a>),
+}
+
+/// Errors of encoding a command; the `legacy` feature adds variants
+#[derive(Debug, PartialEq, Clone)]
+#[non_exhaustive]
+pub enum Error {
+    /// An argument contains CR, LF or NUL, which would end the command
+    /// line early and have the rest read as another command
+    ControlCharacter,
+    /// An argument which has to be a single word contains a space
+    Space,
+    /// The message of XTND XMIT contains a line of a single ".", which
+    /// would end it early
+    #[cfg(feature = "legacy")]
+    Termination,
+    /// An argument cannot be prepared with SASLprep
+    SaslPrep(saslprep::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::ControlCharacter => write!(f, "CR, LF or NUL in an argument"),
+            Error::Space => write!(f, "space in a single word argument"),
+            #[cfg(feature = "legacy")]
+            Error::Termination => write!(f, "termination line in the message"),
+            Error::SaslPrep(x) => write!(f, "SASLprep: {}", x),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::SaslPrep(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl From<saslprep::Error> for Error {
+    fn from(error: saslprep::Error) -> Self {
+        Error::SaslPrep(error)
+    }
+}
+
+/// Commands which can be sent to a POP3 server
+pub trait Encode {
+    /// Appends the command line, including the CRLF pair, to `buf`.
+    /// Nothing is appended if an argument could be read as more than
+    /// one, or as the end of the line.
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error>;
+
+    fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![];
+        self.encode(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+fn encode_base64(data: &[u8]) -> Vec<u8> {
+    if data.is_empty() {
+        b"=".to_vec()
+    } else {
+        STANDARD.encode(data).into_bytes()
+    }
+}
+
+/// Checks that `argument` stays within the command line, and is a
+/// single word unless `spaces`.
+fn check_argument(argument: &[u8], spaces: bool) -> Result<(), Error> {
+    match argument
+        .iter()
+        .find(|&&x| matches!(x, b'\r' | b'\n' | b'\0') || (x == b' ' && !spaces))
+    {
+        Some(b' ') => Err(Error::Space),
+        Some(_) => Err(Error::ControlCharacter),
+        None => Ok(()),
+    }
+}
+
+fn encode_line(buf: &mut Vec<u8>, keyword: &[u8], arguments: &[&[u8]]) -> Result<(), Error> {
+    check_argument(keyword, false)?;
+    for argument in arguments {
+        check_argument(argument, false)?;
+    }
+    buf.extend_from_slice(keyword);
+    for argument in arguments {
+        buf.push(b' ');
+        buf.extend_from_slice(argument);
+    }
+    buf.extend_from_slice(b"\r\n");
+    Ok(())
+}
+
+/// A PASS command line; the password may contain spaces (RFC 1939).
+fn encode_pass(buf: &mut Vec<u8>, string: &[u8]) -> Result<(), Error> {
+    check_argument(string, true)?;
+    buf.extend_from_slice(b"PASS ");
+    buf.extend_from_slice(string);
+    buf.extend_from_slice(b"\r\n");
+    Ok(())
+}
+
+impl Encode for Stat {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"STAT", &[])
+    }
+}
+
+impl Encode for List {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        match self.msg {
+            Some(msg) => encode_line(buf, b"LIST", &[msg.to_string().as_bytes()]),
+            None => encode_line(buf, b"LIST", &[]),
+        }
+    }
+}
+
+impl Encode for Retr {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"RETR", &[self.msg.to_string().as_bytes()])
+    }
+}
+
+impl Encode for Dele {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"DELE", &[self.msg.to_string().as_bytes()])
+    }
+}
+
+impl Encode for Noop {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"NOOP", &[])
+    }
+}
+
+impl Encode for Rset {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"RSET", &[])
+    }
+}
+
+impl Encode for Quit {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"QUIT", &[])
+    }
+}
+
+impl Encode for Top {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(
+            buf,
+            b"TOP",
+            &[
+                self.msg.to_string().as_bytes(),
+                self.n.to_string().as_bytes(),
+            ],
+        )
+    }
+}
+
+impl Encode for Uidl {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        match self.msg {
+            Some(msg) => encode_line(buf, b"UIDL", &[msg.to_string().as_bytes()]),
+            None => encode_line(buf, b"UIDL", &[]),
+        }
+    }
+}
+
+impl Encode for User<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"USER", &[self.name])
+    }
+}
+
+impl Encode for Pass<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_pass(buf, self.string)
+    }
+}
+
+impl User<'_> {
+    /// Appends the command line with the name prepared with SASLprep.
+    /// [`Encode`] sends the name as it is.
+    pub fn encode_prepared(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"USER", &[saslprep(self.name)?.as_bytes()])
+    }
+}
+
+impl Pass<'_> {
+    /// Appends the command line with the password prepared with
+    /// SASLprep. [`Encode`] sends the password as it is.
+    pub fn encode_prepared(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_pass(buf, saslprep(self.string)?.as_bytes())
+    }
+}
+
+impl Encode for Apop<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"APOP", &[self.name, self.digest])
+    }
+}
+
+impl Encode for Capa {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"CAPA", &[])
+    }
+}
+
+impl Encode for Stls {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"STLS", &[])
+    }
+}
+
+impl Encode for Utf8 {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"UTF8", &[])
+    }
+}
+
+impl Encode for Lang<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        match self.tag {
+            Some(x) => encode_line(buf, b"LANG", &[x]),
+            None => encode_line(buf, b"LANG", &[]),
+        }
+    }
+}
+
+impl Encode for Auth<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        match &self.initial_response {
+            Some(x) => encode_line(buf, b"AUTH", &[self.mechanism, &encode_base64(x)]),
+            None => encode_line(buf, b"AUTH", &[self.mechanism]),
+        }
+    }
+}
+
+impl Encode for AuthResponse {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        match self {
+            AuthResponse::Data(x) if x.is_empty() => {
+                buf.extend_from_slice(b"\r\n");
+                Ok(())
+            }
+            AuthResponse::Data(x) => encode_line(buf, &STANDARD.encode(x).into_bytes(), &[]),
+            AuthResponse::Cancel => encode_line(buf, b"*", &[]),
+        }
+    }
+}
+
+impl Encode for Unknown<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, self.keyword, &self.args)
+    }
+}
+
+impl Encode for Custom<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, self.keyword, &self.args)
+    }
+}
+
+#[cfg(feature = "legacy")]
+impl Encode for Last {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"LAST", &[])
+    }
+}
+
+#[cfg(feature = "legacy")]
+impl Encode for Rpop<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        encode_line(buf, b"RPOP", &[self.user])
+    }
+}
+
+#[cfg(feature = "legacy")]
+impl Encode for Xtnd<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        match self {
+            Xtnd::Xmit => encode_line(buf, b"XTND", &[b"XMIT"]),
+            Xtnd::Xlst { header, msg: None } => encode_line(buf, b"XTND", &[b"XLST", header]),
+            Xtnd::Xlst {
+                header,
+                msg: Some(msg),
+            } => encode_line(buf, b"XTND", &[b"XLST", header, msg.to_string().as_bytes()]),
+        }
+    }
+}
+
+/// Appends the message and the termination line; the message must
+/// already be byte-stuffed.
+#[cfg(feature = "legacy")]
+impl Encode for XmitMessage<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        if self
+            .message
+            .split(|&x| x == b'\n')
+            .any(|x| x == b"." || x == b".\r")
+        {
+            return Err(Error::Termination);
+        }
+        if !self.message.is_empty() {
+            buf.extend_from_slice(self.message);
+            buf.extend_from_slice(b"\r\n");
+        }
+        buf.extend_from_slice(b".\r\n");
+        Ok(())
+    }
+}
+
+impl Encode for Command<'_> {
+    fn encode(&self, buf: &mut Vec<u8>) -> Result<(), Error> {
+        match self {
+            Command::User(x) => x.encode(buf),
+            Command::Pass(x) => x.encode(buf),
+            Command::Apop(x) => x.encode(buf),
+            Command::Stat(x) => x.encode(buf),
+            Command::List(x) => x.encode(buf),
+            Command::Retr(x) => x.encode(buf),
+            Command::Dele(x) => x.encode(buf),
+            Command::Noop(x) => x.encode(buf),
+            Command::Rset(x) => x.encode(buf),
+            Command::Quit(x) => x.encode(buf),
+            Command::Top(x) => x.encode(buf),
+            Command::Uidl(x) => x.encode(buf),
+            Command::Capa(x) => x.encode(buf),
+            Command::Stls(x) => x.encode(buf),
+            Command::Utf8(x) => x.encode(buf),
+            Command::Lang(x) => x.encode(buf),
+            Command::Auth(x) => x.encode(buf),
+            Command::AuthResponse(x) => x.encode(buf),
+            Command::Unknown(x) => x.encode(buf),
+            Command::Custom(x) => x.encode(buf),
+            #[cfg(feature = "legacy")]
+            Command::Last(x) => x.encode(buf),
+            #[cfg(feature = "legacy")]
+            Command::Rpop(x) => x.encode(buf),
+            #[cfg(feature = "legacy")]
+            Command::Xtnd(x) => x.encode(buf),
+            #[cfg(feature = "legacy")]
+            Command::XmitMessage(x) => x.encode(buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::command;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(Stat.to_bytes().unwrap(), b"STAT\r\n");
+        assert_eq!(List { msg: None }.to_bytes().unwrap(), b"LIST\r\n");
+        assert_eq!(Uidl { msg: Some(2) }.to_bytes().unwrap(), b"UIDL 2\r\n");
+        assert_eq!(Top { msg: 1, n: 10 }.to_bytes().unwrap(), b"TOP 1 10\r\n");
+        assert_eq!(
+            Pass {
+                string: b"my secret"
+            }
+            .to_bytes()
+            .unwrap(),
+            b"PASS my secret\r\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_prepared() {
+        let mut buf = vec![];
+        User {
+            name: "m\u{ad}rose".as_bytes(),
+        }
+        .encode_prepared(&mut buf)
+        .unwrap();
+        Pass {
+            string: "tan\u{2003}staaf".as_bytes(),
+        }
+        .encode_prepared(&mut buf)
+        .unwrap();
+        assert_eq!(buf, b"USER mrose\r\nPASS tan staaf\r\n");
+        assert_eq!(
+            Pass {
+                string: b"tan\x7fstaaf"
+            }
+            .encode_prepared(&mut buf),
+            Err(Error::SaslPrep(saslprep::Error::Prohibited {
+                character: '\u{7f}',
+                position: 3,
+                table: saslprep::Table::AsciiControl
+            }))
+        );
+        assert_eq!(
+            User {
+                name: "m\u{2003}rose".as_bytes()
+            }
+            .encode_prepared(&mut buf),
+            Err(Error::Space)
+        );
+        assert_eq!(buf, b"USER mrose\r\nPASS tan staaf\r\n");
+    }
+
+    #[test]
+    fn test_encode_injection() {
+        let mut buf = vec![];
+        assert_eq!(
+            User {
+                name: b"mrose\r\nDELE 1"
+            }
+            .encode(&mut buf),
+            Err(Error::ControlCharacter)
+        );
+        assert_eq!(
+            Pass {
+                string: b"tanstaaf\nDELE 1"
+            }
+            .encode(&mut buf),
+            Err(Error::ControlCharacter)
+        );
+        assert_eq!(
+            Pass {
+                string: b"tanstaaf\0"
+            }
+            .encode(&mut buf),
+            Err(Error::ControlCharacter)
+        );
+        assert_eq!(
+            Apop {
+                name: b"mrose c4c9334bac560ecc979e58001b3e22fb",
+                digest: b"c4c9334bac560ecc979e58001b3e22fb"
+            }
+            .encode(&mut buf),
+            Err(Error::Space)
+        );
+        assert_eq!(
+            Unknown {
+                keyword: b"XSENDER",
+                args: vec![b"1\rQUIT"]
+            }
+            .encode(&mut buf),
+            Err(Error::ControlCharacter)
+        );
+        assert_eq!(buf, b"");
+        #[cfg(feature = "legacy")]
+        {
+            let message = |message| XmitMessage { message }.to_bytes();
+            assert_eq!(
+                message(b"a\r\n..\r\nb"),
+                Ok(b"a\r\n..\r\nb\r\n.\r\n".to_vec())
+            );
+            assert_eq!(message(b"a\r\n.\r\nDELE 1"), Err(Error::Termination));
+            assert_eq!(message(b"a\r\n."), Err(Error::Termination));
+        }
+    }
+
+    #[test]
+    fn test_encode_auth() {
+        assert_eq!(
+            Auth {
+                mechanism: b"PLAIN",
+                initial_response: Some(b"\0tim\0tanstaaftanstaaf".to_vec())
+            }
+            .to_bytes()
+            .unwrap(),
+            b"AUTH PLAIN AHRpbQB0YW5zdGFhZnRhbnN0YWFm\r\n"
+        );
+        assert_eq!(
+            Auth {
+                mechanism: b"EXTERNAL",
+                initial_response: Some(vec![])
+            }
+            .to_bytes()
+            .unwrap(),
+            b"AUTH EXTERNAL =\r\n"
+        );
+        assert_eq!(
+            AuthResponse::Data(b"tim".to_vec()).to_bytes().unwrap(),
+            b"dGlt\r\n"
+        );
+        assert_eq!(AuthResponse::Data(vec![]).to_bytes().unwrap(), b"\r\n");
+        assert_eq!(AuthResponse::Cancel.to_bytes().unwrap(), b"*\r\n");
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let commands = [
+            Command::User(User { name: b"mrose" }),
+            Command::Apop(Apop {
+                name: b"mrose",
+                digest: b"c4c9334bac560ecc979e58001b3e22fb",
+            }),
+            Command::Retr(Retr { msg: 1 }),
+            Command::Dele(Dele { msg: 2 }),
+            Command::Noop(Noop),
+            Command::Rset(Rset),
+            Command::Quit(Quit),
+            Command::Capa(Capa),
+            Command::Stls(Stls),
+            Command::Utf8(Utf8),
+            Command::Lang(Lang { tag: None }),
+            Command::Lang(Lang { tag: Some(b"fr") }),
+            Command::Unknown(Unknown {
+                keyword: b"XSENDER",
+                args: vec![b"1"],
+            }),
+            #[cfg(feature = "legacy")]
+            Command::Last(Last),
+            #[cfg(feature = "legacy")]
+            Command::Rpop(Rpop { user: b"mrose" }),
+            #[cfg(feature = "legacy")]
+            Command::Xtnd(Xtnd::Xmit),
+            #[cfg(feature = "legacy")]
+            Command::Xtnd(Xtnd::Xlst {
+                header: b"Subject",
+                msg: Some(3),
+            }),
+        ];
+        for x in commands {
+            assert_eq!(command(&x.to_bytes().unwrap()).unwrap(), x);
+        }
+    }
+}
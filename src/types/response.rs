@@ -1,4 +1,4 @@
-use crate::common::StatusIndicator;
+use crate::common::{response_code_parser, StatusIndicator};
 
 /// Greeting
 /// 
@@ -11,6 +11,17 @@ pub struct Greeting<'a> {
     pub information: &'a [u8],
 }
 
+impl<'a> Greeting<'a> {
+    /// The timestamp a server supporting APOP puts in its greeting,
+    /// including the angle brackets, e.g. `<1896.697170952@dbc.mtview.ca.us>`
+    pub fn timestamp(&self) -> Option<&'a [u8]> {
+        let start = self.information.iter().position(|x| *x == b'<')?;
+        let end = start + self.information[start..].iter().position(|x| *x == b'>')?;
+        let timestamp = &self.information[start..=end];
+        timestamp.contains(&b'@').then_some(timestamp)
+    }
+}
+
 /// Quit
 /// 
 /// StatusIndicator, Status Indicator stand for +OK/-ERR
@@ -61,6 +72,64 @@ pub trait OneLine<'a>: Default {
 
     fn information(&self) -> &[u8];
     fn set_information(&mut self, information: &'a [u8]);
+
+    /// The extended response code in front of the information, if any
+    fn response_code(&self) -> Option<ResponseCode<'_>> {
+        match response_code_parser(self.information()) {
+            Ok((_, code)) => Some(code),
+            Err(_) => None,
+        }
+    }
+
+    /// The information without the extended response code
+    fn text(&self) -> &[u8] {
+        match response_code_parser(self.information()) {
+            Ok((text, _)) => text,
+            Err(_) => self.information(),
+        }
+    }
+}
+
+/// The sub-levels of the SYS response code (RFC 3206)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SysCode {
+    /// SYS/TEMP, a transient system error; retrying later may succeed
+    Temp,
+    /// SYS/PERM, a permanent system error
+    Perm,
+    /// SYS without a sub-level, or with one that is not known
+    Unspecified,
+}
+
+/// An extended response code (RFC 2449)
+///
+/// Response codes are hierarchical, with levels separated by "/".
+/// Following RFC 2449, a sub-level which is not known is treated as its
+/// parent, e.g. `[IN-USE/X-LOCKED]` is `ResponseCode::InUse`. Codes
+/// which are not known at all are kept with every level.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ResponseCode<'a> {
+    /// LOGIN-DELAY, the user logged in too recently
+    LoginDelay,
+    /// IN-USE, the maildrop is locked by another session
+    InUse,
+    /// SYS, a failure of the system rather than of the user (RFC 3206)
+    Sys(SysCode),
+    /// AUTH, the credentials were rejected (RFC 3206)
+    Auth,
+    /// UTF8, the mailbox requires UTF8 mode (RFC 6856)
+    Utf8,
+    Unknown(Vec<&'a [u8]>),
+}
+
+impl ResponseCode<'_> {
+    /// Whether the same command may succeed if the client retries later
+    pub fn is_temporary(&self) -> bool {
+        matches!(
+            self,
+            ResponseCode::LoginDelay | ResponseCode::InUse | ResponseCode::Sys(SysCode::Temp)
+        )
+    }
 }
 
 impl<'a> OneLine<'a> for Greeting<'a> {
@@ -73,7 +142,7 @@ impl<'a> OneLine<'a> for Greeting<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.information
+        self.information
     }
 
     fn set_information(&mut self, information: &'a [u8]) {
@@ -91,7 +160,7 @@ impl<'a> OneLine<'a> for Quit<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.information
+        self.information
     }
 
     fn set_information(&mut self, message: &'a [u8]) {
@@ -109,7 +178,7 @@ impl<'a> OneLine<'a> for OneLineTwoParts<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.right
+        self.right
     }
 
     fn set_information(&mut self, information: &'a [u8]) {
@@ -285,7 +354,7 @@ impl<'a> OneLine<'a> for Dele<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.information
+        self.information
     }
 
     fn set_information(&mut self, information: &'a [u8]) {
@@ -323,7 +392,7 @@ impl<'a> OneLine<'a> for Noop<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.information
+        self.information
     }
 
     fn set_information(&mut self, information: &'a [u8]) {
@@ -361,7 +430,7 @@ impl<'a> OneLine<'a> for Rset<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.information
+        self.information
     }
 
     fn set_information(&mut self, information: &'a [u8]) {
@@ -399,7 +468,7 @@ impl<'a> OneLine<'a> for User<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.information
+        self.information
     }
 
     fn set_information(&mut self, information: &'a [u8]) {
@@ -437,7 +506,7 @@ impl<'a> OneLine<'a> for Pass<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.information
+        self.information
     }
 
     fn set_information(&mut self, information: &'a [u8]) {
@@ -475,10 +544,461 @@ impl<'a> OneLine<'a> for Apop<'a> {
     }
 
     fn information(&self) -> &[u8] {
-        &self.information
+        self.information
+    }
+
+    fn set_information(&mut self, information: &'a [u8]) {
+        self.information = information;
+    }
+}
+
+/// STLS
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// &[u8], message
+#[derive(Debug, PartialEq)]
+pub struct Stls<'a> {
+    pub status_indicator: StatusIndicator,
+    pub information: &'a [u8],
+}
+
+impl Default for Stls<'_> {
+    fn default() -> Self {
+        Stls {
+            status_indicator: StatusIndicator::OK,
+            information: &[],
+        }
+    }
+}
+
+impl<'a> OneLine<'a> for Stls<'a> {
+    fn status_indicator(&self) -> &StatusIndicator {
+        &self.status_indicator
+    }
+
+    fn set_status_indicator(&mut self, si: StatusIndicator) {
+        self.status_indicator = si;
+    }
+
+    fn information(&self) -> &[u8] {
+        self.information
+    }
+
+    fn set_information(&mut self, information: &'a [u8]) {
+        self.information = information;
+    }
+}
+
+/// AUTH
+///
+/// The line which ends an AUTH exchange
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// &[u8], message
+#[derive(Debug, PartialEq)]
+pub struct Auth<'a> {
+    pub status_indicator: StatusIndicator,
+    pub information: &'a [u8],
+}
+
+impl Default for Auth<'_> {
+    fn default() -> Self {
+        Auth {
+            status_indicator: StatusIndicator::OK,
+            information: &[],
+        }
+    }
+}
+
+impl<'a> OneLine<'a> for Auth<'a> {
+    fn status_indicator(&self) -> &StatusIndicator {
+        &self.status_indicator
+    }
+
+    fn set_status_indicator(&mut self, si: StatusIndicator) {
+        self.status_indicator = si;
+    }
+
+    fn information(&self) -> &[u8] {
+        self.information
+    }
+
+    fn set_information(&mut self, information: &'a [u8]) {
+        self.information = information;
+    }
+}
+
+/// A continuation line ("+") sent by the server during an AUTH exchange
+///
+/// Vec<u8>, the challenge, decoded from base64
+#[derive(Debug, PartialEq)]
+pub struct AuthChallenge {
+    pub challenge: Vec<u8>,
+}
+
+/// The EXPIRE policy of a server
+#[derive(Debug, PartialEq)]
+pub enum Expire {
+    /// Messages may be deleted by the server after the number of days
+    Days(usize),
+    /// Messages are never deleted by the server
+    Never,
+}
+
+/// A capability announced in the response to CAPA (RFC 2449)
+#[derive(Debug, PartialEq)]
+pub enum Capability<'a> {
+    Top,
+    User,
+    /// SASL mechanisms accepted by the AUTH command
+    Sasl(Vec<&'a [u8]>),
+    RespCodes,
+    /// Minimum seconds between logins, possibly varying per user
+    LoginDelay {
+        delay: usize,
+        per_user: bool,
+    },
+    Pipelining,
+    /// Retention policy for retrieved messages, possibly varying per user
+    Expire {
+        expire: Expire,
+        per_user: bool,
+    },
+    Uidl,
+    /// Server implementation, for informational purposes only
+    Implementation(&'a [u8]),
+    Stls,
+    /// UTF8 mode, `user` is set if USER and PASS accept UTF-8 too
+    Utf8 {
+        user: bool,
+    },
+    Lang,
+    /// Any other capability, with its name and parameters
+    Unknown {
+        name: &'a [u8],
+        parameters: Vec<&'a [u8]>,
+    },
+}
+
+/// CAPA
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// Vec<Capability>, capabilities in the order listed
+///
+/// &[u8], message
+#[derive(Debug, PartialEq)]
+pub struct Capa<'a> {
+    pub status_indicator: StatusIndicator,
+    pub capabilities: Vec<Capability<'a>>,
+    pub information: &'a [u8],
+}
+
+/// UTF8
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// &[u8], message
+#[derive(Debug, PartialEq)]
+pub struct Utf8<'a> {
+    pub status_indicator: StatusIndicator,
+    pub information: &'a [u8],
+}
+
+impl Default for Utf8<'_> {
+    fn default() -> Self {
+        Utf8 {
+            status_indicator: StatusIndicator::OK,
+            information: &[],
+        }
+    }
+}
+
+impl<'a> OneLine<'a> for Utf8<'a> {
+    fn status_indicator(&self) -> &StatusIndicator {
+        &self.status_indicator
+    }
+
+    fn set_status_indicator(&mut self, si: StatusIndicator) {
+        self.status_indicator = si;
+    }
+
+    fn information(&self) -> &[u8] {
+        self.information
     }
 
     fn set_information(&mut self, information: &'a [u8]) {
         self.information = information;
     }
 }
+
+/// Language
+///
+/// &[u8], language tag, e.g. "en" or "fr-CA"
+///
+/// &[u8], description of the language, in UTF-8
+#[derive(Debug, PartialEq)]
+pub struct Language<'a> {
+    pub tag: &'a [u8],
+    pub description: &'a [u8],
+}
+
+/// LANG
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// Vec<Language>, languages the server supports, empty unless they were
+/// listed
+///
+/// &[u8], message
+#[derive(Debug, PartialEq)]
+pub struct Lang<'a> {
+    pub status_indicator: StatusIndicator,
+    pub languages: Vec<Language<'a>>,
+    pub information: &'a [u8],
+}
+
+/// The response to a command this crate does not know
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// Option<&[u8]>, the lines following the status line of a multi-line
+/// response, byte-stuffed and without the termination line; None for a
+/// one line response
+///
+/// &[u8], message
+#[derive(Debug, PartialEq)]
+pub struct Unknown<'a> {
+    pub status_indicator: StatusIndicator,
+    pub body: Option<&'a [u8]>,
+    pub information: &'a [u8],
+}
+
+/// The shape of the response a command expects
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Shape {
+    /// A single line
+    OneLine,
+    /// A status line, followed by lines up to a termination line if
+    /// the status is positive
+    MultiLine,
+}
+
+/// The response to a command registered with a
+/// [`Registry`](crate::session::registry::Registry)
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// Option<&[u8]>, the lines following the status line of a multi-line
+/// response, byte-stuffed and without the termination line; None for a
+/// one line response
+///
+/// &[u8], message
+#[derive(Debug, PartialEq)]
+pub struct Custom<'a> {
+    pub status_indicator: StatusIndicator,
+    pub body: Option<&'a [u8]>,
+    pub information: &'a [u8],
+}
+
+/// LAST
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// usize, the highest message-number accessed, 0 if none
+///
+/// &[u8], message
+#[cfg(feature = "legacy")]
+#[derive(Debug, PartialEq)]
+pub struct Last<'a> {
+    pub status_indicator: StatusIndicator,
+    pub msg: usize,
+    pub information: &'a [u8],
+}
+
+/// RPOP
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// &[u8], message
+#[cfg(feature = "legacy")]
+#[derive(Debug, PartialEq)]
+pub struct Rpop<'a> {
+    pub status_indicator: StatusIndicator,
+    pub information: &'a [u8],
+}
+
+#[cfg(feature = "legacy")]
+impl Default for Rpop<'_> {
+    fn default() -> Self {
+        Rpop {
+            status_indicator: StatusIndicator::OK,
+            information: &[],
+        }
+    }
+}
+
+#[cfg(feature = "legacy")]
+impl<'a> OneLine<'a> for Rpop<'a> {
+    fn status_indicator(&self) -> &StatusIndicator {
+        &self.status_indicator
+    }
+
+    fn set_status_indicator(&mut self, si: StatusIndicator) {
+        self.status_indicator = si;
+    }
+
+    fn information(&self) -> &[u8] {
+        self.information
+    }
+
+    fn set_information(&mut self, information: &'a [u8]) {
+        self.information = information;
+    }
+}
+
+/// XTND
+///
+/// StatusIndicator, Status Indicator stand for +OK/-ERR
+///
+/// Vec<(usize, &[u8])>, for XTND XLST, a vector containing tuple in which
+///     left usize is message-number and
+///     right &[u8] is the header line
+///
+/// &[u8], message
+#[cfg(feature = "legacy")]
+#[derive(Debug, PartialEq)]
+pub struct Xtnd<'a> {
+    pub status_indicator: StatusIndicator,
+    pub headers: Vec<(usize, &'a [u8])>,
+    pub information: &'a [u8],
+}
+
+/// Any response a POP3 server may send to a command; the `legacy`
+/// feature adds variants
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Response<'a> {
+    User(User<'a>),
+    Pass(Pass<'a>),
+    Apop(Apop<'a>),
+    Stat(Stat<'a>),
+    List(List<'a>),
+    Retr(Retr<'a>),
+    Dele(Dele<'a>),
+    Noop(Noop<'a>),
+    Rset(Rset<'a>),
+    Quit(Quit<'a>),
+    Top(Top<'a>),
+    Uidl(Uidl<'a>),
+    Capa(Capa<'a>),
+    Stls(Stls<'a>),
+    Utf8(Utf8<'a>),
+    Lang(Lang<'a>),
+    Auth(Auth<'a>),
+    AuthChallenge(AuthChallenge),
+    /// The response to an unknown command, or a response which the
+    /// parser of its command could not parse
+    Unknown(Unknown<'a>),
+    Custom(Custom<'a>),
+    #[cfg(feature = "legacy")]
+    Last(Last<'a>),
+    #[cfg(feature = "legacy")]
+    Rpop(Rpop<'a>),
+    /// The response to XTND or to the message sent after XTND XMIT
+    #[cfg(feature = "legacy")]
+    Xtnd(Xtnd<'a>),
+}
+
+impl Response<'_> {
+    /// The status indicator of the first line of the response, None for
+    /// a continuation line of an AUTH exchange
+    pub fn status_indicator(&self) -> Option<&StatusIndicator> {
+        Some(match self {
+            Response::User(x) => &x.status_indicator,
+            Response::Pass(x) => &x.status_indicator,
+            Response::Apop(x) => &x.status_indicator,
+            Response::Stat(x) => &x.status_indicator,
+            Response::List(x) => &x.status_indicator,
+            Response::Retr(x) => &x.status_indicator,
+            Response::Dele(x) => &x.status_indicator,
+            Response::Noop(x) => &x.status_indicator,
+            Response::Rset(x) => &x.status_indicator,
+            Response::Quit(x) => &x.status_indicator,
+            Response::Top(x) => &x.status_indicator,
+            Response::Uidl(x) => &x.status_indicator,
+            Response::Capa(x) => &x.status_indicator,
+            Response::Stls(x) => &x.status_indicator,
+            Response::Utf8(x) => &x.status_indicator,
+            Response::Lang(x) => &x.status_indicator,
+            Response::Auth(x) => &x.status_indicator,
+            Response::AuthChallenge(_) => return None,
+            Response::Unknown(x) => &x.status_indicator,
+            Response::Custom(x) => &x.status_indicator,
+            #[cfg(feature = "legacy")]
+            Response::Last(x) => &x.status_indicator,
+            #[cfg(feature = "legacy")]
+            Response::Rpop(x) => &x.status_indicator,
+            #[cfg(feature = "legacy")]
+            Response::Xtnd(x) => &x.status_indicator,
+        })
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status_indicator() == Some(&StatusIndicator::OK)
+    }
+
+    pub fn is_err(&self) -> bool {
+        self.status_indicator() == Some(&StatusIndicator::ERR)
+    }
+
+    /// The text following the status indicator in the first line
+    pub fn information(&self) -> &[u8] {
+        match self {
+            Response::User(x) => x.information,
+            Response::Pass(x) => x.information,
+            Response::Apop(x) => x.information,
+            Response::Stat(x) => x.information,
+            Response::List(x) => x.information,
+            Response::Retr(x) => x.information,
+            Response::Dele(x) => x.information,
+            Response::Noop(x) => x.information,
+            Response::Rset(x) => x.information,
+            Response::Quit(x) => x.information,
+            Response::Top(x) => x.information,
+            Response::Uidl(x) => x.information,
+            Response::Capa(x) => x.information,
+            Response::Stls(x) => x.information,
+            Response::Utf8(x) => x.information,
+            Response::Lang(x) => x.information,
+            Response::Auth(x) => x.information,
+            Response::AuthChallenge(_) => &[],
+            Response::Unknown(x) => x.information,
+            Response::Custom(x) => x.information,
+            #[cfg(feature = "legacy")]
+            Response::Last(x) => x.information,
+            #[cfg(feature = "legacy")]
+            Response::Rpop(x) => x.information,
+            #[cfg(feature = "legacy")]
+            Response::Xtnd(x) => x.information,
+        }
+    }
+
+    /// The extended response code in front of the information, if any
+    pub fn response_code(&self) -> Option<ResponseCode<'_>> {
+        match response_code_parser(self.information()) {
+            Ok((_, code)) => Some(code),
+            Err(_) => None,
+        }
+    }
+
+    /// The information without the extended response code
+    pub fn text(&self) -> &[u8] {
+        match response_code_parser(self.information()) {
+            Ok((text, _)) => text,
+            Err(_) => self.information(),
+        }
+    }
+}
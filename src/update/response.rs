@@ -31,14 +31,14 @@ use nom::IResult;
 /// 
 /// &nbsp;&nbsp;&nbsp;&nbsp;S: +OK dewey POP3 server signing off (maildrop empty)
 // ################################################################################
-pub fn quit(s: &[u8]) -> Option<Quit> {
+pub fn quit(s: &[u8]) -> Option<Quit<'_>> {
     match quit_parser(s) {
         Ok((_, x)) => Some(x),
         Err(_) => None,
     }
 }
 
-pub(crate) fn quit_parser(s: &[u8]) -> IResult<&[u8], Quit> {
+pub(crate) fn quit_parser(s: &[u8]) -> IResult<&[u8], Quit<'_>> {
     one_line_response_two_parts_parser::<Quit>(s)
 }
 